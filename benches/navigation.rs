@@ -0,0 +1,85 @@
+//! Times move navigation on long games: `cargo bench --bench navigation`.
+//!
+//! Jumping to any ply should cost the same regardless of where it is in the game, so the
+//! per-operation times below shouldn't grow with the game length.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use shakmaty::{Chess, Position};
+use shakmaty::san::SanPlus;
+
+use brilliant::board::ChessBoard;
+use brilliant::pgn::ChessGamePlayer;
+
+const PLIES: usize = 300;
+const ITERATIONS: u32 = 200;
+
+/// A game of `PLIES` pseudo-random legal moves. Games that end early are thrown away.
+fn long_game() -> String {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+
+    loop {
+        let mut position = Chess::default();
+        let mut moves = Vec::with_capacity(PLIES);
+
+        while moves.len() < PLIES {
+            let legal_moves = position.legal_moves();
+            if legal_moves.is_empty() {
+                break;
+            }
+
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let mv = &legal_moves[(seed >> 33) as usize % legal_moves.len()];
+            moves.push(SanPlus::from_move_and_play_unchecked(&mut position, mv).to_string());
+        }
+
+        if moves.len() == PLIES {
+            return format!("[Event \"Benchmark\"]\n\n{} *\n", moves.join(" "));
+        }
+    }
+}
+
+fn report(name: &str, elapsed: Duration, operations: u32) {
+    println!("{:<28} {:>10.2?} per op", name, elapsed / operations);
+}
+
+fn main() {
+    let pgn = long_game();
+    let mut player = ChessGamePlayer::new(ChessBoard::new(72.0));
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        player.load_pgn(black_box(&pgn), 0).unwrap();
+    }
+    report("load 300-ply game", start.elapsed(), ITERATIONS);
+    assert_eq!(player.get_total_moves(), PLIES);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        player.goto_ply(PLIES);
+    }
+    report("goto_ply(end)", start.elapsed(), ITERATIONS);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        player.goto_ply(PLIES);
+        while player.previous_move() {}
+    }
+    report("previous_move", start.elapsed(), ITERATIONS * PLIES as u32);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        player.reset();
+        while player.next_move() {}
+    }
+    report("next_move", start.elapsed(), ITERATIONS * PLIES as u32);
+
+    let mut ply = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS * 100 {
+        ply = (ply * 7 + 131) % (PLIES + 1);
+        player.goto_ply(black_box(ply));
+    }
+    report("goto_ply(scattered)", start.elapsed(), ITERATIONS * 100);
+}
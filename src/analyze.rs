@@ -0,0 +1,266 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::iter;
+
+use pgn_reader::Nag;
+use shakmaty::san::SanPlus;
+use shakmaty::{Chess, Color};
+
+use crate::board::ChessBoard;
+use crate::cli::AnalyzeOptions;
+use crate::config::Config;
+use crate::database::PgnDatabase;
+use crate::engine::{spawn_engine, Evaluation, StockfishEngine};
+use crate::game_tree::{GameTree, NodeId, ROOT};
+use crate::pgn::{ChessGamePlayer, PgnError};
+use crate::pgn_writer::write_game;
+use crate::review::{analyse_position, Classification, GameReview, PositionAnalysis, ReviewSettings};
+use crate::uci::{Score, ScoreBound, ScoreValue};
+
+/// How many moves of the engine's line are written as the alternative to a bad move.
+const MAX_VARIATION_PLIES: usize = 8;
+
+/// Analyses every game of the input file and writes them to the output, one game at a time so
+/// an interrupted run keeps what it has finished. Games that can't be loaded or analysed are
+/// copied as they are; the run fails at the end if the engine couldn't analyse any of them.
+pub fn run(options: &AnalyzeOptions, config: &Config) -> Result<(), String> {
+    let pgn_path = options.pgn_path.as_deref().ok_or("no PGN file given")?;
+    let pgn = fs::read_to_string(pgn_path)
+        .map_err(|err| format!("could not read {}: {}", pgn_path.display(), err))?;
+    let database = PgnDatabase::index(pgn)
+        .map_err(|err| format!("could not load {}: {}", pgn_path.display(), err))?;
+
+    let engine = spawn_engine(options.engine_path.as_deref(), options.fake_engine_script.as_deref(), config, false)?;
+
+    let mut settings = ReviewSettings::from_config(config);
+    if options.depth.is_some() || options.nodes.is_some() {
+        settings = ReviewSettings { depth: options.depth, nodes: options.nodes, time_ms: None };
+    }
+
+    let output_path = options.output_path.clone()
+        .unwrap_or_else(|| pgn_path.with_extension("analyzed.pgn"));
+    let mut output = File::create(&output_path)
+        .map_err(|err| format!("could not create {}: {}", output_path.display(), err))?;
+
+    let mut failed = 0;
+
+    for (index, entry) in database.games().iter().enumerate() {
+        eprintln!("Analysing game {}/{}: {} - {}", index + 1, database.len(), entry.white(), entry.black());
+
+        let pgn = match annotate_game(&engine, &database, index, &settings) {
+            Ok(pgn) => pgn,
+            Err(err) => {
+                if let AnnotateError::Analysis(_) = err {
+                    failed += 1;
+                }
+                eprintln!("Copying game {} unannotated: {}", index + 1, err);
+                format!("{}\n", database.game_text(index).unwrap_or_default().trim())
+            }
+        };
+
+        writeln!(output, "{}", pgn)
+            .map_err(|err| format!("could not write {}: {}", output_path.display(), err))?;
+    }
+
+    eprintln!("Wrote {} game(s) to {}", database.len(), output_path.display());
+
+    if failed > 0 {
+        return Err(format!("the engine failed to analyse {} of {} game(s)", failed, database.len()));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum AnnotateError {
+    Load(PgnError),
+    /// The engine gave no evaluation of the position after this many plies.
+    Analysis(usize),
+}
+
+impl fmt::Display for AnnotateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotateError::Load(err) => write!(f, "{}", err),
+            AnnotateError::Analysis(ply) => write!(f, "the engine gave no evaluation at ply {}", ply),
+        }
+    }
+}
+
+/// Reviews the mainline of game `index` and returns it as PGN with the engine's evaluation of
+/// every position, NAGs on inaccuracies, mistakes and blunders, and the engine's line as a
+/// variation to each of them.
+fn annotate_game(
+    engine: &StockfishEngine,
+    database: &PgnDatabase,
+    index: usize,
+    settings: &ReviewSettings,
+) -> Result<String, AnnotateError> {
+    let mut game = ChessGamePlayer::new(ChessBoard::new(crate::GRID_SIZE));
+    database.load(index, &mut game).map_err(AnnotateError::Load)?;
+
+    let mainline: Vec<NodeId> = iter::once(ROOT)
+        .chain(game.tree().mainline_from(ROOT))
+        .collect();
+
+    let mut review = GameReview::default();
+    for (ply, &node) in mainline.iter().enumerate() {
+        let analysis = analyse_position(engine, game.position_at(node), settings)
+            .ok_or(AnnotateError::Analysis(ply))?;
+        review.insert(node, analysis, &game);
+    }
+
+    let mut tree = game.tree().clone();
+    for &node in &mainline[1..] {
+        annotate_move(&mut tree, &game, &review, node);
+    }
+
+    Ok(write_game(game.get_headers(), &tree, game.start_position()))
+}
+
+fn annotate_move(tree: &mut GameTree, game: &ChessGamePlayer, review: &GameReview, node: NodeId) {
+    let Some(analysis) = review.analysis(node) else {
+        return;
+    };
+    let mut annotation = eval_command(&analysis.evaluation).unwrap_or_default();
+
+    let parent = tree.node(node).parent.unwrap_or(ROOT);
+    let nag = match review.classification(node) {
+        Some(Classification::Inaccuracy | Classification::Miss) => Some(Nag::DUBIOUS_MOVE),
+        Some(Classification::Mistake) => Some(Nag::MISTAKE),
+        Some(Classification::Blunder) => Some(Nag::BLUNDER),
+        _ => None,
+    };
+
+    if let (Some(nag), Some(before)) = (nag, review.analysis(parent)) {
+        let game_node = tree.node_mut(node);
+        // Leave a move alone if whoever annotated the game already judged it.
+        if !game_node.nags.iter().any(|nag| (1..=6).contains(&nag.0)) {
+            game_node.nags.push(nag);
+        }
+
+        if let Some(best) = add_engine_line(tree, game.position_at(parent), parent, before) {
+            let classification = review.classification(node).map_or("", Classification::label);
+            annotation = format!("{} {}. {} was best.", annotation, classification, best).trim().to_string();
+        }
+    }
+
+    // The evaluation goes at the front of the first comment, next to any `[%clk]` there.
+    let comments = &mut tree.node_mut(node).comments;
+    let existing = if comments.is_empty() { String::new() } else { strip_eval_command(&comments.remove(0)) };
+    let comment = format!("{} {}", annotation, existing).trim().to_string();
+    if !comment.is_empty() {
+        comments.insert(0, comment);
+    }
+}
+
+/// Adds the start of the engine's line from `position`, the position at `parent`, as a new
+/// variation. Returns the SAN of its first move, or `None` if there's no line or the move is
+/// already in the game.
+fn add_engine_line(tree: &mut GameTree, position: &Chess, parent: NodeId, analysis: &PositionAnalysis) -> Option<String> {
+    let pv = if analysis.pv.is_empty() { analysis.best_move.iter().cloned().collect() } else { analysis.pv.clone() };
+
+    let mut position = position.clone();
+    let mut moves = Vec::new();
+    for uci_move in pv.iter().take(MAX_VARIATION_PLIES) {
+        let Ok(mv) = uci_move.to_move(&position) else {
+            break;
+        };
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, &mv);
+        moves.push((mv, san.to_string()));
+    }
+
+    let (first_move, first_san) = moves.first()?.clone();
+    let already_played = tree.node(parent).children.iter()
+        .any(|&child| tree.node(child).mv.as_ref() == Some(&first_move));
+    if already_played {
+        return None;
+    }
+
+    let mut node = parent;
+    for (mv, san) in moves {
+        node = tree.add_child(node, mv, san);
+    }
+
+    Some(first_san)
+}
+
+/// The `[%eval]` command for an evaluation: pawns from White's point of view, or `#n` for a
+/// forced mate. There's none for a position that's already checkmate.
+fn eval_command(evaluation: &Evaluation) -> Option<String> {
+    match evaluation.white_mate() {
+        Some(0) => None,
+        Some(moves) => Some(format!("[%eval #{}]", moves)),
+        None => Some(format!("[%eval {:.2}]", evaluation.white_centipawns() as f32 / 100.0)),
+    }
+}
+
+/// The evaluation in a comment's `[%eval]` command, as `eval_command` writes it and Lichess and
+/// ChessBase export it.
+pub fn parse_eval_command(comment: &str) -> Option<Evaluation> {
+    let start = comment.find("[%eval")? + "[%eval".len();
+    let end = comment[start..].find(']')? + start;
+    let value = comment[start..end].split(',').next()?.trim();
+
+    let value = match value.strip_prefix('#') {
+        Some(moves) => ScoreValue::Mate(moves.parse().ok()?),
+        None => ScoreValue::Centipawns((value.parse::<f32>().ok()? * 100.0).round() as i32),
+    };
+
+    Some(Evaluation {
+        score: Score { value, bound: ScoreBound::Exact },
+        wdl: None,
+        turn: Color::White,
+    })
+}
+
+/// `comment` without any `[%eval ...]` command, which the new evaluation replaces.
+fn strip_eval_command(comment: &str) -> String {
+    let mut text = comment.to_string();
+
+    while let Some(start) = text.find("[%eval") {
+        let end = text[start..].find(']').map_or(text.len(), |end| start + end + 1);
+        text.replace_range(start..end, "");
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_engine::FakeEngineScript;
+
+    const GAME: &str = "[Event \"Test\"]\n\n1. e4 e5 2. Nf3 *\n";
+
+    fn annotate(script: &str) -> Result<String, AnnotateError> {
+        let engine = StockfishEngine::with_fake_engine(FakeEngineScript::parse(script).unwrap(), false).unwrap();
+        let database = PgnDatabase::index(GAME.to_string()).unwrap();
+        annotate_game(&engine, &database, 0, &ReviewSettings::from_config(&Config::default()))
+    }
+
+    #[test]
+    fn every_move_gets_an_evaluation() {
+        let pgn = annotate(
+            "on go
+             send info depth 1 score cp 25 pv a2a3
+             send bestmove a2a3",
+        ).unwrap();
+
+        assert_eq!(pgn, "[Event \"Test\"]\n\n1. e4 {[%eval -0.25]} 1... e5 {[%eval 0.25]} 2. Nf3 {[%eval -0.25]} *\n");
+    }
+
+    #[test]
+    fn engine_crash_fails_the_game() {
+        let result = annotate(
+            "on go
+             send info depth 1 score cp 25 pv a2a3
+             send bestmove a2a3
+             on go
+             send info depth 1 score cp 25 pv a7a6
+             crash",
+        );
+
+        assert!(matches!(result, Err(AnnotateError::Analysis(1))));
+    }
+}
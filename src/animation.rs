@@ -0,0 +1,273 @@
+use std::time::{Duration, Instant};
+
+use shakmaty::{Board, Move, Piece, Position, Square};
+
+use crate::config::Config;
+use crate::game_tree::NodeId;
+use crate::pgn::ChessGamePlayer;
+
+/// How long a move takes to play out on the board unless `animation_ms` in the config says
+/// otherwise. 0 turns animations off.
+const DEFAULT_DURATION: Duration = Duration::from_millis(180);
+
+/// A piece sliding from one square to another. A promoting pawn turns into `becomes` on the way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovingPiece {
+    pub from: Square,
+    pub to: Square,
+    pub piece: Piece,
+    pub becomes: Piece,
+}
+
+/// A captured piece being taken off the board, or put back when stepping back over the capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FadingPiece {
+    pub square: Square,
+    pub piece: Piece,
+    pub appearing: bool,
+}
+
+/// One move playing out on the board, forwards or backwards. The board underneath already
+/// shows the position after it, apart from the squares the animation covers.
+#[derive(Debug, Clone)]
+pub struct PieceAnimation {
+    started: Instant,
+    duration: Duration,
+    pub moving: Vec<MovingPiece>,
+    pub fading: Vec<FadingPiece>,
+}
+
+impl PieceAnimation {
+    /// `mv` played from `before`: the piece moving, the rook too when castling, and whatever
+    /// it captures.
+    fn new(mv: &Move, before: &Board, duration: Duration) -> Option<Self> {
+        let piece = before.piece_at(mv.from()?)?;
+        let slide = |from: Square, to: Square, piece: Piece| MovingPiece { from, to, piece, becomes: piece };
+
+        let mut moving = Vec::new();
+        let mut fading = Vec::new();
+
+        match *mv {
+            Move::Normal { from, to, capture, promotion, .. } => {
+                let becomes = Piece { color: piece.color, role: promotion.unwrap_or(piece.role) };
+                moving.push(MovingPiece { from, to, piece, becomes });
+                if capture.is_some() {
+                    fading.push(FadingPiece { square: to, piece: before.piece_at(to)?, appearing: false });
+                }
+            }
+            Move::EnPassant { from, to } => {
+                let captured = Square::from_coords(to.file(), from.rank());
+                moving.push(slide(from, to, piece));
+                fading.push(FadingPiece { square: captured, piece: before.piece_at(captured)?, appearing: false });
+            }
+            Move::Castle { king, rook } => {
+                let side = mv.castling_side()?;
+                moving.push(slide(king, side.king_to(piece.color), piece));
+                moving.push(slide(rook, side.rook_to(piece.color), before.piece_at(rook)?));
+            }
+            Move::Put { .. } => return None,
+        }
+
+        Some(PieceAnimation { started: Instant::now(), duration, moving, fading })
+    }
+
+    /// The same move taken back.
+    fn reversed(mut self) -> Self {
+        for moving in &mut self.moving {
+            *moving = MovingPiece { from: moving.to, to: moving.from, piece: moving.becomes, becomes: moving.piece };
+        }
+        for fading in &mut self.fading {
+            fading.appearing = !fading.appearing;
+        }
+        self
+    }
+
+    /// How far along the animation is, 0 to 1, slowing down towards the end.
+    pub fn progress(&self) -> f32 {
+        let linear = (self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        1.0 - (1.0 - linear).powi(3)
+    }
+
+    fn finished(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+
+    /// Whether the animation draws `square` itself, so the board should leave its piece out.
+    pub fn covers(&self, square: Square) -> bool {
+        self.moving.iter().any(|moving| moving.to == square)
+            || self.fading.iter().any(|fading| fading.appearing && fading.square == square)
+    }
+}
+
+/// Animates the board whenever the viewer steps one move forwards or back. Anything else, like
+/// jumping to the start or another game, shows the new position straight away. The position is
+/// watched rather than each way of moving, so every key, button and click animates alike.
+pub struct BoardAnimation {
+    duration: Duration,
+    shown: Option<(NodeId, Board)>,
+    current: Option<PieceAnimation>,
+}
+
+impl BoardAnimation {
+    pub fn from_config(config: &Config) -> Self {
+        let duration = config.get("animation_ms")
+            .and_then(|duration| duration.parse().ok())
+            .map_or(DEFAULT_DURATION, Duration::from_millis);
+
+        BoardAnimation { duration, shown: None, current: None }
+    }
+
+    /// Starts an animation if the current position is one move away from the last one shown,
+    /// and drops a finished one.
+    pub fn sync(&mut self, game: &ChessGamePlayer) {
+        let node = game.current_node();
+        let board = game.get_position().board();
+
+        if self.shown.as_ref().is_some_and(|(shown_node, shown_board)| *shown_node == node && shown_board == board) {
+            if self.current.as_ref().is_some_and(PieceAnimation::finished) {
+                self.current = None;
+            }
+            return;
+        }
+
+        let previous = self.shown.replace((node, board.clone()));
+        self.current = previous.and_then(|(previous_node, previous_board)| self.step(game, previous_node, &previous_board));
+    }
+
+    /// The animation from the position at `previous` to the current one, if they're a move apart.
+    fn step(&self, game: &ChessGamePlayer, previous: NodeId, previous_board: &Board) -> Option<PieceAnimation> {
+        if self.duration.is_zero() {
+            return None;
+        }
+
+        let tree = game.tree();
+        let node = game.current_node();
+
+        // The boards are compared too, since node ids start over when another game is opened.
+        if tree.node(node).parent == Some(previous) && game.position_at(previous).board() == previous_board {
+            let mv = tree.node(node).mv.as_ref()?;
+            PieceAnimation::new(mv, previous_board, self.duration)
+        } else if tree.node(node).children.contains(&previous) && game.position_at(previous).board() == previous_board {
+            let mv = tree.node(previous).mv.as_ref()?;
+            PieceAnimation::new(mv, game.get_position().board(), self.duration).map(PieceAnimation::reversed)
+        } else {
+            None
+        }
+    }
+
+    pub fn current(&self) -> Option<&PieceAnimation> {
+        self.current.as_ref()
+    }
+
+    /// Jumps to the end of the running animation.
+    pub fn finish(&mut self) {
+        self.current = None;
+    }
+
+    /// Shows the current position without animating, for a move already dragged into place.
+    pub fn skip(&mut self, game: &ChessGamePlayer) {
+        self.shown = Some((game.current_node(), game.get_position().board().clone()));
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::Color;
+
+    use super::*;
+    use crate::board::ChessBoard;
+
+    /// `pgn` loaded with the animation shown its last move being played.
+    fn play_last_move(pgn: &str) -> (ChessGamePlayer, BoardAnimation) {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn(pgn, 0).unwrap();
+        let mut animation = BoardAnimation::from_config(&Config::default());
+
+        game.goto_ply(game.get_total_moves() - 1);
+        animation.sync(&game);
+        assert!(animation.current().is_none());
+        assert!(game.next_move());
+        animation.sync(&game);
+
+        (game, animation)
+    }
+
+    fn slide(from: Square, to: Square, piece: Piece) -> MovingPiece {
+        MovingPiece { from, to, piece, becomes: piece }
+    }
+
+    #[test]
+    fn castling_moves_the_rook_too() {
+        let (mut game, mut animation) = play_last_move("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. O-O *");
+        let played = animation.current().unwrap();
+        assert_eq!(played.moving, [
+            slide(Square::E1, Square::G1, Color::White.king()),
+            slide(Square::H1, Square::F1, Color::White.rook()),
+        ]);
+        assert!(played.fading.is_empty());
+        assert!(played.covers(Square::G1) && played.covers(Square::F1));
+        assert!(!played.covers(Square::E1));
+
+        assert!(game.previous_move());
+        animation.sync(&game);
+        assert_eq!(animation.current().unwrap().moving, [
+            slide(Square::G1, Square::E1, Color::White.king()),
+            slide(Square::F1, Square::H1, Color::White.rook()),
+        ]);
+    }
+
+    #[test]
+    fn en_passant_removes_the_pawn_beside() {
+        let (_, animation) = play_last_move("[FEN \"4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1\"]\n\n1. exd6 *");
+        let played = animation.current().unwrap();
+        assert_eq!(played.moving, [slide(Square::E5, Square::D6, Color::White.pawn())]);
+        assert_eq!(played.fading, [FadingPiece { square: Square::D5, piece: Color::Black.pawn(), appearing: false }]);
+        assert!(played.covers(Square::D6));
+        assert!(!played.covers(Square::D5));
+    }
+
+    #[test]
+    fn promotion_changes_the_piece() {
+        let (mut game, mut animation) = play_last_move("[FEN \"1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. axb8=N *");
+        let played = animation.current().unwrap();
+        assert_eq!(played.moving, [MovingPiece {
+            from: Square::A7,
+            to: Square::B8,
+            piece: Color::White.pawn(),
+            becomes: Color::White.knight(),
+        }]);
+        assert_eq!(played.fading, [FadingPiece { square: Square::B8, piece: Color::Black.rook(), appearing: false }]);
+
+        // Stepping back turns the knight into a pawn again and puts the rook back.
+        assert!(game.previous_move());
+        animation.sync(&game);
+        let taken_back = animation.current().unwrap();
+        assert_eq!(taken_back.moving, [MovingPiece {
+            from: Square::B8,
+            to: Square::A7,
+            piece: Color::White.knight(),
+            becomes: Color::White.pawn(),
+        }]);
+        assert_eq!(taken_back.fading, [FadingPiece { square: Square::B8, piece: Color::Black.rook(), appearing: true }]);
+        assert!(taken_back.covers(Square::A7) && taken_back.covers(Square::B8));
+    }
+
+    #[test]
+    fn only_single_steps_animate() {
+        let (mut game, mut animation) = play_last_move("1. e4 e5 2. Nf3 *");
+        assert!(animation.current().is_some());
+
+        game.goto_ply(0);
+        animation.sync(&game);
+        assert!(animation.current().is_none());
+
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn("1. d4 *", 0).unwrap();
+        let mut animation = BoardAnimation::from_config(&Config::parse("animation_ms = 0"));
+        animation.sync(&game);
+        game.goto_ply(1);
+        animation.sync(&game);
+        assert!(animation.current().is_none());
+    }
+}
@@ -1,8 +1,8 @@
 use ggez::mint::Point2;
-use crate::graphics::{START_X, START_Y};
+use shakmaty::{Board, File, Rank, Role, Square};
 
 #[derive(PartialEq, Clone, Debug)]
-pub(crate) enum PieceType {
+pub enum PieceType {
     None,
     King,
     Queen,
@@ -12,8 +12,21 @@ pub(crate) enum PieceType {
     Pawn,
 }
 
+impl From<Role> for PieceType {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::King => PieceType::King,
+            Role::Queen => PieceType::Queen,
+            Role::Rook => PieceType::Rook,
+            Role::Bishop => PieceType::Bishop,
+            Role::Knight => PieceType::Knight,
+            Role::Pawn => PieceType::Pawn,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone)]
-pub(crate) enum Colour {
+pub enum Colour {
     None,
     White,
     Black,
@@ -21,20 +34,19 @@ pub(crate) enum Colour {
 
 #[derive(Clone)]
 pub struct Piece {
-    pub(crate) piece_type: PieceType,
-    pub(crate) colour: Colour,
-    pub(crate) filename: String,
+    pub piece_type: PieceType,
+    pub filename: String,
 }
 
 impl Piece {
-    pub(crate) fn new(piece_type: PieceType, colour: Colour) -> Self {
+    pub fn new(piece_type: PieceType, colour: Colour) -> Self {
         let mut filename = String::new();
 
         if piece_type != PieceType::None {
-            filename = Self::get_filename(piece_type.clone(), colour.clone());
+            filename = Self::get_filename(piece_type.clone(), colour);
         }
 
-        Piece { piece_type, colour, filename}
+        Piece { piece_type, filename }
     }
 
     fn get_filename(piece_type: PieceType, colour: Colour) -> String {
@@ -61,80 +73,51 @@ impl Piece {
 #[derive(Clone)]
 pub struct BoardSquare {
     pub piece: Piece,
-    pub display_coords: Point2<f32>,
-}
-
-impl BoardSquare {
-    pub fn new(piece: Piece, grid_size: f32, row_index: usize, col_index: usize) -> BoardSquare {
-        let display_x = START_X + (col_index as f32) * grid_size;
-        let display_y = START_Y + (row_index as f32) * grid_size;
-        let display_coords = Point2 { x: display_x, y: display_y };
-
-        BoardSquare {
-            piece,
-            display_coords
-        }
-    }
 }
 
+/// What the board view draws: the pieces of a `shakmaty::Board` laid out as rows of squares.
+/// It holds no game state of its own and is rebuilt from the position after every move.
 #[derive(Clone)]
-pub(crate) struct ChessBoard {
-    pub(crate) grid: Vec<Vec<BoardSquare>>,
-    pub(crate) grid_size: f32,
+pub struct ChessBoard {
+    pub grid: Vec<Vec<BoardSquare>>,
+    pub grid_size: f32,
 }
 
 impl ChessBoard {
     pub fn new(grid_size: f32) -> ChessBoard {
-        let mut grid: Vec<Vec<BoardSquare>> = Vec::new();
-
-        grid.push(Self::get_back_rank(grid_size, Colour::Black));
-        grid.push(Self::get_pawn_rank(grid_size, Colour::Black));
-
-        for i in 0..4 {
-            grid.push(Self::get_empty_rank(grid_size, i));
-        }
+        Self::from_board(&Board::default(), grid_size)
+    }
 
-        grid.push(Self::get_pawn_rank(grid_size, Colour::White));
-        grid.push(Self::get_back_rank(grid_size, Colour::White));
+    /// A board showing the pieces of `board`. Row 0 of the grid is the eighth rank.
+    pub fn from_board(board: &Board, grid_size: f32) -> ChessBoard {
+        let grid = (0..8)
+            .map(|row| {
+                (0..8)
+                    .map(|col| {
+                        let square = Square::from_coords(File::new(col as u32), Rank::new(7 - row as u32));
+                        let piece = match board.piece_at(square) {
+                            Some(piece) => Piece::new(
+                                PieceType::from(piece.role),
+                                piece.color.fold_wb(Colour::White, Colour::Black),
+                            ),
+                            None => Piece::new(PieceType::None, Colour::None),
+                        };
+                        BoardSquare { piece }
+                    })
+                    .collect()
+            })
+            .collect();
 
         ChessBoard {
             grid,
             grid_size,
         }
     }
+}
 
-    pub fn get_back_rank(grid_size: f32, colour: Colour) -> Vec<BoardSquare> {
-        let row_index = match colour {
-            Colour::White => 0,
-            Colour::Black => 7,
-            Colour::None => panic!()
-        };
-
-        vec![
-            BoardSquare::new(Piece::new(PieceType::Rook, colour.clone()), grid_size, row_index, 0),
-            BoardSquare::new(Piece::new(PieceType::Knight, colour.clone()), grid_size, row_index, 1),
-            BoardSquare::new(Piece::new(PieceType::Bishop, colour.clone()), grid_size, row_index, 2),
-            BoardSquare::new(Piece::new(PieceType::Queen, colour.clone()), grid_size, row_index, 3),
-            BoardSquare::new(Piece::new(PieceType::King, colour.clone()), grid_size, row_index, 4),
-            BoardSquare::new(Piece::new(PieceType::Bishop, colour.clone()), grid_size, row_index, 5),
-            BoardSquare::new(Piece::new(PieceType::Knight, colour.clone()), grid_size, row_index, 6),
-            BoardSquare::new(Piece::new(PieceType::Rook, colour.clone()), grid_size, row_index, 7),
-        ]
-    }
-
-    pub fn get_pawn_rank(grid_size: f32, colour: Colour) -> Vec<BoardSquare> {
-        let row_index = match colour {
-            Colour::White => 1,
-            Colour::Black => 6,
-            Colour::None => panic!()
-        };
-
-        (0..8).map(|i| BoardSquare::new(Piece::new(PieceType::Pawn, colour.clone()), grid_size, row_index, i))
-            .collect()
-    }
-
-    pub fn get_empty_rank(grid_size: f32, row_index: usize) -> Vec<BoardSquare> {
-        (0..8).map(|i| BoardSquare::new(Piece::new(PieceType::None, Colour::None), grid_size, row_index, i))
-            .collect()
-    }
+/// The rank (`x`) and file (`y`) of `square`, counted from White's side.
+pub fn square_to_board_coord(square: Square) -> Point2<usize> {
+    let file = square.file() as usize;
+    let rank = square.rank().char() as usize - '1' as usize;
+    Point2 { x: rank, y: file}
 }
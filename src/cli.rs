@@ -0,0 +1,523 @@
+use std::path::PathBuf;
+
+use shakmaty::Square;
+
+use crate::graphics::MAX_ENGINE_LINES;
+
+pub const USAGE: &str = "Usage: brilliant [OPTIONS] [FILE.pgn]
+       brilliant analyze [OPTIONS] FILE.pgn
+       brilliant render [OPTIONS] (--fen FEN | FILE.pgn) -o IMAGE
+       brilliant gif [OPTIONS] FILE.pgn
+
+Options:
+  -g, --game <N>              Open the N-th game in the file (1-based, default 1)
+  -p, --ply <N>               Start at ply N of the game (default 0)
+  -r, --orientation <SIDE>    Start with the board seen from 'white' or 'black'
+  -e, --engine <PATH>         UCI engine binary to use for analysis
+      --fake-engine <SCRIPT>  Test with a scripted stand-in engine instead of a real one
+  -l, --lines <N>             Number of engine lines to show, at most 3 (default 3)
+      --wdl                   Show win/draw/loss percentages under the evaluation bar
+  -h, --help                  Print this message";
+
+pub const ANALYZE_USAGE: &str = "Usage: brilliant analyze [OPTIONS] FILE.pgn
+
+Analyses every game in FILE.pgn without opening a window and writes them back annotated
+with evaluations, marked mistakes and the engine's better moves.
+
+Options:
+  -o, --output <FILE>         Where to write the annotated games (default FILE.analyzed.pgn)
+  -d, --depth <N>             Search each position to depth N
+  -n, --nodes <N>             Search each position for N nodes
+  -e, --engine <PATH>         UCI engine binary to use
+      --fake-engine <SCRIPT>  Test with a scripted stand-in engine instead of a real one
+  -h, --help                  Print this message";
+
+pub const RENDER_USAGE: &str = "Usage: brilliant render [OPTIONS] (--fen FEN | FILE.pgn) -o IMAGE
+
+Draws a position as a board diagram without opening a window. The image is PNG or SVG,
+depending on the extension of IMAGE.
+
+Options:
+  -o, --output <IMAGE>        Where to write the diagram (.png or .svg)
+  -f, --fen <FEN>             Draw this position instead of one from a PGN file
+  -g, --game <N>              Take the position from the N-th game in the file (default 1)
+  -p, --ply <N>               Take the position after N plies of the mainline (default 0)
+  -s, --size <PIXELS>         Width and height of the diagram (default 576)
+  -r, --orientation <SIDE>    Draw the board seen from 'white' or 'black'
+  -a, --arrow <MOVE>          Draw an arrow, e.g. e2e4; can be repeated
+      --no-coordinates        Leave out the file and rank labels
+  -h, --help                  Print this message";
+
+pub const GIF_USAGE: &str = "Usage: brilliant gif [OPTIONS] FILE.pgn
+
+Writes the mainline of a game as an animated GIF without opening a window.
+
+Options:
+  -o, --output <FILE>         Where to write the GIF (default FILE.gif)
+  -g, --game <N>              Use the N-th game in the file (default 1)
+  -s, --size <PIXELS>         Width and height of the board (default 576)
+  -d, --delay <MS>            Time each move is shown for (default 1000)
+  -r, --orientation <SIDE>    Draw the board seen from 'white' or 'black'
+      --eval-bar              Show the evaluations in the game's [%eval] comments as a bar
+      --arrows                Draw the game's [%cal] arrows and the variations' moves
+      --no-title              Leave out the title frame with the players and event
+  -h, --help                  Print this message";
+
+/// What to do: open the viewer, or analyse, draw or animate a file without a window.
+#[derive(Debug, Clone)]
+pub enum Command {
+    View(Options),
+    Analyze(AnalyzeOptions),
+    Render(RenderOptions),
+    Gif(GifOptions),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub pgn_path: Option<PathBuf>,
+    pub game_index: usize,
+    pub start_ply: usize,
+    pub board_flipped: bool,
+    pub engine_path: Option<PathBuf>,
+    pub fake_engine_script: Option<PathBuf>,
+    pub engine_lines: Option<u32>,
+    pub show_wdl: bool,
+    pub show_help: bool,
+}
+
+/// Options of `brilliant analyze`. Without a depth or node count the review settings from the
+/// config are used.
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeOptions {
+    pub pgn_path: Option<PathBuf>,
+    pub output_path: Option<PathBuf>,
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub engine_path: Option<PathBuf>,
+    pub fake_engine_script: Option<PathBuf>,
+    pub show_help: bool,
+}
+
+/// Options of `brilliant render`. The position comes from `fen` if it's given, otherwise from
+/// ply `ply` of the mainline of game `game_index` in the PGN file.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub pgn_path: Option<PathBuf>,
+    pub fen: Option<String>,
+    pub game_index: usize,
+    pub ply: usize,
+    pub output_path: Option<PathBuf>,
+    pub size: Option<u32>,
+    pub board_flipped: bool,
+    pub arrows: Vec<(Square, Square)>,
+    pub hide_coordinates: bool,
+    pub show_help: bool,
+}
+
+/// Options of `brilliant gif`. Without a delay the config's `gif_delay` is used.
+#[derive(Debug, Clone, Default)]
+pub struct GifOptions {
+    pub pgn_path: Option<PathBuf>,
+    pub output_path: Option<PathBuf>,
+    pub game_index: usize,
+    pub size: Option<u32>,
+    pub delay_ms: Option<u64>,
+    pub board_flipped: bool,
+    pub eval_bar: bool,
+    pub arrows: bool,
+    pub hide_title: bool,
+    pub show_help: bool,
+}
+
+pub fn parse_command<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+
+    if args.peek().map(String::as_str) == Some("analyze") {
+        args.next();
+        return parse_analyze_args(args).map(Command::Analyze);
+    }
+    if args.peek().map(String::as_str) == Some("render") {
+        args.next();
+        return parse_render_args(args).map(Command::Render);
+    }
+    if args.peek().map(String::as_str) == Some("gif") {
+        args.next();
+        return parse_gif_args(args).map(Command::Gif);
+    }
+
+    parse_args(args).map(Command::View)
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "--wdl" => options.show_wdl = true,
+            "-g" | "--game" => {
+                let game = parse_number(&arg, args.next())?;
+                if game == 0 {
+                    return Err(format!("{} expects a game number starting at 1", arg));
+                }
+                options.game_index = game - 1;
+            }
+            "-p" | "--ply" => options.start_ply = parse_number(&arg, args.next())?,
+            "-r" | "--orientation" => options.board_flipped = parse_orientation(&arg, args.next())?,
+            "-e" | "--engine" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.engine_path = Some(PathBuf::from(path));
+            }
+            "-l" | "--lines" => {
+                let lines = parse_number(&arg, args.next())?;
+                if lines == 0 {
+                    return Err(format!("{} expects at least one line", arg));
+                }
+                if lines > MAX_ENGINE_LINES as usize {
+                    return Err(format!("{} expects at most {} lines", arg, MAX_ENGINE_LINES));
+                }
+                options.engine_lines = Some(lines as u32);
+            }
+            "--fake-engine" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.fake_engine_script = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if options.pgn_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one PGN file can be opened", arg));
+                }
+                options.pgn_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+pub fn parse_analyze_args<I: IntoIterator<Item = String>>(args: I) -> Result<AnalyzeOptions, String> {
+    let mut options = AnalyzeOptions::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "-o" | "--output" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.output_path = Some(PathBuf::from(path));
+            }
+            "-d" | "--depth" => {
+                let depth = parse_number(&arg, args.next())?;
+                if !(1..=u8::MAX as usize).contains(&depth) {
+                    return Err(format!("{} expects a depth from 1 to {}", arg, u8::MAX));
+                }
+                options.depth = Some(depth as u8);
+            }
+            "-n" | "--nodes" => {
+                let nodes = parse_number(&arg, args.next())?;
+                if nodes == 0 {
+                    return Err(format!("{} expects at least one node", arg));
+                }
+                options.nodes = Some(nodes as u64);
+            }
+            "-e" | "--engine" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.engine_path = Some(PathBuf::from(path));
+            }
+            "--fake-engine" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.fake_engine_script = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if options.pgn_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one PGN file can be analysed", arg));
+                }
+                options.pgn_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    if options.pgn_path.is_none() && !options.show_help {
+        return Err("no PGN file given".to_string());
+    }
+
+    Ok(options)
+}
+
+pub fn parse_render_args<I: IntoIterator<Item = String>>(args: I) -> Result<RenderOptions, String> {
+    let mut options = RenderOptions::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "--no-coordinates" => options.hide_coordinates = true,
+            "-o" | "--output" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.output_path = Some(PathBuf::from(path));
+            }
+            "-f" | "--fen" => {
+                options.fen = Some(args.next().ok_or_else(|| format!("{} expects a value", arg))?);
+            }
+            "-g" | "--game" => {
+                let game = parse_number(&arg, args.next())?;
+                if game == 0 {
+                    return Err(format!("{} expects a game number starting at 1", arg));
+                }
+                options.game_index = game - 1;
+            }
+            "-p" | "--ply" => options.ply = parse_number(&arg, args.next())?,
+            "-s" | "--size" => options.size = Some(parse_size(&arg, args.next())?),
+            "-r" | "--orientation" => options.board_flipped = parse_orientation(&arg, args.next())?,
+            "-a" | "--arrow" => {
+                let value = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                let squares = value.get(..2).zip(value.get(2..))
+                    .and_then(|(from, to)| Some((from.parse::<Square>().ok()?, to.parse::<Square>().ok()?)));
+                match squares {
+                    Some(squares) => options.arrows.push(squares),
+                    None => return Err(format!("{} expects two squares like e2e4, got '{}'", arg, value)),
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if options.pgn_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one PGN file can be drawn from", arg));
+                }
+                options.pgn_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    if !options.show_help {
+        if options.pgn_path.is_none() && options.fen.is_none() {
+            return Err("no FEN or PGN file given".to_string());
+        }
+        if options.output_path.is_none() {
+            return Err("no output file given, pass --output".to_string());
+        }
+    }
+
+    Ok(options)
+}
+
+pub fn parse_gif_args<I: IntoIterator<Item = String>>(args: I) -> Result<GifOptions, String> {
+    let mut options = GifOptions::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "--eval-bar" => options.eval_bar = true,
+            "--arrows" => options.arrows = true,
+            "--no-title" => options.hide_title = true,
+            "-o" | "--output" => {
+                let path = args.next().ok_or_else(|| format!("{} expects a value", arg))?;
+                options.output_path = Some(PathBuf::from(path));
+            }
+            "-g" | "--game" => {
+                let game = parse_number(&arg, args.next())?;
+                if game == 0 {
+                    return Err(format!("{} expects a game number starting at 1", arg));
+                }
+                options.game_index = game - 1;
+            }
+            "-s" | "--size" => options.size = Some(parse_size(&arg, args.next())?),
+            "-d" | "--delay" => {
+                let delay = parse_number(&arg, args.next())?;
+                if delay < 10 {
+                    return Err(format!("{} expects at least 10 milliseconds", arg));
+                }
+                options.delay_ms = Some(delay as u64);
+            }
+            "-r" | "--orientation" => options.board_flipped = parse_orientation(&arg, args.next())?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if options.pgn_path.is_some() {
+                    return Err(format!("unexpected argument '{}', only one PGN file can be animated", arg));
+                }
+                options.pgn_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    if options.pgn_path.is_none() && !options.show_help {
+        return Err("no PGN file given".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_orientation(flag: &str, value: Option<String>) -> Result<bool, String> {
+    match value.as_deref() {
+        Some("white") => Ok(false),
+        Some("black") => Ok(true),
+        Some(other) => Err(format!("unknown orientation '{}', expected 'white' or 'black'", other)),
+        None => Err(format!("{} expects a value", flag)),
+    }
+}
+
+/// Diagram sizes in pixels; GIF frames can't be wider than 65535 anyway.
+fn parse_size(flag: &str, value: Option<String>) -> Result<u32, String> {
+    let size = parse_number(flag, value)?;
+    if !(64..=4096).contains(&size) {
+        return Err(format!("{} expects a size from 64 to 4096 pixels", flag));
+    }
+    Ok(size as u32)
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value.parse::<usize>()
+        .map_err(|_| format!("{} expects a non-negative number, got '{}'", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn view(line: &str) -> Result<Options, String> {
+        parse_args(args(line))
+    }
+
+    #[test]
+    fn viewer_options() {
+        let options = view("-g 2 --ply 12 -r black -e /usr/bin/stockfish -l 2 --wdl games.pgn").unwrap();
+        assert_eq!(options.pgn_path, Some(PathBuf::from("games.pgn")));
+        assert_eq!(options.game_index, 1);
+        assert_eq!(options.start_ply, 12);
+        assert!(options.board_flipped);
+        assert_eq!(options.engine_path, Some(PathBuf::from("/usr/bin/stockfish")));
+        assert_eq!(options.engine_lines, Some(2));
+        assert!(options.show_wdl);
+
+        let options = view("").unwrap();
+        assert_eq!(options.pgn_path, None);
+        assert_eq!(options.game_index, 0);
+        assert_eq!(options.start_ply, 0);
+        assert!(!options.board_flipped);
+    }
+
+    #[test]
+    fn game_numbers_start_at_one() {
+        assert_eq!(view("--game 1").unwrap().game_index, 0);
+        assert_eq!(view("-g 10").unwrap().game_index, 9);
+        assert!(view("-g 0").unwrap_err().contains("starting at 1"));
+        assert!(view("-g -1").is_err());
+    }
+
+    #[test]
+    fn ply() {
+        assert_eq!(view("-p 0").unwrap().start_ply, 0);
+        assert_eq!(view("--ply 41").unwrap().start_ply, 41);
+        assert!(view("--ply one").unwrap_err().contains("'one'"));
+    }
+
+    #[test]
+    fn orientation() {
+        assert!(!view("--orientation white").unwrap().board_flipped);
+        assert!(view("-r black").unwrap().board_flipped);
+        assert!(view("-r Black").unwrap_err().contains("unknown orientation 'Black'"));
+        assert!(view("-r").unwrap_err().contains("expects a value"));
+
+        assert!(parse_render_args(args("-r black --fen 8/8/8/8/8/8/8/K1k5 -o board.svg")).unwrap().board_flipped);
+        assert!(parse_gif_args(args("--orientation black game.pgn")).unwrap().board_flipped);
+    }
+
+    #[test]
+    fn engine_lines_are_limited() {
+        assert_eq!(view("--lines 3").unwrap().engine_lines, Some(3));
+        assert!(view("--lines 0").is_err());
+        assert!(view("--lines 4").unwrap_err().contains("at most 3"));
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values() {
+        assert_eq!(view("--bogus").unwrap_err(), "unknown option '--bogus'");
+        // `-o` is only for the output of the other commands.
+        assert_eq!(view("-o black").unwrap_err(), "unknown option '-o'");
+
+        for flag in ["-g", "--ply", "--engine", "--fake-engine", "--lines"] {
+            assert_eq!(view(flag).unwrap_err(), format!("{} expects a value", flag));
+        }
+        assert!(parse_analyze_args(args("game.pgn --output")).unwrap_err().contains("expects a value"));
+        assert!(parse_render_args(args("game.pgn -o")).unwrap_err().contains("expects a value"));
+        assert!(parse_gif_args(args("game.pgn --delay")).unwrap_err().contains("expects a value"));
+    }
+
+    #[test]
+    fn only_one_file() {
+        assert!(view("a.pgn b.pgn").unwrap_err().contains("'b.pgn'"));
+        assert!(parse_analyze_args(args("a.pgn b.pgn")).is_err());
+        assert!(parse_render_args(args("a.pgn b.pgn -o board.png")).is_err());
+        assert!(parse_gif_args(args("a.pgn b.pgn")).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse_command(args("game.pgn")), Ok(Command::View(_))));
+        assert!(matches!(parse_command(args("analyze game.pgn")), Ok(Command::Analyze(_))));
+        assert!(matches!(parse_command(args("render game.pgn -o board.png")), Ok(Command::Render(_))));
+        assert!(matches!(parse_command(args("gif game.pgn")), Ok(Command::Gif(_))));
+        // A subcommand's name only counts in first place.
+        assert!(view("game.pgn analyze").is_err());
+    }
+
+    #[test]
+    fn analyze_options() {
+        let options = parse_analyze_args(args("game.pgn -o out.pgn -d 12 -n 5000 -e sf")).unwrap();
+        assert_eq!(options.pgn_path, Some(PathBuf::from("game.pgn")));
+        assert_eq!(options.output_path, Some(PathBuf::from("out.pgn")));
+        assert_eq!(options.depth, Some(12));
+        assert_eq!(options.nodes, Some(5000));
+        assert_eq!(options.engine_path, Some(PathBuf::from("sf")));
+
+        assert_eq!(parse_analyze_args(args("-d 12")).unwrap_err(), "no PGN file given");
+        assert!(parse_analyze_args(args("--help")).unwrap().show_help);
+        assert!(parse_analyze_args(args("game.pgn -d 0")).is_err());
+        assert!(parse_analyze_args(args("game.pgn -d 256")).is_err());
+        assert!(parse_analyze_args(args("game.pgn -n 0")).is_err());
+    }
+
+    #[test]
+    fn render_options() {
+        let options = parse_render_args(args("game.pgn -g 2 -p 7 -s 256 -a e2e4 -a g8f6 --no-coordinates -o b.png")).unwrap();
+        assert_eq!(options.pgn_path, Some(PathBuf::from("game.pgn")));
+        assert_eq!(options.game_index, 1);
+        assert_eq!(options.ply, 7);
+        assert_eq!(options.size, Some(256));
+        assert_eq!(options.arrows, [(Square::E2, Square::E4), (Square::G8, Square::F6)]);
+        assert!(options.hide_coordinates);
+        assert_eq!(options.output_path, Some(PathBuf::from("b.png")));
+
+        let options = parse_render_args(["--fen", "8/8/8/8/8/8/8/K1k5 w - - 0 1", "-o", "board.svg"].map(String::from)).unwrap();
+        assert_eq!(options.fen.as_deref(), Some("8/8/8/8/8/8/8/K1k5 w - - 0 1"));
+        assert_eq!(options.pgn_path, None);
+
+        assert_eq!(parse_render_args(args("game.pgn")).unwrap_err(), "no output file given, pass --output");
+        assert_eq!(parse_render_args(args("-o board.png")).unwrap_err(), "no FEN or PGN file given");
+        assert!(parse_render_args(args("--help")).unwrap().show_help);
+        assert!(parse_render_args(args("game.pgn -o b.png -a e2")).unwrap_err().contains("two squares"));
+        assert!(parse_render_args(args("game.pgn -o b.png -s 32")).is_err());
+    }
+
+    #[test]
+    fn gif_options() {
+        let options = parse_gif_args(args("game.pgn -o out.gif -g 3 -s 320 -d 500 --eval-bar --arrows --no-title")).unwrap();
+        assert_eq!(options.pgn_path, Some(PathBuf::from("game.pgn")));
+        assert_eq!(options.output_path, Some(PathBuf::from("out.gif")));
+        assert_eq!(options.game_index, 2);
+        assert_eq!(options.size, Some(320));
+        assert_eq!(options.delay_ms, Some(500));
+        assert!(options.eval_bar && options.arrows && options.hide_title);
+
+        assert_eq!(parse_gif_args(args("-d 500")).unwrap_err(), "no PGN file given");
+        assert!(parse_gif_args(args("--help")).unwrap().show_help);
+        assert!(parse_gif_args(args("game.pgn -d 5")).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use std::process::Command;
+
+/// Programs that print the clipboard's text, tried in order: Wayland, X11, macOS and
+/// Windows.
+const PASTE_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-out"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+    ("powershell", &["-NoProfile", "-Command", "Get-Clipboard"]),
+];
+
+/// The text on the system clipboard. ggez has no clipboard access, so this asks the
+/// platform's paste tool.
+pub fn read_text() -> Result<String, String> {
+    for (program, args) in PASTE_COMMANDS {
+        let Ok(output) = Command::new(program).args(*args).output() else {
+            continue;
+        };
+
+        if output.status.success() {
+            return String::from_utf8(output.stdout)
+                .map_err(|_| "the clipboard doesn't hold text".to_string());
+        }
+    }
+
+    Err("could not read the clipboard, install wl-clipboard, xclip or xsel".to_string())
+}
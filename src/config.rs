@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_ENV_VAR: &str = "BRILLIANT_CONFIG";
+
+/// Settings read from a `key = value` file, one entry per line. Lines starting with `#` are ignored.
+///
+/// The file is looked up at `$BRILLIANT_CONFIG`, then `$XDG_CONFIG_HOME/brilliant/config`,
+/// then `~/.config/brilliant/config`. A missing file gives an empty config.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    values: HashMap<String, String>,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Config::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(_) => Config::default(),
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Self::path_from(env::var_os(CONFIG_ENV_VAR), env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))
+    }
+
+    /// `path` with `$BRILLIANT_CONFIG`, `$XDG_CONFIG_HOME` and `$HOME` passed in.
+    fn path_from(config_path: Option<OsString>, config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+        if let Some(path) = config_path {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_dir = config_home
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_dir.join("brilliant").join("config"))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut values = HashMap::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim().to_string(), value.trim().to_string());
+            } else {
+                eprintln!("Ignoring malformed config line: {}", line);
+            }
+        }
+
+        Config { values }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(
+            "# Engine settings
+             engine = /usr/local/bin/stockfish
+
+               multipv=2
+             # show_wdl = true
+             gif_delay =
+             key = value = with equals
+             not a setting
+             unknown_key = kept",
+        );
+
+        assert_eq!(config.get("engine"), Some("/usr/local/bin/stockfish"));
+        assert_eq!(config.get("multipv"), Some("2"));
+        assert_eq!(config.get("show_wdl"), None);
+        assert_eq!(config.get("gif_delay"), Some(""));
+        assert_eq!(config.get("key"), Some("value = with equals"));
+        assert_eq!(config.get("not a setting"), None);
+        assert_eq!(config.get("unknown_key"), Some("kept"));
+        assert_eq!(config.values.len(), 5);
+    }
+
+    #[test]
+    fn later_lines_win() {
+        let config = Config::parse("multipv = 1\nmultipv = 3\n");
+        assert_eq!(config.get("multipv"), Some("3"));
+    }
+
+    #[test]
+    fn path_precedence() {
+        let os = |value: &str| Some(OsString::from(value));
+
+        assert_eq!(Config::path_from(os("/etc/brilliant"), os("/xdg"), os("/home/me")), Some(PathBuf::from("/etc/brilliant")));
+        assert_eq!(Config::path_from(None, os("/xdg"), os("/home/me")), Some(PathBuf::from("/xdg/brilliant/config")));
+        assert_eq!(Config::path_from(None, None, os("/home/me")), Some(PathBuf::from("/home/me/.config/brilliant/config")));
+        assert_eq!(Config::path_from(None, None, None), None);
+    }
+}
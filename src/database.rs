@@ -0,0 +1,377 @@
+use std::cmp::Ordering;
+
+use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
+
+use crate::pgn::{ChessGamePlayer, PgnError};
+
+/// A game in a PGN database: where its text starts and ends in the file and its headers.
+/// The moves aren't parsed until the game is opened.
+#[derive(Debug, Clone)]
+pub struct GameEntry {
+    pub offset: usize,
+    pub length: usize,
+    pub headers: Vec<(String, String)>,
+}
+
+impl GameEntry {
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn white(&self) -> &str {
+        self.header("White").unwrap_or("?")
+    }
+
+    pub fn black(&self) -> &str {
+        self.header("Black").unwrap_or("?")
+    }
+
+    pub fn date(&self) -> &str {
+        self.header("Date").unwrap_or("????.??.??")
+    }
+
+    pub fn result(&self) -> &str {
+        self.header("Result").unwrap_or("*")
+    }
+
+    pub fn eco(&self) -> &str {
+        self.header("ECO").unwrap_or("")
+    }
+
+    pub fn white_elo(&self) -> Option<u32> {
+        self.header("WhiteElo").and_then(|elo| elo.parse().ok())
+    }
+
+    pub fn black_elo(&self) -> Option<u32> {
+        self.header("BlackElo").and_then(|elo| elo.parse().ok())
+    }
+
+    /// The higher of the two ratings, used to sort games by strength.
+    pub fn rating(&self) -> Option<u32> {
+        self.white_elo().max(self.black_elo())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    White,
+    Black,
+    Date,
+    Result,
+    Eco,
+    Rating,
+}
+
+impl SortKey {
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::White => "White",
+            SortKey::Black => "Black",
+            SortKey::Date => "Date",
+            SortKey::Result => "Result",
+            SortKey::Eco => "ECO",
+            SortKey::Rating => "Elo",
+        }
+    }
+
+    fn compare(self, a: &GameEntry, b: &GameEntry) -> Ordering {
+        match self {
+            SortKey::White => a.white().to_lowercase().cmp(&b.white().to_lowercase()),
+            SortKey::Black => a.black().to_lowercase().cmp(&b.black().to_lowercase()),
+            SortKey::Date => a.date().cmp(b.date()),
+            SortKey::Result => a.result().cmp(b.result()),
+            SortKey::Eco => a.eco().cmp(b.eco()),
+            SortKey::Rating => a.rating().cmp(&b.rating()),
+        }
+    }
+}
+
+/// All games of a PGN file. Indexing reads only the headers of each game; the movetext is
+/// parsed when a game is loaded.
+pub struct PgnDatabase {
+    source: String,
+    games: Vec<GameEntry>,
+}
+
+impl PgnDatabase {
+    pub fn index(source: String) -> Result<Self, PgnError> {
+        let mut games = Vec::new();
+        let mut offset = 0;
+
+        while offset < source.len() {
+            // A fresh reader per game lets us tell how many bytes the game took up from what
+            // the reader hasn't consumed yet.
+            let remaining = &source.as_bytes()[offset..];
+            let mut reader = BufferedReader::new_cursor(remaining);
+            let mut visitor = HeaderVisitor::default();
+
+            let Some(headers) = reader.read_game(&mut visitor)? else {
+                break;
+            };
+
+            let (buffered, rest) = reader.into_inner().into_inner();
+            let unread = buffered.get_ref().as_ref().len() - buffered.position() as usize
+                + remaining.len() - rest.position() as usize;
+            let length = remaining.len() - unread;
+            if length == 0 {
+                break;
+            }
+
+            // pgn-reader skips tokens it doesn't understand, so text after the last game reads
+            // as a game with no headers and no moves.
+            if !headers.is_empty() || has_moves(&remaining[..length]) {
+                games.push(GameEntry { offset, length, headers });
+            }
+            offset += length;
+        }
+
+        if games.is_empty() {
+            return Err(PgnError::NoGame);
+        }
+
+        Ok(PgnDatabase { source, games })
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    pub fn games(&self) -> &[GameEntry] {
+        &self.games
+    }
+
+    pub fn game_text(&self, index: usize) -> Option<&str> {
+        let game = self.games.get(index)?;
+        self.source.get(game.offset..game.offset + game.length)
+    }
+
+    /// The text of the whole file.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Replaces the text of game `index` with `pgn` and indexes the result. Nothing changes if
+    /// the new text can't be indexed.
+    pub fn replace_game(&mut self, index: usize, pgn: &str) -> Result<(), PgnError> {
+        let game = self.games.get(index)
+            .ok_or(PgnError::GameNotFound { index, count: self.len() })?;
+
+        let mut source = self.source[..game.offset].to_string();
+        source.push_str(pgn.trim_end());
+        source.push('\n');
+
+        let rest = self.source[game.offset + game.length..].trim_start();
+        if !rest.is_empty() {
+            source.push('\n');
+            source.push_str(rest);
+        }
+
+        *self = PgnDatabase::index(source)?;
+        Ok(())
+    }
+
+    /// Parses the moves of game `index` into `game_player`.
+    pub fn load(&self, index: usize, game_player: &mut ChessGamePlayer) -> Result<(), PgnError> {
+        let text = self.game_text(index)
+            .ok_or(PgnError::GameNotFound { index, count: self.len() })?;
+        game_player.load_pgn(text, 0)
+    }
+
+    /// Game indices ordered by `key`. Ties keep file order.
+    pub fn sorted(&self, key: SortKey, ascending: bool) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.games.len()).collect();
+        order.sort_by(|&a, &b| {
+            let ordering = key.compare(&self.games[a], &self.games[b]);
+            if ascending { ordering } else { ordering.reverse() }
+        });
+        order
+    }
+}
+
+fn has_moves(pgn: &[u8]) -> bool {
+    let mut visitor = MovesVisitor::default();
+    BufferedReader::new_cursor(pgn).read_game(&mut visitor).ok().flatten().unwrap_or(false)
+}
+
+#[derive(Default)]
+struct MovesVisitor {
+    has_moves: bool,
+}
+
+impl Visitor for MovesVisitor {
+    type Result = bool;
+
+    fn san(&mut self, _: SanPlus) {
+        self.has_moves = true;
+    }
+
+    fn end_game(&mut self) -> Self::Result {
+        self.has_moves
+    }
+}
+
+#[derive(Default)]
+struct HeaderVisitor {
+    headers: Vec<(String, String)>,
+}
+
+impl Visitor for HeaderVisitor {
+    type Result = Vec<(String, String)>;
+
+    fn begin_game(&mut self) {
+        self.headers.clear();
+    }
+
+    fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        if let (Ok(key), Ok(value)) = (std::str::from_utf8(key), value.decode_utf8()) {
+            self.headers.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    fn end_headers(&mut self) -> Skip {
+        Skip(true)
+    }
+
+    fn end_game(&mut self) -> Self::Result {
+        std::mem::take(&mut self.headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::ChessBoard;
+
+    /// Indexes `pgn` and checks that each game's text reads back as the game in that place of
+    /// the whole file. Returns the database and each game's mainline.
+    fn index_and_reread(pgn: &str) -> (PgnDatabase, Vec<Vec<String>>) {
+        let database = PgnDatabase::index(pgn.to_string()).unwrap();
+        let mut mainlines = Vec::new();
+
+        for index in 0..database.len() {
+            let mut from_file = ChessGamePlayer::new(ChessBoard::new(72.0));
+            from_file.load_pgn(pgn, index).unwrap();
+            let mut from_text = ChessGamePlayer::new(ChessBoard::new(72.0));
+            database.load(index, &mut from_text).unwrap();
+
+            assert_eq!(from_text.get_headers(), from_file.get_headers(), "headers of game {}", index);
+            assert_eq!(database.games()[index].headers, from_file.get_headers(), "indexed headers of game {}", index);
+
+            let mainline = |game: &ChessGamePlayer| -> Vec<String> {
+                game.tree().mainline_from(crate::game_tree::ROOT).map(|node| game.tree().node(node).san.clone()).collect()
+            };
+            assert_eq!(mainline(&from_text), mainline(&from_file), "moves of game {}", index);
+            mainlines.push(mainline(&from_text));
+        }
+
+        (database, mainlines)
+    }
+
+    #[test]
+    fn index_crlf_file() {
+        let (database, mainlines) = index_and_reread(
+            "[Event \"One\"]\r\n[Result \"1-0\"]\r\n\r\n1. e4 e5 2. Qh5 Nc6\r\n3. Bc4 Nf6 4. Qxf7# 1-0\r\n\r\n\
+             [Event \"Two\"]\r\n\r\n1. d4 {A comment\r\nover two lines} d5 *\r\n",
+        );
+
+        assert_eq!(database.len(), 2);
+        assert_eq!(mainlines[0].len(), 7);
+        assert_eq!(mainlines[1], ["d4", "d5"]);
+        assert_eq!(database.games()[1].header("Event"), Some("Two"));
+    }
+
+    #[test]
+    fn index_file_with_bom() {
+        let (database, mainlines) = index_and_reread("\u{feff}[Event \"One\"]\n\n1. e4 *\n\n[Event \"Two\"]\n\n1. c4 *\n");
+
+        assert_eq!(database.len(), 2);
+        assert_eq!(database.games()[0].header("Event"), Some("One"));
+        assert_eq!(mainlines, [["e4"], ["c4"]]);
+    }
+
+    #[test]
+    fn index_games_without_headers() {
+        let (database, mainlines) = index_and_reread("1. e4 e5 *\n\n1. d4 d5 2. c4 *\n\n[Event \"Three\"]\n\n1. Nf3 *\n");
+
+        assert_eq!(database.len(), 3);
+        assert!(database.games()[0].headers.is_empty());
+        assert_eq!(database.games()[0].white(), "?");
+        assert_eq!(mainlines[1], ["d4", "d5", "c4"]);
+        assert_eq!(database.games()[2].header("Event"), Some("Three"));
+    }
+
+    #[test]
+    fn index_ignores_trailing_text() {
+        let (database, _) = index_and_reread("[Event \"One\"]\n\n1. e4 *\n\n[Event \"Two\"]\n\n1. d4 *\n\n\n   \n");
+        assert_eq!(database.len(), 2);
+
+        let (database, _) = index_and_reread("[Event \"One\"]\n\n1. e4 *\n\nExported by some program\n");
+        assert_eq!(database.len(), 1);
+        assert!(database.source().ends_with("Exported by some program\n"));
+    }
+
+    #[test]
+    fn index_final_game_without_blank_line() {
+        let (database, mainlines) = index_and_reread("[Event \"One\"]\n\n1. e4 *\n[Event \"Two\"]\n\n1. d4 d5 *");
+
+        assert_eq!(database.len(), 2);
+        assert_eq!(database.games()[1].header("Event"), Some("Two"));
+        assert_eq!(mainlines[1], ["d4", "d5"]);
+        assert!(database.game_text(1).unwrap().ends_with("1. d4 d5 *"));
+    }
+
+    #[test]
+    fn index_rejects_empty_file() {
+        assert!(matches!(PgnDatabase::index(String::new()), Err(PgnError::NoGame)));
+        assert!(matches!(PgnDatabase::index("\n\n  \n".to_string()), Err(PgnError::NoGame)));
+        assert!(matches!(PgnDatabase::index("Not a chess game".to_string()), Err(PgnError::NoGame)));
+    }
+
+    const THREE_GAMES: &str = "[Event \"First\"]\n[White \"A\"]\n\n1. e4 e5 {A comment} 2. Nf3 1-0\n\n\
+        [Event \"Second\"]\n[White \"B\"]\n\n1. d4 d5 *\n\n\n\
+        [Event \"Third\"]\r\n[White \"C\"]\r\n\r\n1. c4 (1. Nf3) e5   0-1\r\n";
+
+    #[test]
+    fn replace_game_keeps_the_other_games() {
+        let mut database = PgnDatabase::index(THREE_GAMES.to_string()).unwrap();
+        let first = database.game_text(0).unwrap().to_string();
+        let third = database.game_text(2).unwrap().to_string();
+
+        database.replace_game(1, "[Event \"Second\"]\n[White \"B\"]\n\n1. d4 {Annotated} d5 (1... Nf6) *\n").unwrap();
+
+        assert_eq!(database.len(), 3);
+        assert_eq!(database.game_text(0).unwrap(), first);
+        assert_eq!(database.game_text(2).unwrap(), third);
+        assert!(database.source().starts_with(&first));
+        assert!(database.source().ends_with(&third));
+        assert!(database.game_text(1).unwrap().contains("{Annotated}"));
+    }
+
+    #[test]
+    fn replace_game_at_either_end() {
+        let mut database = PgnDatabase::index(THREE_GAMES.to_string()).unwrap();
+        let middle = database.game_text(1).unwrap().to_string();
+
+        database.replace_game(0, "[Event \"New first\"]\n\n1. g3 *").unwrap();
+        database.replace_game(2, "[Event \"New third\"]\n\n1. b3 *").unwrap();
+
+        assert_eq!(database.game_text(1).unwrap(), middle);
+        assert_eq!(database.games()[0].header("Event"), Some("New first"));
+        assert_eq!(database.games()[2].header("Event"), Some("New third"));
+        assert!(database.source().ends_with("1. b3 *\n"));
+    }
+
+    #[test]
+    fn replace_game_rejects_a_missing_game() {
+        let mut database = PgnDatabase::index(THREE_GAMES.to_string()).unwrap();
+
+        assert!(matches!(database.replace_game(3, "1. e4 *"), Err(PgnError::GameNotFound { index: 3, count: 3 })));
+        assert_eq!(database.source(), THREE_GAMES);
+    }
+}
@@ -1,58 +1,191 @@
-use std::io::{BufRead, BufReader, Error, Write};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Error, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::sync::{mpsc, Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
-
-const STOCKFISH_PATH: &str = "engines/stockfish-windows-x86-64-avx2";
+use std::time::{Duration, Instant};
+
+use shakmaty::{Chess, Color, Position};
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+
+use crate::config::Config;
+use crate::fake_engine::{FakeEngine, FakeEngineScript};
+use crate::fen::position_to_fen;
+use crate::uci::{GoParams, Score, ScoreValue, UciCommand, UciMessage, Wdl};
+
+const ENGINE_ENV_VAR: &str = "BRILLIANT_ENGINE";
+const ENGINE_CONFIG_KEY: &str = "engine";
+const ENGINE_DIR: &str = "engines";
+const ENGINE_NAMES: &[&str] = &[
+    "stockfish",
+    "stockfish-ubuntu-x86-64-avx2",
+    "stockfish-ubuntu-x86-64-bmi2",
+    "stockfish-ubuntu-x86-64",
+    "stockfish-windows-x86-64-avx2",
+    "stockfish-macos-m1-apple-silicon",
+];
 const ENGINE_THREADS: &str = "4";
 const ENGINE_HASH: &str = "128";
+pub const MATE_CENTIPAWNS: i32 = 10_000;
+
+/// How long dropping an engine waits for its output to end before leaving the reader thread be.
+const READER_EXIT_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Debug)]
 pub struct EngineUpdate {
-    pub best_move: Option<Vec<String>>,
-    pub evaluation: Option<f32>,
+    pub best_move: Option<UciMove>,
+    pub evaluation: Option<Evaluation>,
     pub depth: Option<u8>,
+    pub lines: Vec<EngineLine>,
     pub is_final: bool,
+    /// Why the search ended without the engine's move, if it failed.
+    pub error: Option<String>,
+}
+
+impl EngineUpdate {
+    /// The final update of a search that couldn't be run at all.
+    pub fn failed(error: &Error) -> Self {
+        EngineUpdate {
+            best_move: None,
+            evaluation: None,
+            depth: None,
+            lines: Vec::new(),
+            is_final: true,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// An engine score together with the side it was reported for, since UCI scores are always
+/// from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Evaluation {
+    pub score: Score,
+    pub wdl: Option<Wdl>,
+    pub turn: Color,
+}
+
+impl Evaluation {
+    /// Centipawns from White's point of view, with mates pushed to +/-`MATE_CENTIPAWNS`.
+    pub fn white_centipawns(&self) -> i32 {
+        let centipawns = match self.score.value {
+            ScoreValue::Centipawns(cp) => cp,
+            ScoreValue::Mate(moves) if moves > 0 => MATE_CENTIPAWNS,
+            ScoreValue::Mate(_) => -MATE_CENTIPAWNS,
+        };
+        self.turn.fold_wb(centipawns, -centipawns)
+    }
+
+    /// Moves to mate from White's point of view, negative when Black mates. `Some(0)` means the
+    /// side to move is already checkmated.
+    pub fn white_mate(&self) -> Option<i32> {
+        match self.score.value {
+            ScoreValue::Mate(moves) => Some(self.turn.fold_wb(moves, -moves)),
+            ScoreValue::Centipawns(_) => None,
+        }
+    }
+
+    /// Win/draw/loss in permille from White's point of view.
+    pub fn white_wdl(&self) -> Option<Wdl> {
+        self.wdl.map(|wdl| match self.turn {
+            Color::White => wdl,
+            Color::Black => Wdl { wins: wdl.losses, draws: wdl.draws, losses: wdl.wins },
+        })
+    }
+
+    /// "+0.35", "#3" (White mates in 3) or "#-5" (Black mates in 5).
+    pub fn label(&self) -> String {
+        match self.white_mate() {
+            Some(0) => self.turn.fold_wb("0-1", "1-0").to_string(),
+            Some(moves) => format!("#{}", moves),
+            None => format!("{:+.2}", self.white_centipawns() as f32 / 100.0),
+        }
+    }
+}
+
+/// One principal variation of a MultiPV search. `pv_san` holds the same moves as `pv` in SAN.
+#[derive(Clone, Debug)]
+pub struct EngineLine {
+    pub multipv: u32,
+    pub depth: u32,
+    pub evaluation: Evaluation,
+    pub pv: Vec<UciMove>,
+    pub pv_san: Vec<String>,
 }
 
 
 pub struct StockfishEngineInternal {
-    process: Child,
-    writer: Arc<Mutex<std::process::ChildStdin>>,
+    process: Option<Child>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
     reader_thread: Option<thread::JoinHandle<()>>,
     output_buffer: Arc<Mutex<Vec<String>>>,
     running: Arc<Mutex<bool>>,
-    cancel_search: Arc<AtomicBool>,
-    current_best_move: Arc<Mutex<Option<Vec<String>>>>,
-    current_evaluation: Arc<Mutex<Option<f32>>>,
+    exited: Arc<AtomicBool>,
+    /// Whether the engine has been sent a `go` it hasn't answered with `bestmove` yet.
+    searching: Arc<AtomicBool>,
+    /// Bumped by every search and cancellation. A search's thread stops once it has moved on.
+    search_id: Arc<AtomicUsize>,
+    current_best_move: Arc<Mutex<Option<UciMove>>>,
+    current_evaluation: Arc<Mutex<Option<Evaluation>>>,
+    supports_wdl: bool,
+    debug_mode: bool,
 }
 
 impl StockfishEngineInternal {
-    pub fn new(debug_mode: bool) -> Result<Self, Error> {
-        let mut process = Command::new(STOCKFISH_PATH)
+    pub fn new(engine_path: &Path, debug_mode: bool) -> Result<Self, Error> {
+        let mut process = Command::new(engine_path)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
             .spawn()?;
 
-        let writer = Arc::new(Mutex::new(process.stdin.take().unwrap()));
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+
+        Self::from_io(Box::new(stdout), Box::new(stdin), Some(process), debug_mode)
+    }
+
+    /// Runs the engine against a scripted in-process stand-in instead of a real binary.
+    pub fn with_fake_engine(script: FakeEngineScript, debug_mode: bool) -> Result<Self, Error> {
+        let (stdout, stdin) = FakeEngine::spawn(script)?;
+        Self::from_io(Box::new(stdout), Box::new(stdin), None, debug_mode)
+    }
+
+    fn from_io(
+        stdout: Box<dyn Read + Send>,
+        stdin: Box<dyn Write + Send>,
+        process: Option<Child>,
+        debug_mode: bool,
+    ) -> Result<Self, Error> {
+        let writer = Arc::new(Mutex::new(stdin));
         let output_buffer = Arc::new(Mutex::new(Vec::new()));
         let running = Arc::new(Mutex::new(true));
+        let exited = Arc::new(AtomicBool::new(false));
+        let searching = Arc::new(AtomicBool::new(false));
 
         let reader_output_buffer = output_buffer.clone();
         let reader_running = running.clone();
-        let stdout = process.stdout.take().unwrap();
+        let reader_exited = exited.clone();
+        let reader_searching = searching.clone();
 
         let reader_thread = thread::spawn(move || {
             let buf_reader = BufReader::new(stdout);
             for line in buf_reader.lines() {
                 if let Ok(line) = line {
-                    if !line.contains("info") || debug_mode {
+                    if debug_mode {
                         println!("Engine output: {}", line);
                     }
 
+                    if line.starts_with("bestmove") {
+                        reader_searching.store(false, Ordering::Relaxed);
+                    }
+
                     if let Ok(mut buffer) = reader_output_buffer.lock() {
                         buffer.push(line);
                     }
@@ -64,37 +197,52 @@ impl StockfishEngineInternal {
                     }
                 }
             }
+
+            reader_exited.store(true, Ordering::Relaxed);
         });
 
-        let engine = StockfishEngineInternal {
+        let mut engine = StockfishEngineInternal {
             process,
             writer,
             reader_thread: Some(reader_thread),
             output_buffer,
             running,
-            cancel_search: Arc::new(AtomicBool::new(false)),
+            exited,
+            searching,
+            search_id: Arc::new(AtomicUsize::new(0)),
             current_best_move: Arc::new(Mutex::new(None)),
             current_evaluation: Arc::new(Mutex::new(None)),
+            supports_wdl: false,
+            debug_mode,
         };
 
-        engine.send_command("uci")?;
-        engine.wait_for_response("uciok", 5000)?;
+        engine.send_command(&UciCommand::Uci)?;
+        let uci_output = engine.wait_for_response(&UciMessage::UciOk, 5000)?;
+        engine.supports_wdl = uci_output.iter().any(|line| {
+            matches!(UciMessage::parse(line), Ok(UciMessage::Option(option)) if option.name == "UCI_ShowWDL")
+        });
+        if engine.supports_wdl {
+            engine.set_option("UCI_ShowWDL", "true")?;
+        }
+        if debug_mode {
+            engine.send_command(&UciCommand::Debug(true))?;
+        }
         engine.set_option("Threads", ENGINE_THREADS)?;
         engine.set_option("Hash", ENGINE_HASH)?;
         engine.set_option("MultiPV", "1")?;
 
-        engine.send_command("ucinewgame")?;
-        engine.send_command("isready")?;
-        engine.wait_for_response("readyok", 5000)?;
+        engine.send_command(&UciCommand::UciNewGame)?;
+        engine.send_command(&UciCommand::IsReady)?;
+        engine.wait_for_response(&UciMessage::ReadyOk, 5000)?;
 
-        engine.send_command("position startpos")?;
-        engine.send_command("isready")?;
-        engine.wait_for_response("readyok", 5000)?;
+        engine.send_command(&UciCommand::Position { fen: None, moves: Vec::new() })?;
+        engine.send_command(&UciCommand::IsReady)?;
+        engine.wait_for_response(&UciMessage::ReadyOk, 5000)?;
 
         Ok(engine)
     }
 
-    pub fn send_command(&self, command: &str) -> Result<(), Error> {
+    pub fn send_command(&self, command: &UciCommand) -> Result<(), Error> {
         if let Ok(mut stdin) = self.writer.lock() {
             writeln!(stdin, "{}", command)?;
             stdin.flush()?;
@@ -110,24 +258,20 @@ impl StockfishEngineInternal {
         result
     }
 
-    pub fn wait_for_response(&self, response: &str, timeout_ms: u64) -> Result<Vec<String>, Error> {
+    pub fn wait_for_response(&self, response: &UciMessage, timeout_ms: u64) -> Result<Vec<String>, Error> {
         let start = std::time::Instant::now();
         let mut found = false;
+        let is_response = |line: &String| UciMessage::parse(line).is_ok_and(|message| message == *response);
 
         if let Ok(buffer) = self.output_buffer.lock() {
-            if buffer.iter().any(|line| line.contains(response)) {
+            if buffer.iter().any(is_response) {
                 found = true;
             }
         }
 
         while !found && start.elapsed().as_millis() < timeout_ms as u128 {
             if let Ok(buffer) = self.output_buffer.lock() {
-                for line in buffer.iter() {
-                    if line.contains(response) {
-                        found = true;
-                        break;
-                    }
-                }
+                found = buffer.iter().any(is_response);
             }
             thread::sleep(Duration::from_millis(10));
         }
@@ -142,41 +286,73 @@ impl StockfishEngineInternal {
         Ok(self.get_output())
     }
 
-    pub fn find_best_move(&self, depth: Option<u8>, time_ms: Option<u64>, is_white_move: bool, update_sender: mpsc::Sender<EngineUpdate>) {
-        self.cancel_search.store(false, Ordering::Relaxed);
-
-        let mut go_cmd = String::from("go");
+    pub fn find_best_move(
+        &self,
+        position: &Chess,
+        depth: Option<u8>,
+        time_ms: Option<u64>,
+        update_sender: mpsc::Sender<EngineUpdate>,
+    ) -> Result<(), Error> {
+        let mut go_params = GoParams::default();
         if let Some(d) = depth {
-            go_cmd.push_str(&format!(" depth {}", d));
+            go_params.depth = Some(d.into());
         } else if let Some(t) = time_ms {
-            go_cmd.push_str(&format!(" movetime {}", t));
+            go_params.move_time = Some(t);
         } else {
-            go_cmd.push_str(" depth 20");
+            go_params.depth = Some(20);
         }
 
-        self.send_command(&go_cmd).unwrap();
+        self.search(position, go_params, update_sender)
+    }
 
-        {
-            *self.current_best_move.lock().unwrap() = None;
-            *self.current_evaluation.lock().unwrap() = None;
+    /// Searches `position` with the given limits and streams its progress to `update_sender`
+    /// until the engine plays its move or the search is cancelled. Fails if the engine has
+    /// exited or can't be sent the search.
+    ///
+    /// A search still running is stopped first, and its `bestmove` waited for, so none of its
+    /// output is taken for this one's.
+    pub fn search(&self, position: &Chess, go_params: GoParams, update_sender: mpsc::Sender<EngineUpdate>) -> Result<(), Error> {
+        if self.has_exited() {
+            return Err(Error::new(std::io::ErrorKind::BrokenPipe, "the engine is not running"));
         }
 
+        self.finish_search()?;
+        self.send_command(&UciCommand::IsReady)?;
+        self.wait_for_response(&UciMessage::ReadyOk, 5000)?;
+
+        let id = self.search_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let turn = position.turn();
+        let position = position.clone();
+
         {
             self.output_buffer.lock().unwrap().clear();
         }
 
+        self.set_position(&position_to_fen(&position))?;
+        self.searching.store(true, Ordering::Relaxed);
+        self.send_command(&UciCommand::Go(go_params))?;
+
+        {
+            *self.current_best_move.lock().unwrap() = None;
+            *self.current_evaluation.lock().unwrap() = None;
+        }
+
         let output_buffer = self.output_buffer.clone();
-        let cancel_search = self.cancel_search.clone();
+        let exited = self.exited.clone();
+        let search_id = self.search_id.clone();
+        let cancelled = move || search_id.load(Ordering::Relaxed) != id;
         let current_best_move = self.current_best_move.clone();
         let current_evaluation = self.current_evaluation.clone();
+        let debug_mode = self.debug_mode;
 
         thread::spawn(move || {
-            let mut last_sent_move: Option<Vec<String>> = None;
-            let mut last_sent_eval: Option<f32> = None;
+            let mut last_sent_move: Option<UciMove> = None;
+            let mut last_sent_eval: Option<Evaluation> = None;
             let mut processed_lines = 0;
+            let mut lines: BTreeMap<u32, EngineLine> = BTreeMap::new();
 
             loop {
-                if cancel_search.load(Ordering::Relaxed) {
+                if cancelled() {
                     break;
                 }
 
@@ -186,7 +362,10 @@ impl StockfishEngineInternal {
                 let mut current_move = None;
                 let mut current_eval = None;
                 let mut current_depth = None;
+                let mut lines_changed = false;
                 let mut is_final = false;
+                let mut error = None;
+                let engine_exited = exited.load(Ordering::Relaxed);
 
                 if let Ok(buffer) = output_buffer.lock() {
                     if processed_lines > buffer.len() {
@@ -200,94 +379,68 @@ impl StockfishEngineInternal {
                     };
 
                     for line in new_lines.iter() {
-                        if cancel_search.load(Ordering::Relaxed) {
+                        if cancelled() {
                             return;
                         }
 
-                        if line.contains("info depth") && line.contains("score") && line.contains("pv ") {
-                            if let Some(depth_start) = line.find("depth ") {
-                                let depth_str = &line[depth_start + 6..];
-                                if let Some(space_pos) = depth_str.find(' ') {
-                                    if let Ok(d) = depth_str[..space_pos].parse::<u8>() {
-                                        current_depth = Some(d);
-                                    }
-                                }
-                            }
-
-                            if line.contains("score cp ") {
-                                let parts: Vec<&str> = line.split("score cp ").collect();
-                                if parts.len() >= 2 {
-                                    let score_parts: Vec<&str> = parts[1].split_whitespace().collect();
-                                    if !score_parts.is_empty() {
-                                        if let Ok(score) = score_parts[0].parse::<i32>() {
-                                            let adjusted_score = if is_white_move { score } else { -score };
-                                            current_eval = Some(adjusted_score as f32);
-                                        }
-                                    }
-                                }
-                            } else if line.contains("score mate ") {
-                                let parts: Vec<&str> = line.split("score mate ").collect();
-                                if parts.len() >= 2 {
-                                    let score_parts: Vec<&str> = parts[1].split_whitespace().collect();
-                                    if !score_parts.is_empty() {
-                                        if let Ok(moves) = score_parts[0].parse::<i32>() {
-                                            let mate_score = if moves > 0 { 1000.0 } else { -1000.0 };
-                                            current_eval = Some(if is_white_move { mate_score } else { -mate_score });
-                                        }
-                                    }
-                                }
-                            }
+                        match UciMessage::parse(line) {
+                            Ok(UciMessage::Info(info)) => {
+                                let (Some(score), Some(first_move)) = (info.score, info.pv.first().cloned()) else {
+                                    continue;
+                                };
 
-                            if let Some(pv_start) = line.find(" pv ") {
-                                let pv_str = &line[pv_start + 3..];
-                                let moves: Vec<&str> = pv_str.split_whitespace().collect();
+                                let eval = Evaluation { score, wdl: info.wdl, turn };
+                                let multipv = info.multipv.unwrap_or(1);
 
-                                println!("PV line: {}", pv_str);
-
-                                if !moves.is_empty() {
-                                    let best_move = moves[0];
-                                    println!("Extracted best move: '{}'", best_move);
-
-                                    if best_move.len() >= 4 && best_move.len() <= 5 {
-                                        let from = &best_move[0..2];
-                                        let to = &best_move[2..4];
-                                        current_move = Some(vec![from.to_string(), to.to_string()]);
-                                        println!("Valid move parsed: {} -> {}", from, to);
-
-                                    } else {
-                                        println!("Invalid move length: '{}' (len: {})", best_move, best_move.len());
-                                    }
+                                if multipv == 1 {
+                                    current_depth = info.depth.map(|d| d.min(u8::MAX.into()) as u8);
+                                    current_eval = Some(eval);
+                                    current_move = Some(first_move);
                                 }
-                            }
 
-                            found_update = true;
-                        }
+                                lines.insert(multipv, EngineLine {
+                                    multipv,
+                                    depth: info.depth.unwrap_or(0),
+                                    evaluation: eval,
+                                    pv_san: pv_to_san(&position, &info.pv),
+                                    pv: info.pv,
+                                });
+                                lines_changed = true;
 
-                        if line.contains("bestmove") {
-                            let parts: Vec<&str> = line.split_whitespace().collect();
-                            if parts.len() >= 2 {
-                                let best_move = parts[1];
-                                if !best_move.contains("(none)") && best_move.len() >= 4 {
-                                    let from = &best_move[0..2];
-                                    let to = &best_move[2..4];
-                                    current_move = Some(vec![from.to_string(), to.to_string()]);
-                                    is_final = true;
-                                    found_update = true;
+                                found_update = true;
+                            }
+                            Ok(UciMessage::BestMove { best_move, .. }) => {
+                                if best_move.is_some() {
+                                    current_move = best_move;
                                 }
+                                is_final = true;
+                                found_update = true;
+                                break;
                             }
-                            break;
+                            Ok(_) => {}
+                            Err(err) if debug_mode => println!("Ignoring engine output: {}", err),
+                            Err(_) => {}
                         }
                     }
 
                     processed_lines = buffer.len();
                 }
 
+                if engine_exited && !is_final {
+                    if debug_mode {
+                        println!("Engine exited during search");
+                    }
+                    is_final = true;
+                    found_update = true;
+                    error = Some("the engine stopped running".to_string());
+                }
+
                 if found_update {
                     let move_changed = current_move != last_sent_move;
                     let eval_changed = current_eval != last_sent_eval;
                     let depth_changed = current_depth.is_some();
 
-                    if move_changed || eval_changed || depth_changed || is_final {
+                    if move_changed || eval_changed || depth_changed || lines_changed || is_final {
                         if let Some(ref mv) = current_move {
                             *current_best_move.lock().unwrap() = Some(mv.clone());
                         }
@@ -299,14 +452,20 @@ impl StockfishEngineInternal {
                             best_move: current_move.clone(),
                             evaluation: current_eval,
                             depth: current_depth,
+                            lines: lines.values().cloned().collect(),
                             is_final,
+                            error,
                         };
 
-                        println!("Sending update: move={:?}, eval={:?}, depth={:?}, final={}",
-                                 update.best_move, update.evaluation, update.depth, update.is_final);
+                        if debug_mode {
+                            println!("Sending update: move={:?}, eval={:?}, depth={:?}, final={}",
+                                     update.best_move, update.evaluation, update.depth, update.is_final);
+                        }
 
                         if update_sender.send(update).is_err() {
-                            println!("Failed to send update - receiver dropped");
+                            if debug_mode {
+                                println!("Failed to send update - receiver dropped");
+                            }
                             break;
                         }
 
@@ -320,19 +479,53 @@ impl StockfishEngineInternal {
                 }
             }
         });
+
+        Ok(())
     }
 
     pub fn cancel_search(&self) {
-        self.cancel_search.store(true, Ordering::Relaxed);
-        let _ = self.send_command("stop");
+        self.search_id.fetch_add(1, Ordering::Relaxed);
+        let _ = self.send_command(&UciCommand::Stop);
+    }
+
+    /// Stops the engine's search, if it's running one, and waits for its `bestmove`.
+    fn finish_search(&self) -> Result<(), Error> {
+        if !self.searching.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.send_command(&UciCommand::Stop)?;
+
+        let start = Instant::now();
+        while self.searching.load(Ordering::Relaxed) {
+            if self.has_exited() || start.elapsed() > Duration::from_millis(5000) {
+                return Err(Error::new(std::io::ErrorKind::TimedOut, "the engine didn't stop searching"));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(())
     }
 
     pub fn set_position(&self, position: &str) -> Result<(), Error> {
-        self.send_command(&format!("position fen {}", position))
+        self.send_command(&UciCommand::Position { fen: Some(position.to_string()), moves: Vec::new() })
+    }
+
+    pub fn supports_wdl(&self) -> bool {
+        self.supports_wdl
+    }
+
+    /// Whether the engine's output has closed, because it quit or crashed.
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::Relaxed)
+    }
+
+    pub fn set_multipv(&self, lines: u32) -> Result<(), Error> {
+        self.set_option("MultiPV", &lines.to_string())
     }
 
     pub fn set_option(&self, name: &str, value: &str) -> Result<(), Error> {
-        self.send_command(&format!("setoption name {} value {}", name, value))
+        self.send_command(&UciCommand::SetOption { name: name.to_string(), value: Some(value.to_string()) })
     }
 }
 
@@ -342,30 +535,50 @@ impl Drop for StockfishEngineInternal {
             *is_running = false;
         }
 
-        let _ = self.send_command("quit");
+        let _ = self.send_command(&UciCommand::Quit);
 
-        if let Some(thread) = self.reader_thread.take() {
-            let _ = thread.join();
+        // Closing stdin and killing the process end the engine's output even if it ignores
+        // `quit`, and the reader thread is only waited on for so long in case it still hangs.
+        if let Ok(mut stdin) = self.writer.lock() {
+            *stdin = Box::new(io::sink());
+        }
+
+        if let Some(process) = &mut self.process {
+            let _ = process.kill();
+            let _ = process.wait();
         }
 
-        let _ = self.process.kill();
+        if let Some(thread) = self.reader_thread.take() {
+            let start = Instant::now();
+            while !thread.is_finished() && start.elapsed() < READER_EXIT_TIMEOUT {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if thread.is_finished() {
+                let _ = thread.join();
+            }
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct StockfishEngine {
-    pub(crate) internal: Arc<Mutex<StockfishEngineInternal>>,
+    pub internal: Arc<Mutex<StockfishEngineInternal>>,
 }
 
 impl StockfishEngine {
-    pub fn new(debug_mode: bool) -> Self {
-        let engine_internal = StockfishEngineInternal::new(debug_mode).unwrap();
+    pub fn new(engine_path: &Path, debug_mode: bool) -> Result<Self, Error> {
+        let engine_internal = StockfishEngineInternal::new(engine_path, debug_mode)?;
         let arc_mutex_internal = Arc::new(Mutex::new(engine_internal));
 
-        Self { internal: arc_mutex_internal }
+        Ok(Self { internal: arc_mutex_internal })
+    }
+
+    pub fn with_fake_engine(script: FakeEngineScript, debug_mode: bool) -> Result<Self, Error> {
+        let engine_internal = StockfishEngineInternal::with_fake_engine(script, debug_mode)?;
+        Ok(Self { internal: Arc::new(Mutex::new(engine_internal)) })
     }
 
-    pub fn lock(&self) -> std::sync::MutexGuard<StockfishEngineInternal> {
+    pub fn lock(&self) -> std::sync::MutexGuard<'_, StockfishEngineInternal> {
         self.internal.lock().unwrap()
     }
 
@@ -376,3 +589,358 @@ impl StockfishEngine {
 }
 
 
+
+/// Converts a principal variation to SAN, stopping at the first move that isn't legal.
+fn pv_to_san(position: &Chess, pv: &[UciMove]) -> Vec<String> {
+    let mut position = position.clone();
+    let mut san_moves = Vec::with_capacity(pv.len());
+
+    for uci_move in pv {
+        let Ok(mv) = uci_move.to_move(&position) else {
+            break;
+        };
+        san_moves.push(SanPlus::from_move_and_play_unchecked(&mut position, &mv).to_string());
+    }
+
+    san_moves
+}
+
+/// Starts the scripted stand-in engine if a script is given, otherwise the engine binary picked
+/// by `locate_engine`.
+pub fn spawn_engine(
+    engine_path: Option<&Path>,
+    fake_engine_script: Option<&Path>,
+    config: &Config,
+    debug_mode: bool,
+) -> Result<StockfishEngine, String> {
+    if let Some(script_path) = fake_engine_script {
+        let script = fs::read_to_string(script_path)
+            .map_err(|err| err.to_string())
+            .and_then(|script| FakeEngineScript::parse(&script))
+            .map_err(|err| format!("failed to load engine script {}: {}", script_path.display(), err))?;
+
+        return StockfishEngine::with_fake_engine(script, debug_mode)
+            .map_err(|err| format!("failed to start fake engine: {}", err));
+    }
+
+    let engine_path = locate_engine(engine_path, config)
+        .ok_or_else(|| format!("no chess engine found, pass --engine or set {}", ENGINE_ENV_VAR))?;
+
+    StockfishEngine::new(&engine_path, debug_mode)
+        .map_err(|err| format!("failed to start engine {}: {}", engine_path.display(), err))
+}
+
+/// Picks the engine binary to run. An explicit path wins, then `$BRILLIANT_ENGINE`, then the
+/// `engine` key of the config file. Otherwise the `engines/` directory and `$PATH` are searched
+/// for the usual Stockfish binary names.
+pub fn locate_engine(explicit_path: Option<&Path>, config: &Config) -> Option<PathBuf> {
+    let mut search_dirs = vec![PathBuf::from(ENGINE_DIR)];
+    if let Some(path_var) = env::var_os("PATH") {
+        search_dirs.extend(env::split_paths(&path_var));
+    }
+
+    find_engine(explicit_path, env::var_os(ENGINE_ENV_VAR), config, &search_dirs)
+}
+
+/// `locate_engine` with `$BRILLIANT_ENGINE` and the directories to search passed in.
+fn find_engine(
+    explicit_path: Option<&Path>,
+    env_path: Option<OsString>,
+    config: &Config,
+    search_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    if let Some(path) = explicit_path {
+        return Some(path.to_path_buf());
+    }
+
+    if let Some(path) = env_path.filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+
+    if let Some(path) = config.get(ENGINE_CONFIG_KEY) {
+        return Some(PathBuf::from(path));
+    }
+
+    search_dirs.iter()
+        .flat_map(|dir| ENGINE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|candidate| is_executable(candidate) || is_executable(&candidate.with_extension("exe")))
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::sync::mpsc::{Receiver, RecvTimeoutError};
+    use std::time::Instant;
+
+    use super::*;
+
+    fn fake_engine(script: &str) -> StockfishEngine {
+        let script = FakeEngineScript::parse(script).unwrap();
+        StockfishEngine::with_fake_engine(script, false).unwrap()
+    }
+
+    fn start_search(engine: &StockfishEngine) -> Receiver<EngineUpdate> {
+        let (sender, receiver) = mpsc::channel();
+        engine.lock().search(&Chess::default(), GoParams::default(), sender).unwrap();
+        receiver
+    }
+
+    /// Every update up to the final one, or until the search thread gives up.
+    fn collect_updates(receiver: &Receiver<EngineUpdate>) -> Vec<EngineUpdate> {
+        let mut updates = Vec::new();
+        while let Ok(update) = receiver.recv_timeout(Duration::from_secs(5)) {
+            let is_final = update.is_final;
+            updates.push(update);
+            if is_final {
+                break;
+            }
+        }
+        updates
+    }
+
+    fn uci(mv: &str) -> UciMove {
+        mv.parse().unwrap()
+    }
+
+    #[test]
+    fn search_streams_updates_until_bestmove() {
+        let engine = fake_engine(
+            "on go
+             send info depth 1 score cp 20 pv e2e4
+             delay 300
+             send info depth 2 score cp 35 pv d2d4 d7d5
+             delay 300
+             send bestmove d2d4",
+        );
+
+        let updates = collect_updates(&start_search(&engine));
+
+        let first = &updates[0];
+        assert!(!first.is_final);
+        assert_eq!(first.depth, Some(1));
+        assert_eq!(first.best_move, Some(uci("e2e4")));
+        assert_eq!(first.evaluation.map(|eval| eval.white_centipawns()), Some(20));
+
+        assert!(updates.iter().any(|update| !update.is_final && update.depth == Some(2)));
+
+        let last = updates.last().unwrap();
+        assert!(last.is_final);
+        assert_eq!(last.best_move, Some(uci("d2d4")));
+        assert_eq!(last.lines.len(), 1);
+        assert_eq!(last.lines[0].pv_san, ["d4", "d5"]);
+    }
+
+    #[test]
+    fn cancel_search_stops_updates_and_the_engine() {
+        let engine = fake_engine(
+            "on go
+             send info depth 1 score cp 20 pv e2e4
+             delay 5000
+             send bestmove e2e4",
+        );
+
+        let receiver = start_search(&engine);
+        let first = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert!(!first.is_final);
+
+        let cancelled = Instant::now();
+        engine.cancel_search();
+
+        // The search thread stops without reporting a result, long before the script's delay.
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(2)) {
+                Ok(update) => assert!(!update.is_final),
+                Err(err) => {
+                    assert_eq!(err, RecvTimeoutError::Disconnected);
+                    break;
+                }
+            }
+        }
+        assert!(cancelled.elapsed() < Duration::from_secs(2));
+
+        // The engine was told to stop and cut its search short.
+        let best_move = UciMessage::BestMove { best_move: Some(uci("e2e4")), ponder: None };
+        engine.lock().wait_for_response(&best_move, 2000).unwrap();
+    }
+
+    #[test]
+    fn wait_for_response_times_out() {
+        let engine = fake_engine("");
+
+        let started = Instant::now();
+        let err = engine.lock().wait_for_response(&UciMessage::ReadyOk, 100).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn engine_crash_during_search_ends_it() {
+        let engine = fake_engine(
+            "on go
+             send info depth 1 score cp 20 pv e2e4
+             delay 200
+             crash",
+        );
+
+        let updates = collect_updates(&start_search(&engine));
+
+        // What the engine found before crashing is kept, and the search still ends.
+        assert!(updates.iter().any(|update| update.best_move == Some(uci("e2e4"))));
+        assert!(updates.last().unwrap().is_final);
+        assert!(updates.last().unwrap().error.is_some());
+        assert!(engine.lock().has_exited());
+    }
+
+    #[test]
+    fn search_after_the_engine_exits_fails() {
+        let engine = fake_engine(
+            "on go
+             crash",
+        );
+        collect_updates(&start_search(&engine));
+        assert!(engine.lock().has_exited());
+
+        let (sender, _receiver) = mpsc::channel();
+        let err = engine.lock().search(&Chess::default(), GoParams::default(), sender).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+
+        // The engine is still usable, just not running.
+        engine.cancel_search();
+        assert!(engine.lock().has_exited());
+    }
+
+    #[test]
+    fn dropping_a_hung_engine_returns() {
+        let script = FakeEngineScript::parse(
+            "on go
+             delay 30000
+             send bestmove e2e4
+             on quit
+             delay 30000",
+        ).unwrap();
+        let engine = StockfishEngineInternal::with_fake_engine(script, false).unwrap();
+        engine.send_command(&UciCommand::Go(GoParams::default())).unwrap();
+
+        let start = Instant::now();
+        drop(engine);
+        assert!(start.elapsed() < READER_EXIT_TIMEOUT + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn search_after_cancel_gets_none_of_the_old_output() {
+        let script = FakeEngineScript::parse(
+            "on go
+             send info depth 1 score cp 20 pv e2e4
+             delay 2000
+             send bestmove e2e4
+             on go
+             delay 100
+             send info depth 5 score cp 15 pv c7c5
+             delay 100
+             send bestmove c7c5",
+        ).unwrap();
+        let engine = StockfishEngine::with_fake_engine(script.clone(), false).unwrap();
+
+        let first = start_search(&engine);
+        assert_eq!(first.recv_timeout(Duration::from_secs(5)).unwrap().best_move, Some(uci("e2e4")));
+        engine.cancel_search();
+
+        let mut after_e4 = Chess::default();
+        after_e4.play_unchecked(&uci("e2e4").to_move(&after_e4).unwrap());
+        let (sender, receiver) = mpsc::channel();
+        engine.lock().search(&after_e4, GoParams::default(), sender).unwrap();
+
+        let updates = collect_updates(&receiver);
+        assert!(updates.iter().all(|update| update.best_move == Some(uci("c7c5"))), "{:?}", updates);
+        assert!(updates.last().unwrap().is_final);
+
+        let received = script.received();
+        let position = received.iter().rposition(|command| command.starts_with("position")).unwrap();
+        assert!(received[..position].iter().any(|command| command == "stop"));
+        assert_eq!(received[position + 1..], ["go"]);
+    }
+
+    #[test]
+    fn search_stops_the_one_running() {
+        let script = FakeEngineScript::parse(
+            "on go
+             delay 5000
+             send bestmove e2e4
+             on go
+             send bestmove d2d4",
+        ).unwrap();
+        let engine = StockfishEngine::with_fake_engine(script.clone(), false).unwrap();
+
+        let _first = start_search(&engine);
+        let second = start_search(&engine);
+
+        let updates = collect_updates(&second);
+        assert_eq!(updates.last().unwrap().best_move, Some(uci("d2d4")));
+        assert!(script.received().contains(&"stop".to_string()));
+    }
+
+    #[test]
+    fn engine_precedence() {
+        let config = Config::parse("engine = /from/config");
+        let explicit = Path::new("/explicit");
+        let env_path = || Some(OsString::from("/from/env"));
+
+        assert_eq!(find_engine(Some(explicit), env_path(), &config, &[]), Some(PathBuf::from("/explicit")));
+        assert_eq!(find_engine(None, env_path(), &config, &[]), Some(PathBuf::from("/from/env")));
+        assert_eq!(find_engine(None, None, &config, &[]), Some(PathBuf::from("/from/config")));
+        // An empty variable counts as unset.
+        assert_eq!(find_engine(None, Some(OsString::new()), &config, &[]), Some(PathBuf::from("/from/config")));
+        assert_eq!(find_engine(None, None, &Config::default(), &[]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn engine_search() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = env::temp_dir().join(format!("brilliant-engine-search-{}", std::process::id()));
+        let (engines, bin) = (root.join("engines"), root.join("bin"));
+        fs::create_dir_all(&engines).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        let create = |path: &Path, mode: u32| {
+            fs::write(path, "").unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+
+        // Not executable, so skipped for the one further down the search path.
+        create(&engines.join("stockfish"), 0o644);
+        create(&bin.join("stockfish-ubuntu-x86-64"), 0o755);
+        let search_dirs = [engines.clone(), bin.clone()];
+        assert_eq!(find_engine(None, None, &Config::default(), &search_dirs), Some(bin.join("stockfish-ubuntu-x86-64")));
+
+        // Earlier directories win, then the order of the names.
+        create(&engines.join("stockfish-macos-m1-apple-silicon"), 0o755);
+        assert_eq!(find_engine(None, None, &Config::default(), &search_dirs), Some(engines.join("stockfish-macos-m1-apple-silicon")));
+        create(&engines.join("stockfish"), 0o755);
+        assert_eq!(find_engine(None, None, &Config::default(), &search_dirs), Some(engines.join("stockfish")));
+
+        // Any configured engine is used before searching.
+        let config = Config::parse("engine = sf");
+        assert_eq!(find_engine(None, None, &config, &search_dirs), Some(PathBuf::from("sf")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+use ggez::event::winit_event::{ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent};
+use ggez::event::{self, ErrorOrigin, EventHandler};
+use ggez::input::keyboard::{KeyInput, KeyMods};
+use ggez::winit::dpi::LogicalPosition;
+use ggez::winit::event_loop::{ControlFlow, EventLoop};
+use ggez::{Context, GameResult};
+
+/// Window events that `ggez::event::EventHandler` has no callback for.
+pub trait WindowEventHandler: EventHandler {
+    /// A file was dropped onto the window. ggez has no `ErrorOrigin` for it, so an error is
+    /// passed to `on_error` as coming from `MouseButtonUpEvent`, the release that ends the drag.
+    fn file_dropped_event(&mut self, ctx: &mut Context, path: PathBuf) -> GameResult;
+}
+
+/// The same loop as `ggez::event::run` in ggez 0.9.3, which drops `WindowEvent::DroppedFile` on
+/// the floor, with dropped files passed on to `file_dropped_event`. Errors from the callbacks go
+/// to `on_error` as there. Gamepad events are left out, since a `GamepadId` can't be made
+/// outside ggez.
+pub fn run<S: WindowEventHandler + 'static>(mut ctx: Context, event_loop: EventLoop<()>, mut state: S) -> ! {
+    event_loop.run(move |mut event, _, control_flow| {
+        let ctx = &mut ctx;
+        let state = &mut state;
+
+        if ctx.quit_requested {
+            ctx.quit_requested = false;
+            let result = quit_event(ctx, state);
+            if catch_error(ctx, result, state, control_flow, ErrorOrigin::QuitEvent) {
+                return;
+            }
+        }
+        if !ctx.continuing {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
+        *control_flow = ControlFlow::Poll;
+        event::process_event(ctx, &mut event);
+
+        match event {
+            Event::WindowEvent { event, .. } => {
+                if let Some((origin, result)) = window_event(ctx, state, event) {
+                    catch_error(ctx, result, state, control_flow, origin);
+                }
+            }
+            Event::MainEventsCleared => frame(ctx, state, control_flow),
+            _ => {}
+        }
+    })
+}
+
+/// Passes an error from the callback for `origin` to `on_error`, ending the loop if it says to.
+/// Returns whether it did.
+fn catch_error<S: WindowEventHandler>(
+    ctx: &mut Context,
+    result: GameResult,
+    state: &mut S,
+    control_flow: &mut ControlFlow,
+    origin: ErrorOrigin,
+) -> bool {
+    if let Err(err) = result {
+        eprintln!("Error on EventHandler {:?}: {:?}", origin, err);
+        if state.on_error(ctx, origin, err) {
+            *control_flow = ControlFlow::Exit;
+            return true;
+        }
+    }
+    false
+}
+
+/// Asks the state whether to quit and stops the loop unless it objects.
+fn quit_event<S: WindowEventHandler>(ctx: &mut Context, state: &mut S) -> GameResult {
+    if !state.quit_event(ctx)? {
+        ctx.continuing = false;
+    }
+    Ok(())
+}
+
+/// Calls the callback for a window event, returning what it gave back and where it came from.
+fn window_event<S: WindowEventHandler>(
+    ctx: &mut Context,
+    state: &mut S,
+    event: WindowEvent,
+) -> Option<(ErrorOrigin, GameResult)> {
+    let result = match event {
+        WindowEvent::Resized(size) => {
+            (ErrorOrigin::ResizeEvent, state.resize_event(ctx, size.width as f32, size.height as f32))
+        }
+        WindowEvent::CloseRequested => (ErrorOrigin::QuitEvent, quit_event(ctx, state)),
+        WindowEvent::Focused(gained) => (ErrorOrigin::FocusEvent, state.focus_event(ctx, gained)),
+        WindowEvent::DroppedFile(path) => (ErrorOrigin::MouseButtonUpEvent, state.file_dropped_event(ctx, path)),
+        WindowEvent::ReceivedCharacter(character) => {
+            (ErrorOrigin::TextInputEvent, state.text_input_event(ctx, character))
+        }
+        WindowEvent::ModifiersChanged(mods) => {
+            ctx.keyboard.set_modifiers(KeyMods::from(mods));
+            return None;
+        }
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: key_state, virtual_keycode: keycode, scancode, .. },
+            ..
+        } => {
+            let input = KeyInput { scancode, keycode, mods: ctx.keyboard.active_mods() };
+            match key_state {
+                ElementState::Pressed => {
+                    let repeated = ctx.keyboard.is_key_repeated();
+                    (ErrorOrigin::KeyDownEvent, state.key_down_event(ctx, input, repeated))
+                }
+                ElementState::Released => (ErrorOrigin::KeyUpEvent, state.key_up_event(ctx, input)),
+            }
+        }
+        WindowEvent::MouseWheel { delta, .. } => {
+            let (x, y) = match delta {
+                MouseScrollDelta::LineDelta(x, y) => (x, y),
+                MouseScrollDelta::PixelDelta(position) => {
+                    let LogicalPosition { x, y } = position.to_logical::<f32>(ctx.gfx.window().scale_factor());
+                    (x, y)
+                }
+            };
+            (ErrorOrigin::MouseWheelEvent, state.mouse_wheel_event(ctx, x, y))
+        }
+        WindowEvent::MouseInput { state: button_state, button, .. } => {
+            let position = ctx.mouse.position();
+            match button_state {
+                ElementState::Pressed => (
+                    ErrorOrigin::MouseButtonDownEvent,
+                    state.mouse_button_down_event(ctx, button, position.x, position.y),
+                ),
+                ElementState::Released => (
+                    ErrorOrigin::MouseButtonUpEvent,
+                    state.mouse_button_up_event(ctx, button, position.x, position.y),
+                ),
+            }
+        }
+        WindowEvent::CursorMoved { .. } => {
+            let position = ctx.mouse.position();
+            let delta = ctx.mouse.last_delta();
+            (ErrorOrigin::MouseMotionEvent, state.mouse_motion_event(ctx, position.x, position.y, delta.x, delta.y))
+        }
+        WindowEvent::Touch(touch) => (
+            ErrorOrigin::TouchEvent,
+            state.touch_event(ctx, touch.phase, touch.location.x, touch.location.y),
+        ),
+        WindowEvent::CursorEntered { .. } => (ErrorOrigin::MouseEnterOrLeave, state.mouse_enter_or_leave(ctx, true)),
+        WindowEvent::CursorLeft { .. } => (ErrorOrigin::MouseEnterOrLeave, state.mouse_enter_or_leave(ctx, false)),
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+/// Updates and draws a frame. Graphics errors end the loop without asking `on_error`, as in ggez.
+fn frame<S: WindowEventHandler>(ctx: &mut Context, state: &mut S, control_flow: &mut ControlFlow) {
+    ctx.time.tick();
+
+    let result = state.update(ctx);
+    if catch_error(ctx, result, state, control_flow, ErrorOrigin::Update) {
+        return;
+    }
+
+    if let Err(err) = ctx.gfx.begin_frame() {
+        eprintln!("Error on GraphicsContext::begin_frame(): {:?}", err);
+        *control_flow = ControlFlow::Exit;
+    }
+
+    let result = state.draw(ctx);
+    if catch_error(ctx, result, state, control_flow, ErrorOrigin::Draw) {
+        return;
+    }
+
+    if let Err(err) = ctx.gfx.end_frame() {
+        eprintln!("Error on GraphicsContext::end_frame(): {:?}", err);
+        *control_flow = ControlFlow::Exit;
+    }
+
+    ctx.mouse.reset_delta();
+    ctx.keyboard.save_keyboard_state();
+    ctx.mouse.save_mouse_state();
+}
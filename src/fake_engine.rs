@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::uci::{UciCommand, UciMessage};
+
+/// One step of a scripted reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    Send(String),
+    Delay(Duration),
+    Crash,
+}
+
+/// Canned replies for an in-process stand-in engine, keyed by command name.
+///
+/// The script is line based:
+///
+/// ```text
+/// # Replies to the first `go`, a later `on go` block answers the second one.
+/// on go
+/// send info depth 1 score cp 20 pv e2e4
+/// delay 200
+/// send bestmove e2e4
+/// ```
+///
+/// `send` writes a raw line, so malformed output can be scripted too. `crash` closes the
+/// engine's output and stops it. Commands without a block get the minimal replies a UCI
+/// engine must give: `uci` gets `uciok`, `isready` gets `readyok` and `stop` gets a `bestmove`
+/// if a search is running. If `stop` arrives while a `go` block is being played, the remaining
+/// `info` lines are skipped and the block's `bestmove` is sent straight away.
+///
+/// Clones of a script share the log of [`received`](Self::received) commands, so a test can
+/// keep one to see what the engine was told.
+#[derive(Debug, Clone, Default)]
+pub struct FakeEngineScript {
+    replies: HashMap<String, Vec<Vec<ScriptStep>>>,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeEngineScript {
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut replies: HashMap<String, Vec<Vec<ScriptStep>>> = HashMap::new();
+        let mut current: Option<(String, Vec<ScriptStep>)> = None;
+
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let argument = argument.trim();
+
+            let step = match keyword {
+                "on" => {
+                    if argument.is_empty() {
+                        return Err(format!("line {}: 'on' expects a command name", index + 1));
+                    }
+                    if let Some((command, steps)) = current.take() {
+                        replies.entry(command).or_default().push(steps);
+                    }
+                    current = Some((argument.to_string(), Vec::new()));
+                    continue;
+                }
+                "send" => ScriptStep::Send(argument.to_string()),
+                "delay" => {
+                    let millis = argument.parse::<u64>()
+                        .map_err(|_| format!("line {}: invalid delay '{}'", index + 1, argument))?;
+                    ScriptStep::Delay(Duration::from_millis(millis))
+                }
+                "crash" => ScriptStep::Crash,
+                _ => return Err(format!("line {}: unknown keyword '{}'", index + 1, keyword)),
+            };
+
+            match &mut current {
+                Some((_, steps)) => steps.push(step),
+                None => return Err(format!("line {}: '{}' outside an 'on' block", index + 1, keyword)),
+            }
+        }
+
+        if let Some((command, steps)) = current {
+            replies.entry(command).or_default().push(steps);
+        }
+
+        Ok(FakeEngineScript { replies, received: Arc::default() })
+    }
+
+    /// Every command line sent to the engine running this script so far, in order.
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Returns the next block for `command`. Blocks are used in order and the last one repeats.
+    fn next_reply(&mut self, command: &str) -> Option<Vec<ScriptStep>> {
+        let blocks = self.replies.get_mut(command)?;
+        if blocks.len() > 1 {
+            Some(blocks.remove(0))
+        } else {
+            blocks.first().cloned()
+        }
+    }
+}
+
+pub struct FakeEngine {
+    script: FakeEngineScript,
+    commands: Receiver<String>,
+    output: Option<PipeWriter>,
+    searching: bool,
+}
+
+impl FakeEngine {
+    /// Starts the fake engine on a background thread and returns its stdout and stdin.
+    pub fn spawn(script: FakeEngineScript) -> io::Result<(PipeReader, PipeWriter)> {
+        let (stdout_reader, stdout_writer) = io::pipe()?;
+        let (stdin_reader, stdin_writer) = io::pipe()?;
+        let (command_sender, commands) = mpsc::channel();
+        let received = script.received.clone();
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdin_reader).lines() {
+                let Ok(line) = line else { break };
+                received.lock().unwrap().push(line.clone());
+                if command_sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            let mut engine = FakeEngine {
+                script,
+                commands,
+                output: Some(stdout_writer),
+                searching: false,
+            };
+            engine.run();
+        });
+
+        Ok((stdout_reader, stdin_writer))
+    }
+
+    fn run(&mut self) {
+        while let Ok(line) = self.commands.recv() {
+            if !self.handle_command(&line) {
+                break;
+            }
+        }
+    }
+
+    fn handle_command(&mut self, line: &str) -> bool {
+        let command = match UciCommand::parse(line) {
+            Ok(command) => command,
+            Err(err) => {
+                self.send(&format!("info string {}", err));
+                return true;
+            }
+        };
+
+        let name = line.split_whitespace().next().unwrap_or_default();
+        if let Some(steps) = self.script.next_reply(name) {
+            if matches!(command, UciCommand::Go(_)) {
+                self.searching = true;
+            }
+            return self.play(&steps);
+        }
+
+        match command {
+            UciCommand::Uci => self.send(&UciMessage::UciOk.to_string()),
+            UciCommand::IsReady => self.send(&UciMessage::ReadyOk.to_string()),
+            UciCommand::Go(_) => self.searching = true,
+            UciCommand::Stop if self.searching => {
+                self.searching = false;
+                self.send(&UciMessage::BestMove { best_move: None, ponder: None }.to_string());
+            }
+            UciCommand::Quit => return false,
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Plays a block of steps, returning false once the engine has crashed or quit.
+    fn play(&mut self, steps: &[ScriptStep]) -> bool {
+        let mut stopped = false;
+
+        for step in steps {
+            match step {
+                ScriptStep::Send(line) => {
+                    let is_best_move = line.starts_with("bestmove");
+                    if stopped && !is_best_move {
+                        continue;
+                    }
+                    self.send(line);
+                    if is_best_move {
+                        self.searching = false;
+                    }
+                }
+                ScriptStep::Delay(duration) => {
+                    if stopped {
+                        continue;
+                    }
+                    match self.wait_for_stop(*duration) {
+                        Some(true) => stopped = true,
+                        Some(false) => {}
+                        None => return false,
+                    }
+                }
+                ScriptStep::Crash => {
+                    self.output = None;
+                    return false;
+                }
+            }
+        }
+
+        if stopped && self.searching {
+            self.searching = false;
+            self.send(&UciMessage::BestMove { best_move: None, ponder: None }.to_string());
+        }
+
+        true
+    }
+
+    /// Sleeps for `duration` while still reading commands. Returns whether `stop` was received,
+    /// or `None` if the GUI quit or hung up.
+    fn wait_for_stop(&mut self, duration: Duration) -> Option<bool> {
+        let deadline = Instant::now() + duration;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.commands.recv_timeout(remaining) {
+                Ok(line) => match UciCommand::parse(&line) {
+                    Ok(UciCommand::Stop) if self.searching => return Some(true),
+                    Ok(UciCommand::Quit) => return None,
+                    _ => {
+                        if !self.handle_command(&line) {
+                            return None;
+                        }
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => return Some(false),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        if let Some(output) = &mut self.output {
+            if writeln!(output, "{}", line).and_then(|_| output.flush()).is_err() {
+                self.output = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use ggez::graphics::Rect;
+
+use crate::database::{PgnDatabase, SortKey};
+use crate::graphics::{START_X, START_Y};
+
+pub const PICKER_WIDTH: f32 = 1100.0;
+pub const PICKER_HEIGHT: f32 = 600.0;
+pub const ROW_HEIGHT: f32 = 22.0;
+pub const HEADER_HEIGHT: f32 = 32.0;
+pub const VISIBLE_ROWS: usize = ((PICKER_HEIGHT - HEADER_HEIGHT - 24.0) / ROW_HEIGHT) as usize;
+
+/// The "#" column, clicking it goes back to file order.
+pub const NUMBER_COLUMN: (f32, f32) = (10.0, 60.0);
+
+/// Sortable columns with their x offset inside the picker and width.
+pub const COLUMNS: [(SortKey, f32, f32); 6] = [
+    (SortKey::White, 70.0, 260.0),
+    (SortKey::Black, 330.0, 260.0),
+    (SortKey::Result, 590.0, 80.0),
+    (SortKey::Date, 670.0, 120.0),
+    (SortKey::Eco, 790.0, 70.0),
+    (SortKey::Rating, 860.0, 160.0),
+];
+
+/// The list of games in a PGN database, shown over the board. `order` holds game indices in
+/// display order, `sort_key` is `None` for file order.
+pub struct GamePicker {
+    pub open: bool,
+    pub sort_key: Option<SortKey>,
+    pub ascending: bool,
+    pub order: Vec<usize>,
+    pub scroll: usize,
+    pub hovered: Option<usize>,
+}
+
+impl GamePicker {
+    pub fn new(database: &PgnDatabase) -> Self {
+        GamePicker {
+            open: false,
+            sort_key: None,
+            ascending: true,
+            order: (0..database.len()).collect(),
+            scroll: 0,
+            hovered: None,
+        }
+    }
+
+    pub fn rect() -> Rect {
+        Rect::new(START_X, START_Y, PICKER_WIDTH, PICKER_HEIGHT)
+    }
+
+    pub fn row_rect(row: usize) -> Rect {
+        Rect::new(
+            START_X,
+            START_Y + HEADER_HEIGHT + row as f32 * ROW_HEIGHT,
+            PICKER_WIDTH,
+            ROW_HEIGHT,
+        )
+    }
+
+    /// Opens the picker scrolled so `current_game` is in view, or closes it.
+    pub fn toggle(&mut self, current_game: usize) {
+        self.open = !self.open;
+        self.hovered = None;
+
+        if self.open {
+            self.scroll_to(current_game);
+        }
+    }
+
+    /// Sorts by `key`. Sorting by the same key again flips the direction; ratings start with
+    /// the strongest games.
+    pub fn sort_by(&mut self, key: Option<SortKey>, database: &PgnDatabase) {
+        if key.is_some() && key == self.sort_key {
+            self.ascending = !self.ascending;
+        } else {
+            self.sort_key = key;
+            self.ascending = key != Some(SortKey::Rating);
+        }
+
+        self.order = match key {
+            Some(key) => database.sorted(key, self.ascending),
+            None => (0..database.len()).collect(),
+        };
+        self.scroll = 0;
+    }
+
+    pub fn scroll_by(&mut self, rows: isize) {
+        let max_scroll = self.order.len().saturating_sub(VISIBLE_ROWS);
+        self.scroll = self.scroll.saturating_add_signed(rows).min(max_scroll);
+    }
+
+    fn scroll_to(&mut self, game: usize) {
+        let Some(position) = self.order.iter().position(|&index| index == game) else {
+            return;
+        };
+
+        if position < self.scroll || position >= self.scroll + VISIBLE_ROWS {
+            self.scroll = 0;
+            self.scroll_by(position as isize - VISIBLE_ROWS as isize / 2);
+        }
+    }
+
+    /// Game indices of the rows currently in view.
+    pub fn visible(&self) -> &[usize] {
+        let end = (self.scroll + VISIBLE_ROWS).min(self.order.len());
+        &self.order[self.scroll..end]
+    }
+
+    /// The game shown at `point`, if any.
+    pub fn game_at(&self, point: [f32; 2]) -> Option<usize> {
+        self.row_at(point).map(|row| self.visible()[row])
+    }
+
+    /// The visible row at `point`, if any.
+    pub fn row_at(&self, point: [f32; 2]) -> Option<usize> {
+        (0..self.visible().len()).find(|&row| Self::row_rect(row).contains(point))
+    }
+
+    /// The column header at `point`: `Some(None)` for the "#" column, `Some(Some(key))` for
+    /// a sortable column.
+    pub fn header_at(&self, point: [f32; 2]) -> Option<Option<SortKey>> {
+        let [x, y] = point;
+        if !(START_Y..START_Y + HEADER_HEIGHT).contains(&y) {
+            return None;
+        }
+
+        let x = x - START_X;
+        let (number_x, number_width) = NUMBER_COLUMN;
+        if (number_x..number_x + number_width).contains(&x) {
+            return Some(None);
+        }
+
+        COLUMNS.iter()
+            .find(|(_, column_x, width)| (*column_x..column_x + width).contains(&x))
+            .map(|(key, _, _)| Some(*key))
+    }
+
+    /// The game before or after `current_game` in display order.
+    pub fn step(&self, current_game: usize, step: isize) -> Option<usize> {
+        let position = self.order.iter().position(|&index| index == current_game)?;
+        let next = position.checked_add_signed(step)?;
+        self.order.get(next).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: usize = 50;
+
+    /// Games named in reverse file order, with ratings that are all different.
+    fn database() -> PgnDatabase {
+        let pgn: String = (0..GAMES)
+            .map(|game| format!(
+                "[White \"Player {:02}\"]\n[Black \"Opponent\"]\n[WhiteElo \"{}\"]\n\n1. e4 *\n\n",
+                GAMES - 1 - game,
+                2000 + game * 13 % GAMES,
+            ))
+            .collect();
+        PgnDatabase::index(pgn).unwrap()
+    }
+
+    fn header_point(x: f32) -> [f32; 2] {
+        [START_X + x, START_Y + HEADER_HEIGHT / 2.0]
+    }
+
+    #[test]
+    fn sorting() {
+        let database = database();
+        let mut picker = GamePicker::new(&database);
+        let ratings = |picker: &GamePicker| -> Vec<u32> {
+            picker.order.iter().map(|&game| database.games()[game].rating().unwrap()).collect()
+        };
+
+        picker.sort_by(Some(SortKey::Rating), &database);
+        assert!(!picker.ascending);
+        assert!(ratings(&picker).is_sorted_by(|a, b| a > b));
+
+        picker.sort_by(Some(SortKey::Rating), &database);
+        assert!(picker.ascending);
+        assert!(ratings(&picker).is_sorted());
+
+        picker.sort_by(Some(SortKey::White), &database);
+        assert!(picker.ascending);
+        assert_eq!(picker.order, (0..GAMES).rev().collect::<Vec<_>>());
+
+        picker.scroll_by(10);
+        picker.sort_by(Some(SortKey::White), &database);
+        assert!(!picker.ascending);
+        assert_eq!(picker.order, (0..GAMES).collect::<Vec<_>>());
+        assert_eq!(picker.scroll, 0);
+
+        picker.sort_by(None, &database);
+        assert_eq!(picker.sort_key, None);
+        assert_eq!(picker.order, (0..GAMES).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn scrolling_stays_within_the_list() {
+        let database = database();
+        let mut picker = GamePicker::new(&database);
+        let max_scroll = GAMES - VISIBLE_ROWS;
+
+        picker.scroll_by(-3);
+        assert_eq!(picker.scroll, 0);
+        picker.scroll_by(5);
+        assert_eq!(picker.scroll, 5);
+        picker.scroll_by(1000);
+        assert_eq!(picker.scroll, max_scroll);
+        assert_eq!(picker.visible().len(), VISIBLE_ROWS);
+        assert_eq!(picker.visible().last(), Some(&(GAMES - 1)));
+        picker.scroll_by(-1000);
+        assert_eq!(picker.scroll, 0);
+
+        let short = PgnDatabase::index("1. e4 *\n\n1. d4 *\n".to_string()).unwrap();
+        let mut picker = GamePicker::new(&short);
+        picker.scroll_by(3);
+        assert_eq!(picker.scroll, 0);
+        assert_eq!(picker.visible(), [0, 1]);
+    }
+
+    #[test]
+    fn opening_centres_the_current_game() {
+        let database = database();
+
+        let mut picker = GamePicker::new(&database);
+        picker.toggle(30);
+        assert!(picker.open);
+        assert_eq!(picker.scroll, 30 - VISIBLE_ROWS / 2);
+
+        // Already in view, so the list doesn't move.
+        picker.toggle(30);
+        assert!(!picker.open);
+        picker.toggle(20);
+        assert_eq!(picker.scroll, 30 - VISIBLE_ROWS / 2);
+
+        // Near either end the list can't scroll far enough to centre it.
+        let mut picker = GamePicker::new(&database);
+        picker.toggle(GAMES - 1);
+        assert_eq!(picker.scroll, GAMES - VISIBLE_ROWS);
+        picker.toggle(0);
+        picker.toggle(0);
+        assert_eq!(picker.scroll, 0);
+    }
+
+    #[test]
+    fn headers() {
+        let picker = GamePicker::new(&database());
+
+        assert_eq!(picker.header_at(header_point(5.0)), None);
+        assert_eq!(picker.header_at(header_point(10.0)), Some(None));
+        assert_eq!(picker.header_at(header_point(69.9)), Some(None));
+        assert_eq!(picker.header_at(header_point(70.0)), Some(Some(SortKey::White)));
+        assert_eq!(picker.header_at(header_point(329.9)), Some(Some(SortKey::White)));
+        assert_eq!(picker.header_at(header_point(330.0)), Some(Some(SortKey::Black)));
+        assert_eq!(picker.header_at(header_point(1019.9)), Some(Some(SortKey::Rating)));
+        assert_eq!(picker.header_at(header_point(1020.0)), None);
+
+        // Above the header and on the first row.
+        assert_eq!(picker.header_at([START_X + 100.0, START_Y - 1.0]), None);
+        assert_eq!(picker.header_at([START_X + 100.0, START_Y + HEADER_HEIGHT]), None);
+    }
+
+    #[test]
+    fn rows() {
+        let database = database();
+        let mut picker = GamePicker::new(&database);
+        picker.scroll_by(10);
+        let row_point = |row: f32| [START_X + 100.0, START_Y + HEADER_HEIGHT + row * ROW_HEIGHT];
+
+        assert_eq!(picker.game_at(row_point(0.0)), Some(10));
+        assert_eq!(picker.game_at(row_point(2.5)), Some(12));
+        assert_eq!(picker.game_at(row_point(-0.5)), None);
+        assert_eq!(picker.game_at(row_point(VISIBLE_ROWS as f32 + 0.5)), None);
+    }
+
+    #[test]
+    fn stepping_through_the_order() {
+        let database = database();
+        let mut picker = GamePicker::new(&database);
+
+        assert_eq!(picker.step(0, -1), None);
+        assert_eq!(picker.step(0, 1), Some(1));
+        assert_eq!(picker.step(GAMES - 1, 1), None);
+
+        // Steps follow the sorted order, not the file's.
+        picker.sort_by(Some(SortKey::White), &database);
+        assert_eq!(picker.step(GAMES - 1, -1), None);
+        assert_eq!(picker.step(GAMES - 1, 1), Some(GAMES - 2));
+        assert_eq!(picker.step(0, 1), None);
+        assert_eq!(picker.step(0, -1), Some(1));
+    }
+}
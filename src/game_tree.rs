@@ -0,0 +1,120 @@
+use pgn_reader::Nag;
+use shakmaty::Move;
+
+pub type NodeId = usize;
+
+pub const ROOT: NodeId = 0;
+
+/// A move in the game tree. The first child continues the line this node is on, later
+/// children are variations branching off at this point.
+#[derive(Clone, Debug, Default)]
+pub struct GameNode {
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub ply: usize,
+    pub mv: Option<Move>,
+    pub san: String,
+    pub nags: Vec<Nag>,
+    pub starting_comments: Vec<String>,
+    pub comments: Vec<String>,
+}
+
+/// The mainline and all nested variations of a game. Node `ROOT` is the starting position and
+/// has no move; its comments are the game comment before the first move.
+#[derive(Clone, Debug)]
+pub struct GameTree {
+    nodes: Vec<GameNode>,
+}
+
+impl Default for GameTree {
+    fn default() -> Self {
+        GameTree { nodes: vec![GameNode::default()] }
+    }
+}
+
+impl GameTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node(&self, id: NodeId) -> &GameNode {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut GameNode {
+        &mut self.nodes[id]
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, mv: Move, san: String) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(GameNode {
+            parent: Some(parent),
+            ply: self.nodes[parent].ply + 1,
+            mv: Some(mv),
+            san,
+            ..GameNode::default()
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// The nodes from the first move down to `id`, not including the root.
+    pub fn path_to(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut node = id;
+
+        while let Some(parent) = self.nodes[node].parent {
+            path.push(node);
+            node = parent;
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Number of moves played to reach `id`.
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.nodes[id].ply
+    }
+
+    /// The nodes following `id` along first children, not including `id` itself.
+    pub fn mainline_from(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.nodes[id].children.first().copied(), |&node| {
+            self.nodes[node].children.first().copied()
+        })
+    }
+
+    pub fn is_mainline(&self, id: NodeId) -> bool {
+        let mut node = id;
+
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].children.first() != Some(&node) {
+                return false;
+            }
+            node = parent;
+        }
+
+        true
+    }
+
+    /// Alternatives to the move at `id`, including `id` itself.
+    pub fn siblings(&self, id: NodeId) -> &[NodeId] {
+        match self.nodes[id].parent {
+            Some(parent) => &self.nodes[parent].children,
+            None => std::slice::from_ref(&ROOT),
+        }
+    }
+}
+
+/// The move suffix for the common annotation NAGs, `$n` for everything else.
+pub fn nag_symbol(nag: &Nag) -> String {
+    match nag.0 {
+        1 => "!".to_string(),
+        2 => "?".to_string(),
+        3 => "!!".to_string(),
+        4 => "??".to_string(),
+        5 => "!?".to_string(),
+        6 => "?!".to_string(),
+        _ => format!(" {}", nag),
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::iter;
+
+use gif::{Encoder, Frame, Repeat};
+use shakmaty::{Position, Square};
+use tiny_skia::{Paint, Pixmap, Rect, Transform};
+
+use crate::analyze::parse_eval_command;
+use crate::cli::GifOptions;
+use crate::config::Config;
+use crate::engine::Evaluation;
+use crate::game_tree::{GameTree, NodeId, ROOT};
+use crate::graphics::{evaluation_bar_portion, EVAL_BAR_BLACK, EVAL_BAR_WHITE, LIGHT_SQUARE};
+use crate::pgn::ChessGamePlayer;
+use crate::render::{draw_diagram, draw_text, load_game, move_squares, text_width, Diagram, PieceImages, DEFAULT_SIZE};
+
+pub const DEFAULT_DELAY_MS: u64 = 1000;
+
+/// How long the title frame is shown.
+const TITLE_DELAY_MS: u64 = 2500;
+
+/// The final position stays up this many times the per-move delay before the GIF loops.
+const FINAL_POSITION_DELAYS: u64 = 3;
+
+/// NeuQuant sampling speed for building each frame's palette, 1 (best) to 30 (fastest). The
+/// board has few colours, so a fast pass loses nothing visible.
+const QUANTIZE_SPEED: i32 = 10;
+
+const TITLE_BACKGROUND: (u8, u8, u8) = (48, 46, 43);
+
+/// Writes the mainline of a game as an animated GIF, one frame per position, optionally led by
+/// a title frame from the headers. The per-move delay comes from `--delay`, then `gif_delay`
+/// (milliseconds) in the config.
+pub fn run(options: &GifOptions, config: &Config) -> Result<(), String> {
+    let pgn_path = options.pgn_path.as_deref().ok_or("no PGN file given")?;
+    let game = load_game(pgn_path, options.game_index)?;
+    let pieces = PieceImages::load()?;
+
+    let delay_ms = options.delay_ms
+        .or_else(|| config.get("gif_delay").and_then(|delay| delay.parse().ok()))
+        .unwrap_or(DEFAULT_DELAY_MS);
+    let size = options.size.unwrap_or(DEFAULT_SIZE);
+    let bar_width = if options.eval_bar { (size / 16).max(8) } else { 0 };
+    let (width, height) = (size + bar_width, size);
+
+    let output_path = options.output_path.clone()
+        .unwrap_or_else(|| pgn_path.with_extension("gif"));
+    let describe_error = |err: gif::EncodingError| format!("could not write {}: {}", output_path.display(), err);
+    let file = File::create(&output_path)
+        .map_err(|err| format!("could not create {}: {}", output_path.display(), err))?;
+    let mut encoder = Encoder::new(BufWriter::new(file), width as u16, height as u16, &[]).map_err(describe_error)?;
+    encoder.set_repeat(Repeat::Infinite).map_err(describe_error)?;
+
+    let mut pixmap = Pixmap::new(width, height).ok_or("the image size must be above zero")?;
+
+    if !options.hide_title {
+        draw_title(&mut pixmap, &game);
+        encoder.write_frame(&gif_frame(&pixmap, TITLE_DELAY_MS)).map_err(describe_error)?;
+    }
+
+    let tree = game.tree();
+    let positions: Vec<NodeId> = iter::once(ROOT).chain(tree.mainline_from(ROOT)).collect();
+    let mut evaluation = None;
+
+    for (index, &node) in positions.iter().enumerate() {
+        // Positions without an `[%eval]` keep the last one so the bar doesn't jump back to 0.
+        evaluation = tree.node(node).comments.iter().find_map(|comment| parse_eval_command(comment)).or(evaluation);
+        let arrows = if options.arrows { node_arrows(tree, node) } else { Vec::new() };
+
+        let diagram = Diagram {
+            board: game.position_at(node).board(),
+            flipped: options.board_flipped,
+            highlight: tree.node(node).mv.as_ref().and_then(move_squares),
+            arrows: &arrows,
+            coordinates: true,
+        };
+
+        if options.eval_bar {
+            draw_evaluation_bar(&mut pixmap, evaluation.as_ref(), bar_width as f32, options.board_flipped);
+        }
+        draw_diagram(&mut pixmap, &pieces, &diagram, bar_width as f32, 0.0, size as f32);
+
+        let delay = if index + 1 == positions.len() { delay_ms * FINAL_POSITION_DELAYS } else { delay_ms };
+        encoder.write_frame(&gif_frame(&pixmap, delay)).map_err(describe_error)?;
+    }
+
+    eprintln!("Wrote {} positions to {}", positions.len(), output_path.display());
+    Ok(())
+}
+
+/// The arrows drawn on the position after the move at `node`: those in its comments' `[%cal]`
+/// commands, and one for each variation played instead of the next move, such as the better
+/// move `brilliant analyze` adds. The alternatives are shown before the move they replace, on
+/// the position they start from.
+fn node_arrows(tree: &GameTree, node: NodeId) -> Vec<(Square, Square)> {
+    let alternatives = tree.node(node).children.iter()
+        .skip(1)
+        .filter_map(|&alternative| tree.node(alternative).mv.as_ref().and_then(move_squares));
+    let commented = tree.node(node).comments.iter().flat_map(|comment| parse_arrow_command(comment));
+
+    alternatives.chain(commented).collect()
+}
+
+/// The arrows of a `[%cal Ge2e4,Rd7d5]` command. Their colours are ignored; every arrow is drawn
+/// like the viewer's.
+fn parse_arrow_command(comment: &str) -> Vec<(Square, Square)> {
+    let Some(start) = comment.find("[%cal").map(|start| start + "[%cal".len()) else {
+        return Vec::new();
+    };
+    let end = comment[start..].find(']').map_or(comment.len(), |end| start + end);
+
+    comment[start..end].split(',')
+        .filter_map(|arrow| {
+            let arrow = arrow.trim();
+            let from = arrow.get(1..3)?.parse::<Square>().ok()?;
+            let to = arrow.get(3..5)?.parse::<Square>().ok()?;
+            Some((from, to))
+        })
+        .collect()
+}
+
+/// Draws the evaluation bar down the left edge, White's share at the bottom unless the board is
+/// seen from Black's side.
+fn draw_evaluation_bar(pixmap: &mut Pixmap, evaluation: Option<&Evaluation>, width: f32, flipped: bool) {
+    let height = pixmap.height() as f32;
+    let white_height = height * evaluation_bar_portion(evaluation);
+    let (top_color, top_height) = if flipped {
+        (EVAL_BAR_WHITE, white_height)
+    } else {
+        (EVAL_BAR_BLACK, height - white_height)
+    };
+    let bottom_color = if flipped { EVAL_BAR_BLACK } else { EVAL_BAR_WHITE };
+
+    fill_rect(pixmap, 0.0, 0.0, width, height, bottom_color);
+    fill_rect(pixmap, 0.0, 0.0, width, top_height, top_color);
+}
+
+/// The players with their ratings, the event and date, and the result, centred on a dark
+/// background. Headers left as "?" are skipped.
+fn draw_title(pixmap: &mut Pixmap, game: &ChessGamePlayer) {
+    let (width, height) = (pixmap.width() as f32, pixmap.height() as f32);
+    fill_rect(pixmap, 0.0, 0.0, width, height, TITLE_BACKGROUND);
+
+    let header = |key: &str| {
+        game.get_headers().iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty() && *value != "?" && !value.contains("??"))
+    };
+    let player = |name: &str, elo: &str| match header(elo) {
+        Some(elo) => format!("{} ({})", header(name).unwrap_or(name), elo),
+        None => header(name).unwrap_or(name).to_string(),
+    };
+
+    let large = height * 0.07;
+    let small = height * 0.045;
+    let mut lines = vec![
+        (player("White", "WhiteElo"), large),
+        ("vs".to_string(), small),
+        (player("Black", "BlackElo"), large),
+    ];
+    let event = [header("Event"), header("Date")].into_iter().flatten().collect::<Vec<_>>().join(", ");
+    if !event.is_empty() {
+        lines.push((event, small));
+    }
+    if let Some(result) = header("Result").filter(|result| *result != "*") {
+        lines.push((result.to_string(), large));
+    }
+
+    let line_height = |text_size: f32| text_size * 1.6;
+    let total_height: f32 = lines.iter().map(|&(_, text_size)| line_height(text_size)).sum();
+    let mut y = (height - total_height) / 2.0;
+
+    for (text, text_size) in lines {
+        // Long names are shrunk to fit the frame rather than cut off.
+        let text_size = text_size.min(text_size * width * 0.9 / text_width(&text, text_size).max(1.0));
+        y += line_height(text_size);
+        let x = (width - text_width(&text, text_size)) / 2.0;
+        draw_text(pixmap, &text, x, y - text_size * 0.5, text_size, LIGHT_SQUARE);
+    }
+}
+
+fn fill_rect(pixmap: &mut Pixmap, x: f32, y: f32, width: f32, height: f32, color: (u8, u8, u8)) {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.0, color.1, color.2, 255);
+
+    if let Some(rect) = Rect::from_xywh(x, y, width, height) {
+        pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+}
+
+/// Every frame is drawn fully opaque, so the premultiplied pixels are plain RGBA.
+fn gif_frame(pixmap: &Pixmap, delay_ms: u64) -> Frame<'static> {
+    let mut pixels = pixmap.data().to_vec();
+    let mut frame = Frame::from_rgba_speed(pixmap.width() as u16, pixmap.height() as u16, &mut pixels, QUANTIZE_SPEED);
+    frame.delay = (delay_ms / 10).min(u16::MAX as u64) as u16;
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::ChessBoard;
+
+    #[test]
+    fn alternatives_are_drawn_before_the_move_they_replace() {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn("{[%cal Ge2e4]} 1. e4 e5 (1... c5) (1... e6) 2. Nf3 {[%cal Rd2d4,Gf1c4]} *", 0).unwrap();
+        let tree = game.tree();
+        let mainline: Vec<NodeId> = tree.mainline_from(ROOT).collect();
+        let square = |name: &str| name.parse::<Square>().unwrap();
+
+        assert_eq!(node_arrows(tree, ROOT), [(square("e2"), square("e4"))]);
+        assert_eq!(node_arrows(tree, mainline[0]), [(square("c7"), square("c5")), (square("e7"), square("e6"))]);
+        assert_eq!(node_arrows(tree, mainline[1]), []);
+        assert_eq!(node_arrows(tree, mainline[2]), [(square("d2"), square("d4")), (square("f1"), square("c4"))]);
+    }
+
+    #[test]
+    fn arrow_commands() {
+        let square = |name: &str| name.parse::<Square>().unwrap();
+
+        assert_eq!(parse_arrow_command("Good move [%cal Ge2e4, Yd7d5] [%clk 0:01:00]"), [
+            (square("e2"), square("e4")),
+            (square("d7"), square("d5")),
+        ]);
+        assert_eq!(parse_arrow_command("[%csl Ge4]"), []);
+        assert_eq!(parse_arrow_command("[%cal Gz9e4,Ge2]"), []);
+    }
+}
@@ -1,8 +1,18 @@
+use crate::animation::PieceAnimation;
 use crate::board::{ChessBoard, PieceType};
+use crate::database::{GameEntry, SortKey};
+use crate::engine::{EngineLine, Evaluation, MATE_CENTIPAWNS};
+use crate::game_picker::{GamePicker, COLUMNS, NUMBER_COLUMN};
+use crate::game_tree::NodeId;
+use crate::move_input::{promotion_rects, square_rect, MoveInput};
+use crate::move_list::{MoveList, TokenKind};
+use crate::review::Classification;
+use crate::summary::{GameSummary, MoveStats, Phase};
 use ggez::{Context, GameResult};
 use ggez::graphics::{Color, DrawMode, DrawParam, Image, Mesh, Rect, Canvas, Text, TextFragment, Drawable};
 use std::collections::HashMap;
 use ggez::mint::Point2;
+use shakmaty::{Board, File, Rank, Role, Square};
 
 pub const BLACK_KING: &[u8] = include_bytes!("../resources/black-king.png");
 pub const BLACK_QUEEN: &[u8] = include_bytes!("../resources/black-queen.png");
@@ -17,8 +27,145 @@ pub const WHITE_BISHOP: &[u8] = include_bytes!("../resources/white-bishop.png");
 pub const WHITE_KNIGHT: &[u8] = include_bytes!("../resources/white-knight.png");
 pub const WHITE_PAWN: &[u8] = include_bytes!("../resources/white-pawn.png");
 
+/// The piece images by the name `piece_image_name` gives them.
+pub const PIECE_IMAGES: [(&str, &[u8]); 12] = [
+    ("black-king", BLACK_KING),
+    ("black-queen", BLACK_QUEEN),
+    ("black-rook", BLACK_ROOK),
+    ("black-bishop", BLACK_BISHOP),
+    ("black-knight", BLACK_KNIGHT),
+    ("black-pawn", BLACK_PAWN),
+    ("white-king", WHITE_KING),
+    ("white-queen", WHITE_QUEEN),
+    ("white-rook", WHITE_ROOK),
+    ("white-bishop", WHITE_BISHOP),
+    ("white-knight", WHITE_KNIGHT),
+    ("white-pawn", WHITE_PAWN),
+];
+
+pub const LIGHT_SQUARE: (u8, u8, u8) = (240, 217, 181);
+pub const DARK_SQUARE: (u8, u8, u8) = (181, 136, 99);
+pub const HIGHLIGHT_SQUARE: (u8, u8, u8) = (205, 210, 106);
+pub const ARROW_COLOR: (u8, u8, u8) = (255, 234, 74);
+pub const EVAL_BAR_WHITE: (u8, u8, u8) = (240, 217, 181);
+pub const EVAL_BAR_BLACK: (u8, u8, u8) = (64, 61, 57);
+
 pub const START_X: f32 = 100.0;
 pub const START_Y: f32 = 100.0;
+pub const PANEL_X: f32 = 720.0;
+pub const PANEL_WIDTH: f32 = 540.0;
+pub const PV_Y: f32 = 688.0;
+/// The PV preview board stands in for the move list while it's shown.
+pub const GHOST_BOARD_Y: f32 = MOVE_LIST_Y;
+pub const GHOST_GRID_SIZE: f32 = 32.0;
+pub const COMMENT_Y: f32 = 612.0;
+const ENGINE_LINES_Y: f32 = START_Y + 28.0;
+const ENGINE_LINE_HEIGHT: f32 = 40.0;
+/// As many engine lines as fit between the panel title and the move list.
+pub const MAX_ENGINE_LINES: u32 = ((MOVE_LIST_Y - ENGINE_LINES_Y) / ENGINE_LINE_HEIGHT) as u32;
+pub const MOVE_LIST_Y: f32 = 260.0;
+pub const MOVE_LIST_WIDTH: f32 = 520.0;
+pub const MOVE_LIST_HEIGHT: f32 = 340.0;
+pub const MOVE_LIST_SCALE: f32 = 16.0;
+pub const GRAPH_Y: f32 = 712.0;
+pub const GRAPH_WIDTH: f32 = 576.0;
+pub const GRAPH_HEIGHT: f32 = 44.0;
+pub const SLIDER_Y: f32 = 866.0;
+pub const SLIDER_WIDTH: f32 = 980.0;
+pub const SLIDER_HEIGHT: f32 = 12.0;
+const ROW_TEXT_HEIGHT: f32 = 18.0;
+
+// The side panel stacks the engine lines, the move list (or the PV preview) and the comments.
+const _: () = assert!(MAX_ENGINE_LINES > 0);
+const _: () = assert!(GHOST_BOARD_Y + GHOST_GRID_SIZE * 8.0 <= MOVE_LIST_Y + MOVE_LIST_HEIGHT);
+const _: () = assert!(MOVE_LIST_Y + MOVE_LIST_HEIGHT <= COMMENT_Y);
+
+/// Where the viewer is in the game: the ply, the length of the line being viewed, the last
+/// move with its annotations and the comments attached to it.
+pub struct MoveInfo<'a> {
+    pub current_move: usize,
+    pub total_moves: usize,
+    /// Move numbers of the last move played and of the end of the line, as in the notation.
+    pub current_turn: u32,
+    pub total_turns: u32,
+    pub move_text: Option<&'a str>,
+    pub in_variation: bool,
+    pub comments: &'a [&'a str],
+    pub move_list: &'a MoveList,
+    pub current_node: NodeId,
+    /// The review's verdict on the last move, shown on the square it went to.
+    pub badge: Option<(Square, Classification)>,
+    /// Positions analysed and to analyse while a review is running.
+    pub review_progress: Option<(usize, usize)>,
+}
+
+/// The game picker with the games it lists and the game currently loaded.
+pub struct PickerDisplay<'a> {
+    pub picker: &'a GamePicker,
+    pub games: &'a [GameEntry],
+    pub current_game: usize,
+}
+
+/// Everything `draw_ui` shows in one frame. `current_depth` is left out while the engine isn't
+/// analysing.
+pub struct FrameView<'a> {
+    pub board: &'a ChessBoard,
+    pub board_flipped: bool,
+    pub buttons: &'a [&'a Button],
+    pub game_info: &'a str,
+    pub move_info: MoveInfo<'a>,
+    pub arrows: &'a [(Point2<f32>, Point2<f32>)],
+    pub engine_lines: &'a [EngineLine],
+    pub evaluation: Option<&'a Evaluation>,
+    pub show_wdl: bool,
+    pub current_depth: Option<u8>,
+    pub principal_variation: PvDisplay<'a>,
+    pub eval_graph: Option<&'a EvalGraph>,
+    pub move_input: &'a MoveInput,
+    pub animation: Option<&'a PieceAnimation>,
+    pub overlays: Overlays<'a>,
+    pub debug_mode: bool,
+}
+
+/// Panels drawn over the rest of the window, at most one of each.
+pub struct Overlays<'a> {
+    pub game_picker: Option<PickerDisplay<'a>>,
+    pub prompt: Option<PromptDisplay<'a>>,
+    pub help: Option<&'a [(String, String)]>,
+    pub summary: Option<&'a GameSummary>,
+    pub toast: Option<(&'a str, ToastKind)>,
+}
+
+/// A one-line text prompt: what it asks for, how to answer and what has been typed.
+pub struct PromptDisplay<'a> {
+    pub label: &'a str,
+    pub hint: &'a str,
+    pub input: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastKind {
+    Error,
+    Info,
+}
+
+/// The reviewed evaluations of the mainline, drawn as a graph under the board.
+pub struct EvalGraph {
+    /// White's win chance, 0 to 100, at each ply from the start; `None` if not analysed yet.
+    pub points: Vec<Option<f64>>,
+    /// Plies whose move gets a marker.
+    pub marks: Vec<(usize, Classification)>,
+    /// The ply on the board, if it's on the mainline.
+    pub current: Option<usize>,
+}
+
+/// The best line in SAN as shown under the board. When one of its moves is hovered, `preview`
+/// holds the board after that move and the move's squares for the ghost board.
+pub struct PvDisplay<'a> {
+    pub tokens: &'a [String],
+    pub hovered: Option<usize>,
+    pub preview: Option<(&'a Board, Option<(Square, Square)>)>,
+}
 
 pub struct Button {
     pub rect: Rect,
@@ -43,18 +190,9 @@ impl Button {
 pub fn load_images(ctx: &mut Context) -> GameResult<HashMap<String, Image>> {
     let mut images = HashMap::new();
 
-    images.insert("black-king".to_string(), Image::from_bytes(ctx, BLACK_KING)?);
-    images.insert("black-queen".to_string(), Image::from_bytes(ctx, BLACK_QUEEN)?);
-    images.insert("black-rook".to_string(), Image::from_bytes(ctx, BLACK_ROOK)?);
-    images.insert("black-bishop".to_string(), Image::from_bytes(ctx, BLACK_BISHOP)?);
-    images.insert("black-knight".to_string(), Image::from_bytes(ctx, BLACK_KNIGHT)?);
-    images.insert("black-pawn".to_string(), Image::from_bytes(ctx, BLACK_PAWN)?);
-    images.insert("white-king".to_string(), Image::from_bytes(ctx, WHITE_KING)?);
-    images.insert("white-queen".to_string(), Image::from_bytes(ctx, WHITE_QUEEN)?);
-    images.insert("white-rook".to_string(), Image::from_bytes(ctx, WHITE_ROOK)?);
-    images.insert("white-bishop".to_string(), Image::from_bytes(ctx, WHITE_BISHOP)?);
-    images.insert("white-knight".to_string(), Image::from_bytes(ctx, WHITE_KNIGHT)?);
-    images.insert("white-pawn".to_string(), Image::from_bytes(ctx, WHITE_PAWN)?);
+    for (name, bytes) in PIECE_IMAGES {
+        images.insert(name.to_string(), Image::from_bytes(ctx, bytes)?);
+    }
 
     Ok(images)
 }
@@ -122,103 +260,780 @@ pub fn draw_button(canvas: &mut Canvas, ctx: &mut Context, button: &Button) -> G
 pub fn draw_info_text(
     canvas: &mut Canvas,
     game_info: &str,
-    current_move: usize,
-    total_moves: usize,
-    depth: u8,
+    move_info: &MoveInfo,
+    depth: Option<u8>,
 ) {
     let info_text = Text::new(TextFragment::from(format!("Game: {}", game_info)));
-    canvas.draw(&info_text, DrawParam::default().dest([100.0, 720.0]));
+    canvas.draw(&info_text, DrawParam::default().dest([100.0, 60.0]));
+
+    let mut move_text = format!("Turn: {}/{}", move_info.current_turn, move_info.total_turns);
+    if let Some(last_move) = move_info.move_text {
+        move_text.push_str(&format!("   {}", last_move));
+    }
+    if let Some((_, classification)) = move_info.badge {
+        move_text.push_str(&format!("   {}", classification.label()));
+    }
+    if move_info.in_variation {
+        move_text.push_str("   (variation)");
+    }
+    let move_info_text = Text::new(TextFragment::from(move_text));
+    canvas.draw(&move_info_text, DrawParam::default().dest([100.0, 764.0]));
+
+    let depth_text = match (move_info.review_progress, depth) {
+        (Some((done, total)), _) => format!("Reviewing: {}/{} positions", done, total),
+        (None, Some(depth)) => format!("Depth: {}", depth),
+        (None, None) => "Analysis off".to_string(),
+    };
+    let depth = Text::new(TextFragment::from(depth_text));
+    canvas.draw(&depth, DrawParam::default().dest([100.0, 786.0]));
+}
+
+pub fn draw_comments(canvas: &mut Canvas, comments: &[&str]) {
+    if comments.is_empty() {
+        return;
+    }
+
+    let mut text = Text::new(TextFragment::from(comments.join("\n"))
+        .color(Color::from_rgba(200, 230, 200, 255))
+        .scale(16.0));
+    text.set_bounds([PANEL_WIDTH, 90.0]);
+
+    canvas.draw(&text, DrawParam::default().dest([PANEL_X, COMMENT_Y]));
+}
+
+fn picker_cell(game: &GameEntry, key: SortKey) -> String {
+    match key {
+        SortKey::White => game.white().to_string(),
+        SortKey::Black => game.black().to_string(),
+        SortKey::Result => game.result().to_string(),
+        SortKey::Date => game.date().to_string(),
+        SortKey::Eco => game.eco().to_string(),
+        SortKey::Rating => match (game.white_elo(), game.black_elo()) {
+            (None, None) => String::new(),
+            (white, black) => format!(
+                "{} / {}",
+                white.map_or("?".to_string(), |elo| elo.to_string()),
+                black.map_or("?".to_string(), |elo| elo.to_string())
+            ),
+        },
+    }
+    .chars()
+    .take(30)
+    .collect()
+}
+
+/// Draws the list of games over the board. The current sort column is marked with an arrow,
+/// the loaded game is highlighted.
+pub fn draw_game_picker(ctx: &mut Context, canvas: &mut Canvas, display: &PickerDisplay) -> GameResult {
+    let picker = display.picker;
+    let area = GamePicker::rect();
+
+    let background = Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgba(30, 30, 30, 240))?;
+    canvas.draw(&background, DrawParam::default());
+
+    let header_color = Color::from_rgba(255, 234, 74, 255);
+    let sort_marker = if picker.ascending { " ^" } else { " v" };
+
+    let number_label = if picker.sort_key.is_none() { format!("#{}", sort_marker) } else { "#".to_string() };
+    let number_text = Text::new(TextFragment::from(number_label).color(header_color).scale(16.0));
+    canvas.draw(&number_text, DrawParam::default().dest([area.x + NUMBER_COLUMN.0, area.y + 8.0]));
+
+    for (key, column_x, _) in COLUMNS {
+        let mut label = key.label().to_string();
+        if picker.sort_key == Some(key) {
+            label.push_str(sort_marker);
+        }
+
+        let text = Text::new(TextFragment::from(label).color(header_color).scale(16.0));
+        canvas.draw(&text, DrawParam::default().dest([area.x + column_x, area.y + 8.0]));
+    }
+
+    for (row, &index) in picker.visible().iter().enumerate() {
+        let row_rect = GamePicker::row_rect(row);
+
+        let highlight = if index == display.current_game {
+            Some(Color::from_rgba(90, 80, 30, 255))
+        } else if picker.hovered == Some(row) {
+            Some(Color::from_rgba(60, 60, 60, 255))
+        } else {
+            None
+        };
+        if let Some(color) = highlight {
+            let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), row_rect, color)?;
+            canvas.draw(&mesh, DrawParam::default());
+        }
+
+        let game = &display.games[index];
+        let text_y = row_rect.y + 3.0;
+
+        let number = Text::new(TextFragment::from((index + 1).to_string()).scale(16.0));
+        canvas.draw(&number, DrawParam::default().dest([area.x + NUMBER_COLUMN.0, text_y]));
+
+        for (key, column_x, width) in COLUMNS {
+            let mut text = Text::new(TextFragment::from(picker_cell(game, key)).scale(16.0));
+            text.set_bounds([width - 8.0, ROW_TEXT_HEIGHT]);
+            canvas.draw(&text, DrawParam::default().dest([area.x + column_x, text_y]));
+        }
+    }
+
+    let footer = Text::new(TextFragment::from(format!(
+        "{} game(s). Click a column to sort, a game to open it.",
+        display.games.len()
+    )).color(Color::from_rgba(180, 180, 180, 255)).scale(14.0));
+    canvas.draw(&footer, DrawParam::default().dest([area.x + NUMBER_COLUMN.0, area.y + area.h - 20.0]));
+
+    Ok(())
+}
+
+pub fn move_list_rect() -> Rect {
+    Rect::new(PANEL_X, MOVE_LIST_Y, MOVE_LIST_WIDTH, MOVE_LIST_HEIGHT)
+}
+
+/// Width of `text` as drawn in the move list.
+pub fn move_list_text_width(ctx: &Context, text: &str) -> f32 {
+    Text::new(TextFragment::from(text).scale(MOVE_LIST_SCALE))
+        .dimensions(ctx)
+        .map_or(0.0, |rect| rect.w)
+}
+
+pub fn classification_color(classification: Classification) -> Color {
+    match classification {
+        Classification::Brilliant => Color::from_rgba(27, 172, 166, 255),
+        Classification::Best => Color::from_rgba(129, 182, 76, 255),
+        Classification::Excellent => Color::from_rgba(150, 188, 75, 255),
+        Classification::Good => Color::from_rgba(149, 175, 138, 255),
+        Classification::Inaccuracy => Color::from_rgba(247, 192, 69, 255),
+        Classification::Mistake => Color::from_rgba(230, 145, 44, 255),
+        Classification::Miss => Color::from_rgba(238, 108, 90, 255),
+        Classification::Blunder => Color::from_rgba(202, 52, 49, 255),
+    }
+}
+
+/// Draws a classification's symbol on a rounded badge filling `area`.
+fn draw_badge(ctx: &mut Context, canvas: &mut Canvas, area: Rect, classification: Classification, scale: f32) -> GameResult {
+    let background = Mesh::new_rounded_rectangle(ctx, DrawMode::fill(), area, area.h / 2.0, classification_color(classification))?;
+    canvas.draw(&background, DrawParam::default());
+
+    let symbol = Text::new(TextFragment::from(classification.symbol()).color(Color::WHITE).scale(scale));
+    let size = symbol.dimensions(ctx).unwrap_or_default();
+    canvas.draw(&symbol, DrawParam::default().dest([
+        area.x + (area.w - size.w) / 2.0,
+        area.y + (area.h - size.h) / 2.0,
+    ]));
+
+    Ok(())
+}
+
+/// Draws the notation panel, only the part scrolled into view. The current move is highlighted.
+pub fn draw_move_list(ctx: &mut Context, canvas: &mut Canvas, move_list: &MoveList, current: NodeId) -> GameResult {
+    let area = move_list_rect();
+
+    for token in move_list.placed() {
+        let y = token.rect.y - move_list.scroll;
+        if y < 0.0 || y + token.rect.h > area.h {
+            continue;
+        }
+
+        let x = area.x + token.rect.x;
+        if let TokenKind::Badge(classification) = token.kind {
+            let badge = Rect::new(x - 3.0, area.y + y + 2.0, token.rect.w + 6.0, token.rect.h - 4.0);
+            draw_badge(ctx, canvas, badge, classification, MOVE_LIST_SCALE - 3.0)?;
+            continue;
+        }
+
+        let color = match token.kind {
+            TokenKind::Move(node) if node == current => {
+                let highlight = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::fill(),
+                    Rect::new(x - 2.0, area.y + y, token.rect.w + 4.0, token.rect.h),
+                    Color::from_rgba(90, 80, 30, 255),
+                )?;
+                canvas.draw(&highlight, DrawParam::default());
+                Color::from_rgba(255, 234, 74, 255)
+            }
+            TokenKind::Move(_) | TokenKind::Badge(_) => Color::from_rgba(230, 230, 230, 255),
+            TokenKind::MoveNumber | TokenKind::Bracket => Color::from_rgba(150, 150, 150, 255),
+            TokenKind::Comment => Color::from_rgba(200, 230, 200, 255),
+        };
+
+        let text = Text::new(TextFragment::from(token.text.as_str()).color(color).scale(MOVE_LIST_SCALE));
+        canvas.draw(&text, DrawParam::default().dest([x, area.y + y + 2.0]));
+    }
+
+    Ok(())
+}
+
+/// The move slider under the buttons, with some slack above and below for easier grabbing.
+pub fn slider_rect() -> Rect {
+    Rect::new(START_X, SLIDER_Y - 6.0, SLIDER_WIDTH, SLIDER_HEIGHT + 12.0)
+}
+
+/// The ply the slider points to at screen position `x`.
+pub fn slider_ply(x: f32, total_moves: usize) -> usize {
+    let fraction = ((x - START_X) / SLIDER_WIDTH).clamp(0.0, 1.0);
+    (fraction * total_moves as f32).round() as usize
+}
+
+pub fn draw_move_slider(ctx: &mut Context, canvas: &mut Canvas, current_move: usize, total_moves: usize) -> GameResult {
+    let track = Mesh::new_rectangle(
+        ctx,
+        DrawMode::fill(),
+        Rect::new(START_X, SLIDER_Y, SLIDER_WIDTH, SLIDER_HEIGHT),
+        Color::from_rgba(70, 70, 70, 255),
+    )?;
+    canvas.draw(&track, DrawParam::default());
+
+    let fraction = if total_moves == 0 { 0.0 } else { current_move as f32 / total_moves as f32 };
+    if fraction > 0.0 {
+        let filled = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(START_X, SLIDER_Y, SLIDER_WIDTH * fraction, SLIDER_HEIGHT),
+            Color::from_rgba(150, 150, 150, 255),
+        )?;
+        canvas.draw(&filled, DrawParam::default());
+    }
+
+    let handle = Mesh::new_rectangle(
+        ctx,
+        DrawMode::fill(),
+        Rect::new(START_X + SLIDER_WIDTH * fraction - 3.0, SLIDER_Y - 4.0, 6.0, SLIDER_HEIGHT + 8.0),
+        Color::from_rgba(255, 234, 74, 255),
+    )?;
+    canvas.draw(&handle, DrawParam::default());
+
+    Ok(())
+}
+
+pub fn graph_rect() -> Rect {
+    Rect::new(START_X, GRAPH_Y, GRAPH_WIDTH, GRAPH_HEIGHT)
+}
+
+/// The ply of a graph with `plies` points at screen position `x`.
+pub fn graph_ply(x: f32, plies: usize) -> usize {
+    let fraction = ((x - START_X) / GRAPH_WIDTH).clamp(0.0, 1.0);
+    (fraction * plies.saturating_sub(1) as f32).round() as usize
+}
+
+/// Draws White's win chance over the game: the light area is White's share. Win chances keep
+/// big advantages and mates on the chart, at the top or bottom edge.
+pub fn draw_eval_graph(ctx: &mut Context, canvas: &mut Canvas, graph: &EvalGraph) -> GameResult {
+    let area = graph_rect();
+    let last = graph.points.len().saturating_sub(1).max(1) as f32;
+    let x_at = |ply: usize| area.x + area.w * ply as f32 / last;
+    let y_at = |chance: f64| area.y + area.h * (1.0 - chance as f32 / 100.0);
+
+    let background = Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgba(64, 61, 57, 255))?;
+    canvas.draw(&background, DrawParam::default());
+
+    let curve: Vec<[f32; 2]> = graph.points.iter()
+        .enumerate()
+        .filter_map(|(ply, chance)| chance.map(|chance| [x_at(ply), y_at(chance)]))
+        .collect();
+
+    if let (Some(start), Some(end)) = (curve.first(), curve.last()) {
+        if curve.len() > 1 {
+            let bottom = area.y + area.h;
+            let mut outline = vec![[start[0], bottom]];
+            outline.extend(curve.iter().copied());
+            outline.push([end[0], bottom]);
+
+            let white_area = Mesh::new_polygon(ctx, DrawMode::fill(), &outline, Color::from_rgba(240, 217, 181, 255))?;
+            canvas.draw(&white_area, DrawParam::default());
+        }
+    }
+
+    let middle = Mesh::new_rectangle(
+        ctx,
+        DrawMode::fill(),
+        Rect::new(area.x, area.y + area.h / 2.0 - 0.5, area.w, 1.0),
+        Color::from_rgba(128, 128, 128, 255),
+    )?;
+    canvas.draw(&middle, DrawParam::default());
+
+    if let Some(current) = graph.current {
+        let marker = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(x_at(current) - 1.0, area.y, 2.0, area.h),
+            Color::from_rgba(255, 234, 74, 255),
+        )?;
+        canvas.draw(&marker, DrawParam::default());
+    }
+
+    for &(ply, classification) in &graph.marks {
+        let Some(Some(chance)) = graph.points.get(ply) else {
+            continue;
+        };
+
+        let dot = Mesh::new_circle(ctx, DrawMode::fill(), [x_at(ply), y_at(*chance)], 4.0, 0.5, classification_color(classification))?;
+        canvas.draw(&dot, DrawParam::default());
+    }
+
+    Ok(())
+}
+
+/// Draws the keyboard shortcuts as pairs of keys and what they do.
+pub fn draw_help(ctx: &mut Context, canvas: &mut Canvas, shortcuts: &[(String, String)]) -> GameResult {
+    let area = Rect::new(340.0, 120.0, 600.0, 60.0 + shortcuts.len() as f32 * 24.0);
+    let background = Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgba(30, 30, 30, 240))?;
+    canvas.draw(&background, DrawParam::default());
+
+    let title = Text::new(TextFragment::from("Keyboard shortcuts").color(Color::from_rgba(255, 234, 74, 255)).scale(20.0));
+    canvas.draw(&title, DrawParam::default().dest([area.x + 20.0, area.y + 14.0]));
+
+    for (index, (keys, description)) in shortcuts.iter().enumerate() {
+        let y = area.y + 48.0 + index as f32 * 24.0;
+
+        let keys = Text::new(TextFragment::from(keys.as_str()).color(Color::from_rgba(200, 200, 200, 255)).scale(16.0));
+        canvas.draw(&keys, DrawParam::default().dest([area.x + 20.0, y]));
+
+        let description = Text::new(TextFragment::from(description.as_str()).scale(16.0));
+        canvas.draw(&description, DrawParam::default().dest([area.x + 220.0, y]));
+    }
+
+    Ok(())
+}
+
+/// "87.3% / 24 ACPL", or a dash when there are no moves.
+fn stats_text(stats: &MoveStats) -> String {
+    match (stats.accuracy(), stats.acpl()) {
+        (Some(accuracy), Some(acpl)) => format!("{:.1}% / {:.0} ACPL ({})", accuracy, acpl, stats.moves()),
+        _ => "-".to_string(),
+    }
+}
+
+/// Draws the per-player review summary: accuracy, average centipawn loss, how many moves got
+/// each classification and the numbers for each phase of the game.
+pub fn draw_summary(ctx: &mut Context, canvas: &mut Canvas, summary: &GameSummary) -> GameResult {
+    const ROW: f32 = 24.0;
+    let rows = 7 + Classification::ALL.len() + Phase::ALL.len();
+    let area = Rect::new(240.0, 80.0, 800.0, 70.0 + rows as f32 * ROW);
+    let background = Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgba(30, 30, 30, 240))?;
+    canvas.draw(&background, DrawParam::default());
+
+    let title = Text::new(TextFragment::from("Game summary").color(Color::from_rgba(255, 234, 74, 255)).scale(20.0));
+    canvas.draw(&title, DrawParam::default().dest([area.x + 20.0, area.y + 14.0]));
+
+    let reviewed = Text::new(TextFragment::from(format!("{} of {} moves reviewed", summary.reviewed_moves, summary.total_moves))
+        .color(Color::from_rgba(150, 150, 150, 255))
+        .scale(14.0));
+    canvas.draw(&reviewed, DrawParam::default().dest([area.x + 220.0, area.y + 18.0]));
+
+    let columns = [area.x + 20.0, area.x + 260.0, area.x + 530.0];
+    let mut y = area.y + 56.0;
+    let mut row = |canvas: &mut Canvas, label: &str, white: String, black: String, color: Color| {
+        for (x, text) in columns.iter().zip([label.to_string(), white, black]) {
+            let text = Text::new(TextFragment::from(text).color(color).scale(16.0));
+            canvas.draw(&text, DrawParam::default().dest([*x, y]));
+        }
+        y += ROW;
+    };
+
+    let heading = Color::from_rgba(200, 200, 200, 255);
+    let (white, black) = (&summary.white, &summary.black);
+
+    row(canvas, "", white.name.clone(), black.name.clone(), Color::from_rgba(255, 234, 74, 255));
+    row(canvas, "Accuracy", percentage(white.overall.accuracy()), percentage(black.overall.accuracy()), Color::WHITE);
+    row(canvas, "Average centipawn loss", rounded(white.overall.acpl()), rounded(black.overall.acpl()), Color::WHITE);
+    row(canvas, "", String::new(), String::new(), Color::WHITE);
+
+    for classification in Classification::ALL {
+        row(
+            canvas,
+            classification.label(),
+            white.count(classification).to_string(),
+            black.count(classification).to_string(),
+            classification_color(classification),
+        );
+    }
+
+    row(canvas, "", String::new(), String::new(), Color::WHITE);
+    row(canvas, "Phase", "Accuracy / ACPL (moves)".to_string(), String::new(), heading);
+    for phase in Phase::ALL {
+        row(canvas, phase.label(), stats_text(white.phase(phase)), stats_text(black.phase(phase)), Color::WHITE);
+    }
+
+    Ok(())
+}
+
+fn percentage(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{:.1}%", value))
+}
+
+fn rounded(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{:.0}", value))
+}
 
-    let current_turn = (current_move + 1) / 2;
-    let total_turns = (total_moves + 1) / 2;
+/// Draws a one-line text prompt with a cursor at the end of the input.
+pub fn draw_prompt(ctx: &mut Context, canvas: &mut Canvas, prompt: &PromptDisplay) -> GameResult {
+    let area = Rect::new(240.0, 360.0, 800.0, 96.0);
+    let background = Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgba(30, 30, 30, 240))?;
+    canvas.draw(&background, DrawParam::default());
 
-    let move_text = format!("Turn: {}/{}", current_turn, total_turns);
-    let move_info = Text::new(TextFragment::from(move_text));
-    canvas.draw(&move_info, DrawParam::default().dest([100.0, 750.0]));
+    let label = Text::new(TextFragment::from(prompt.label).color(Color::from_rgba(255, 234, 74, 255)).scale(18.0));
+    canvas.draw(&label, DrawParam::default().dest([area.x + 20.0, area.y + 12.0]));
 
-    let depth = Text::new(TextFragment::from(format!("Depth: {}", depth)));
-    canvas.draw(&depth, DrawParam::default().dest([100.0, 780.0]));
+    let mut input = Text::new(TextFragment::from(format!("{}_", prompt.input)).scale(18.0));
+    input.set_bounds([area.w - 40.0, 24.0]);
+    canvas.draw(&input, DrawParam::default().dest([area.x + 20.0, area.y + 40.0]));
+
+    let hint = Text::new(TextFragment::from(prompt.hint)
+        .color(Color::from_rgba(150, 150, 150, 255))
+        .scale(14.0));
+    canvas.draw(&hint, DrawParam::default().dest([area.x + 20.0, area.y + 70.0]));
+
+    Ok(())
+}
+
+/// Draws a message box at the bottom of the window, used to report errors that would
+/// otherwise only reach the terminal and to confirm things done in the background.
+pub fn draw_toast(ctx: &mut Context, canvas: &mut Canvas, message: &str, kind: ToastKind) -> GameResult {
+    let mut text = Text::new(TextFragment::from(message).scale(16.0));
+    text.set_bounds([760.0, f32::INFINITY]);
+    let dimensions = text.dimensions(ctx).unwrap_or_default();
+
+    let area = Rect::new(
+        640.0 - (dimensions.w + 40.0) / 2.0,
+        740.0 - dimensions.h,
+        dimensions.w + 40.0,
+        dimensions.h + 24.0,
+    );
+    let color = match kind {
+        ToastKind::Error => Color::from_rgba(150, 30, 30, 235),
+        ToastKind::Info => Color::from_rgba(40, 100, 55, 235),
+    };
+    let background = Mesh::new_rounded_rectangle(ctx, DrawMode::fill(), area, 6.0, color)?;
+    canvas.draw(&background, DrawParam::default());
+    canvas.draw(&text, DrawParam::default().dest([area.x + 20.0, area.y + 12.0]));
+
+    Ok(())
 }
 
+/// Draws an engine arrow. `rank` is the line's position in the MultiPV list (0 for the best
+/// line); lower ranked lines get thinner, more transparent arrows.
 pub fn draw_arrow(
     ctx: &mut Context,
     canvas: &mut Canvas,
     start: Point2<f32>,
     end: Point2<f32>,
+    rank: usize,
 ) -> GameResult {
-    let color = Color::from_rgba(255, 234, 74, 200);
+    let prominence = 1.0 / (rank as f32 + 1.0);
+    let alpha = (80.0 + 120.0 * prominence) as u8;
+    let (red, green, blue) = ARROW_COLOR;
+    let color = Color::from_rgba(red, green, blue, alpha);
+
+    let size = 0.5 + 0.5 * prominence;
+    let outline = arrow_outline([start.x, start.y], [end.x, end.y], size);
+
+    let arrow = Mesh::new_polygon(
+        ctx,
+        DrawMode::fill(),
+        &outline,
+        color
+    )?;
+
+    canvas.draw(&arrow, DrawParam::default());
+    Ok(())
+}
 
-    let dx = end.x - start.x;
-    let dy = end.y - start.y;
-    let angle = dy.atan2(dx);
+/// The corners of an arrow from `start` to `end`, going round from the left of the shaft's
+/// base. At `size` 1 the shaft is 20 pixels wide and the head 40 wide and 30 long.
+pub fn arrow_outline(start: [f32; 2], end: [f32; 2], size: f32) -> [[f32; 2]; 7] {
+    let angle = (end[1] - start[1]).atan2(end[0] - start[0]);
 
-    let shaft_width = 20.0;
-    let head_width = 40.0;
-    let head_length = 30.0;
+    let shaft_width = 20.0 * size;
+    let head_width = 40.0 * size;
+    let head_length = 30.0 * size;
 
     let perpendicular_angle = angle + std::f32::consts::PI / 2.0;
-    let perpendicular_dx = perpendicular_angle.cos() * shaft_width / 2.0;
-    let perpendicular_dy = perpendicular_angle.sin() * shaft_width / 2.0;
+    let (perpendicular_x, perpendicular_y) = (perpendicular_angle.cos(), perpendicular_angle.sin());
+    let head_base = [end[0] - head_length * angle.cos(), end[1] - head_length * angle.sin()];
+    let offset = |point: [f32; 2], width: f32| [point[0] + perpendicular_x * width / 2.0, point[1] + perpendicular_y * width / 2.0];
+
+    [
+        offset(start, shaft_width),
+        offset(head_base, shaft_width),
+        offset(head_base, head_width),
+        end,
+        offset(head_base, -head_width),
+        offset(head_base, -shaft_width),
+        offset(start, -shaft_width),
+    ]
+}
+pub fn draw_engine_lines(canvas: &mut Canvas, lines: &[EngineLine]) {
+    let title = Text::new(TextFragment::from("Engine lines").scale(18.0));
+    canvas.draw(&title, DrawParam::default().dest([PANEL_X, START_Y]));
+
+    let mut y_pos = ENGINE_LINES_Y;
+
+    for (rank, line) in lines.iter().take(MAX_ENGINE_LINES as usize).enumerate() {
+        let color = if rank == 0 {
+            Color::from_rgba(255, 234, 74, 255)
+        } else {
+            Color::from_rgba(220, 220, 220, 255)
+        };
+
+        let mut text = Text::new(TextFragment::from(format!(
+            "{}. {} (d{})  {}",
+            line.multipv,
+            line.evaluation.label(),
+            line.depth,
+            line.pv_san.join(" ")
+        )).color(color).scale(16.0));
+        text.set_bounds([PANEL_WIDTH, ENGINE_LINE_HEIGHT]);
+
+        canvas.draw(&text, DrawParam::default().dest([PANEL_X, y_pos]));
+        y_pos += ENGINE_LINE_HEIGHT;
+    }
+}
 
-    let shaft_left = Point2 {
-        x: start.x + perpendicular_dx,
-        y: start.y + perpendicular_dy,
-    };
+/// Draws the principal variation on one line under the board, returning the screen area of
+/// each move so hovering can be hit tested. Moves that don't fit are left out.
+pub fn draw_principal_variation(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    tokens: &[String],
+    hovered: Option<usize>,
+    max_width: f32,
+) -> GameResult<Vec<Rect>> {
+    let mut rects = Vec::with_capacity(tokens.len());
+    let mut x_pos = START_X;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let color = if hovered == Some(index) {
+            Color::from_rgba(255, 234, 74, 255)
+        } else {
+            Color::from_rgba(220, 220, 220, 255)
+        };
+
+        let text = Text::new(TextFragment::from(token.as_str()).color(color).scale(16.0));
+        let dimensions = text.dimensions(ctx).unwrap_or_default();
+
+        if x_pos + dimensions.w > START_X + max_width {
+            break;
+        }
 
-    let shaft_right = Point2 {
-        x: start.x - perpendicular_dx,
-        y: start.y - perpendicular_dy,
-    };
+        canvas.draw(&text, DrawParam::default().dest([x_pos, PV_Y]));
+        rects.push(Rect::new(x_pos, PV_Y, dimensions.w, dimensions.h.max(16.0)));
+        x_pos += dimensions.w + 8.0;
+    }
 
-    let shaft_end_left = Point2 {
-        x: end.x - head_length * angle.cos() + perpendicular_dx,
-        y: end.y - head_length * angle.sin() + perpendicular_dy,
-    };
+    Ok(rects)
+}
 
-    let shaft_end_right = Point2 {
-        x: end.x - head_length * angle.cos() - perpendicular_dx,
-        y: end.y - head_length * angle.sin() - perpendicular_dy,
+pub fn piece_image_name(piece: shakmaty::Piece) -> String {
+    let colour = if piece.color.is_white() { "white" } else { "black" };
+    let role = match piece.role {
+        Role::King => "king",
+        Role::Queen => "queen",
+        Role::Rook => "rook",
+        Role::Bishop => "bishop",
+        Role::Knight => "knight",
+        Role::Pawn => "pawn",
     };
 
-    let head_perpendicular_dx = perpendicular_angle.cos() * head_width / 2.0;
-    let head_perpendicular_dy = perpendicular_angle.sin() * head_width / 2.0;
+    format!("{}-{}", colour, role)
+}
 
-    let head_base_left = Point2 {
-        x: end.x - head_length * angle.cos() + head_perpendicular_dx,
-        y: end.y - head_length * angle.sin() + head_perpendicular_dy,
-    };
+/// Draws `image` scaled to fit a square `size` wide with its top left at `x`, `y`.
+fn draw_piece(canvas: &mut Canvas, image: &Image, x: f32, y: f32, size: f32) {
+    draw_faded_piece(canvas, image, x, y, size, 1.0);
+}
 
-    let head_base_right = Point2 {
-        x: end.x - head_length * angle.cos() - head_perpendicular_dx,
-        y: end.y - head_length * angle.sin() - head_perpendicular_dy,
-    };
+/// Draws a piece with `opacity` from 0 (invisible) to 1.
+fn draw_faded_piece(canvas: &mut Canvas, image: &Image, x: f32, y: f32, size: f32, opacity: f32) {
+    let scale_factor = (size / image.width() as f32).min(size / image.height() as f32);
+    let x_offset = (size - image.width() as f32 * scale_factor) / 2.0;
+    let y_offset = (size - image.height() as f32 * scale_factor) / 2.0;
+
+    canvas.draw(
+        image,
+        DrawParam::default()
+            .dest([x + x_offset, y + y_offset])
+            .scale([scale_factor, scale_factor])
+            .color(Color::new(1.0, 1.0, 1.0, opacity))
+    );
+}
 
-    let arrow_points = &[
-        shaft_left,
-        shaft_end_left,
-        head_base_left,
-        end,
-        head_base_right,
-        shaft_end_right,
-        shaft_right
-    ];
+/// Draws the pieces of a move playing out: captured pieces fading, then the moving pieces
+/// sliding over them, a promoting pawn blending into its new piece.
+fn draw_piece_animation(
+    canvas: &mut Canvas,
+    images: &HashMap<String, Image>,
+    animation: &PieceAnimation,
+    grid_size: f32,
+    board_flipped: bool,
+) {
+    let progress = animation.progress();
 
-    let arrow = Mesh::new_polygon(
+    for fading in &animation.fading {
+        let rect = square_rect(fading.square, grid_size, board_flipped);
+        let opacity = if fading.appearing { progress } else { 1.0 - progress };
+        if let Some(image) = images.get(&piece_image_name(fading.piece)) {
+            draw_faded_piece(canvas, image, rect.x, rect.y, grid_size, opacity);
+        }
+    }
+
+    for moving in &animation.moving {
+        let from = square_rect(moving.from, grid_size, board_flipped);
+        let to = square_rect(moving.to, grid_size, board_flipped);
+        let x = from.x + (to.x - from.x) * progress;
+        let y = from.y + (to.y - from.y) * progress;
+
+        if moving.piece == moving.becomes {
+            if let Some(image) = images.get(&piece_image_name(moving.piece)) {
+                draw_piece(canvas, image, x, y, grid_size);
+            }
+        } else {
+            if let Some(image) = images.get(&piece_image_name(moving.piece)) {
+                draw_faded_piece(canvas, image, x, y, grid_size, 1.0 - progress);
+            }
+            if let Some(image) = images.get(&piece_image_name(moving.becomes)) {
+                draw_faded_piece(canvas, image, x, y, grid_size, progress);
+            }
+        }
+    }
+}
+
+/// Marks the squares the selected piece can move to: a dot on empty squares, a ring around
+/// pieces it can capture.
+fn draw_move_targets(ctx: &mut Context, canvas: &mut Canvas, board: &ChessBoard, move_input: &MoveInput, board_flipped: bool) -> GameResult {
+    let grid_size = board.grid_size;
+    let color = Color::from_rgba(20, 85, 30, 110);
+
+    for target in move_input.targets() {
+        let rect = square_rect(target, grid_size, board_flipped);
+        let center = [rect.x + grid_size / 2.0, rect.y + grid_size / 2.0];
+        let occupied = board.grid[7 - target.rank() as usize][target.file() as usize].piece.piece_type != PieceType::None;
+
+        let marker = if occupied {
+            Mesh::new_circle(ctx, DrawMode::stroke(6.0), center, grid_size / 2.0 - 3.0, 0.5, color)?
+        } else {
+            Mesh::new_circle(ctx, DrawMode::fill(), center, grid_size / 6.0, 0.5, color)?
+        };
+        canvas.draw(&marker, DrawParam::default());
+    }
+
+    Ok(())
+}
+
+/// Draws the piece being dragged under the mouse and the promotion chooser, if open.
+fn draw_move_input(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    images: &HashMap<String, Image>,
+    board: &ChessBoard,
+    move_input: &MoveInput,
+    board_flipped: bool,
+) -> GameResult {
+    let grid_size = board.grid_size;
+
+    if let (Some(square), Some(point)) = (move_input.dragged(), move_input.drag) {
+        let piece = &board.grid[7 - square.rank() as usize][square.file() as usize].piece;
+        if let Some(image) = images.get(&piece.filename) {
+            draw_piece(canvas, image, point[0] - grid_size / 2.0, point[1] - grid_size / 2.0, grid_size);
+        }
+    }
+
+    if let Some(promotion) = &move_input.promotion {
+        for (role, rect) in promotion_rects(promotion.square, grid_size, board_flipped) {
+            let background = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, Color::from_rgba(235, 235, 235, 255))?;
+            canvas.draw(&background, DrawParam::default());
+            let border = Mesh::new_rectangle(ctx, DrawMode::stroke(1.0), rect, Color::from_rgba(100, 100, 100, 255))?;
+            canvas.draw(&border, DrawParam::default());
+
+            let piece = shakmaty::Piece { color: promotion.color, role };
+            if let Some(image) = images.get(&piece_image_name(piece)) {
+                draw_piece(canvas, image, rect.x, rect.y, grid_size);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Draws a small board in place of the move list, used to preview positions along the PV.
+pub fn draw_ghost_board(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    images: &HashMap<String, Image>,
+    board: &Board,
+    highlight: Option<(Square, Square)>,
+    board_flipped: bool,
+) -> GameResult {
+    let grid_size = GHOST_GRID_SIZE;
+
+    for square in Square::ALL {
+        let file = square.file() as usize;
+        let rank = square.rank() as usize;
+        let (display_row, display_col) = if board_flipped {
+            (rank, 7 - file)
+        } else {
+            (7 - rank, file)
+        };
+
+        let x_pos = PANEL_X + display_col as f32 * grid_size;
+        let y_pos = GHOST_BOARD_Y + display_row as f32 * grid_size;
+
+        let is_highlighted = highlight.is_some_and(|(from, to)| square == from || square == to);
+        let color = match (is_highlighted, (file + rank) % 2 == 1) {
+            (true, _) => Color::from(HIGHLIGHT_SQUARE),
+            (false, true) => Color::from(LIGHT_SQUARE),
+            (false, false) => Color::from(DARK_SQUARE),
+        };
+
+        let square_mesh = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(x_pos, y_pos, grid_size, grid_size),
+            color,
+        )?;
+        canvas.draw(&square_mesh, DrawParam::default());
+
+        if let Some(image) = board.piece_at(square).and_then(|piece| images.get(&piece_image_name(piece))) {
+            let scale_factor = (grid_size / image.width() as f32).min(grid_size / image.height() as f32);
+            canvas.draw(
+                image,
+                DrawParam::default()
+                    .dest([x_pos, y_pos])
+                    .scale([scale_factor, scale_factor])
+            );
+        }
+    }
+
+    let border = Mesh::new_rectangle(
         ctx,
-        DrawMode::fill(),
-        arrow_points,
-        color
+        DrawMode::stroke(2.0),
+        Rect::new(PANEL_X, GHOST_BOARD_Y, grid_size * 8.0, grid_size * 8.0),
+        Color::from_rgba(100, 100, 100, 255),
     )?;
+    canvas.draw(&border, DrawParam::default());
 
-    canvas.draw(&arrow, DrawParam::default());
     Ok(())
 }
-pub fn draw_evaluation_bar(ctx: &mut Context, canvas: &mut Canvas, evaluation: f32) -> GameResult {
-    let eval_in_pawns = evaluation / 100.0;
-    let clamped_eval = eval_in_pawns.max(-10.0).min(10.0);
 
-    let normalized_eval = clamped_eval / 10.0;
+/// How much of the evaluation bar is White's, 0 to 1: linear up to ten pawns either way, and
+/// all of it for a mate.
+pub fn evaluation_bar_portion(evaluation: Option<&Evaluation>) -> f32 {
+    let centipawns = evaluation.map_or(0, Evaluation::white_centipawns);
+    let normalized_eval = if centipawns.abs() >= MATE_CENTIPAWNS {
+        centipawns.signum() as f32
+    } else {
+        (centipawns as f32 / 100.0).clamp(-10.0, 10.0) / 10.0
+    };
+
+    (normalized_eval + 1.0) / 2.0
+}
 
+pub fn draw_evaluation_bar(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    evaluation: Option<&Evaluation>,
+    show_wdl: bool,
+) -> GameResult {
     let bar_x = 10.0;
     let bar_width = 30.0;
     let bar_height = 400.0;
@@ -226,8 +1041,7 @@ pub fn draw_evaluation_bar(ctx: &mut Context, canvas: &mut Canvas, evaluation: f
 
     let middle_y = bar_y + bar_height / 2.0;
 
-    let white_portion = (normalized_eval + 1.0) / 2.0;
-    let white_height = bar_height * white_portion;
+    let white_height = bar_height * evaluation_bar_portion(evaluation);
     let black_height = bar_height - white_height;
 
     if black_height > 0.0 {
@@ -240,7 +1054,7 @@ pub fn draw_evaluation_bar(ctx: &mut Context, canvas: &mut Canvas, evaluation: f
                 bar_width,
                 black_height
             ),
-            Color::from_rgba(64, 61, 57, 255),
+            Color::from(EVAL_BAR_BLACK),
         )?;
         canvas.draw(&black_rect, DrawParam::default());
     }
@@ -255,7 +1069,7 @@ pub fn draw_evaluation_bar(ctx: &mut Context, canvas: &mut Canvas, evaluation: f
                 bar_width,
                 white_height
             ),
-            Color::from_rgba(240, 217, 181, 255),
+            Color::from(EVAL_BAR_WHITE),
         )?;
         canvas.draw(&white_rect, DrawParam::default());
     }
@@ -276,35 +1090,48 @@ pub fn draw_evaluation_bar(ctx: &mut Context, canvas: &mut Canvas, evaluation: f
     )?;
     canvas.draw(&middle_line, DrawParam::default());
 
-    let eval_text = if evaluation.abs() >= 1000.0 {
-        format!("#{}", if evaluation > 0.0 { "+" } else { "-" })
-    } else {
-        format!("{:+.2}", eval_in_pawns)
-    };
-
-    let text = Text::new(TextFragment::from(eval_text)
+    let label = evaluation.map_or_else(|| "+0.00".to_string(), Evaluation::label);
+    let text = Text::new(TextFragment::from(label)
         .color(Color::from_rgba(255, 255, 255, 255))
         .scale(16.0));
 
     canvas.draw(&text, DrawParam::default().dest([bar_x + bar_width + 5.0, middle_y - 8.0]));
 
+    if let Some(wdl) = evaluation.and_then(Evaluation::white_wdl).filter(|_| show_wdl) {
+        let total = (wdl.wins + wdl.draws + wdl.losses).max(1) as f32;
+        let percentages = [("W", wdl.wins), ("D", wdl.draws), ("L", wdl.losses)];
+
+        for (index, (label, permille)) in percentages.iter().enumerate() {
+            let text = Text::new(TextFragment::from(format!("{} {:.0}%", label, *permille as f32 * 100.0 / total))
+                .color(Color::from_rgba(255, 255, 255, 255))
+                .scale(14.0));
+            canvas.draw(&text, DrawParam::default().dest([bar_x, bar_y + bar_height + 10.0 + index as f32 * 18.0]));
+        }
+    }
+
     Ok(())
 }
 
-pub fn draw_ui(
-    ctx: &mut Context,
-    board: &ChessBoard,
-    images: &HashMap<String, Image>,
-    buttons: &[&Button],
-    game_info: &str,
-    current_move: usize,
-    total_moves: usize,
-    board_flipped: bool,
-    current_arrow: Option<(Point2<f32>, Point2<f32>)>,
-    debug_mode: bool,
-    evaluation: f32,
-    current_depth: u8
-) -> GameResult {
+pub fn draw_ui(ctx: &mut Context, images: &HashMap<String, Image>, view: &FrameView) -> GameResult<Vec<Rect>> {
+    let &FrameView {
+        board,
+        board_flipped,
+        buttons,
+        game_info,
+        ref move_info,
+        arrows,
+        engine_lines,
+        evaluation,
+        show_wdl,
+        current_depth,
+        ref principal_variation,
+        eval_graph,
+        move_input,
+        animation,
+        ref overlays,
+        debug_mode,
+    } = view;
+
     let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
     let grid_size = board.grid_size;
 
@@ -316,10 +1143,14 @@ pub fn draw_ui(
                 (row, col)
             };
 
-            let color = if (row + col) % 2 == 0 {
-                Color::from_rgba(240, 217, 181, 255)
+            let board_square = Square::from_coords(File::new(col as u32), Rank::new(7 - row as u32));
+
+            let color = if move_input.selected == Some(board_square) {
+                Color::from(HIGHLIGHT_SQUARE)
+            } else if (row + col) % 2 == 0 {
+                Color::from(LIGHT_SQUARE)
             } else {
-                Color::from_rgba(181, 136, 99, 255)
+                Color::from(DARK_SQUARE)
             };
 
             let square = Mesh::new_rectangle(
@@ -336,7 +1167,9 @@ pub fn draw_ui(
 
             canvas.draw(&square, DrawParam::default());
 
-            if board.grid[row][col].piece.piece_type != PieceType::None {
+            let dragged = move_input.dragged() == Some(board_square);
+            let animated = animation.is_some_and(|animation| animation.covers(board_square));
+            if board.grid[row][col].piece.piece_type != PieceType::None && !dragged && !animated {
                 let piece_name = &board.grid[row][col].piece.filename;
 
                 if let Some(image) = images.get(piece_name) {
@@ -377,18 +1210,75 @@ pub fn draw_ui(
         draw_board_labels(&mut canvas, grid_size, board_flipped)?;
     }
 
+    if let Some(animation) = animation {
+        draw_piece_animation(&mut canvas, images, animation, grid_size, board_flipped);
+    }
+
+    draw_move_targets(ctx, &mut canvas, board, move_input, board_flipped)?;
+
+    if let Some((square, classification)) = move_info.badge {
+        let rect = square_rect(square, grid_size, board_flipped);
+        let size = grid_size * 0.36;
+        let badge = Rect::new(rect.x + rect.w - size * 0.75, rect.y - size * 0.25, size, size);
+        draw_badge(ctx, &mut canvas, badge, classification, 15.0)?;
+    }
+
     for button in buttons {
         draw_button(&mut canvas, ctx, button)?;
     }
 
-    draw_info_text(&mut canvas, game_info, current_move, total_moves, current_depth);
+    draw_info_text(&mut canvas, game_info, move_info, current_depth);
+    draw_comments(&mut canvas, move_info.comments);
+    draw_move_slider(ctx, &mut canvas, move_info.current_move, move_info.total_moves)?;
+
+    if let Some(graph) = eval_graph {
+        draw_eval_graph(ctx, &mut canvas, graph)?;
+    }
+    if principal_variation.preview.is_none() {
+        draw_move_list(ctx, &mut canvas, move_info.move_list, move_info.current_node)?;
+    }
+
+    for (rank, (from, to)) in arrows.iter().enumerate().rev() {
+        draw_arrow(ctx, &mut canvas, *from, *to, rank)?;
+    }
+
+    draw_engine_lines(&mut canvas, engine_lines);
+
+    let pv_rects = draw_principal_variation(
+        ctx,
+        &mut canvas,
+        principal_variation.tokens,
+        principal_variation.hovered,
+        grid_size * 8.0,
+    )?;
+
+    if let Some((preview_board, highlight)) = principal_variation.preview {
+        draw_ghost_board(ctx, &mut canvas, images, preview_board, highlight, board_flipped)?;
+    }
+
+    draw_evaluation_bar(ctx, &mut canvas, evaluation, show_wdl)?;
+    draw_move_input(ctx, &mut canvas, images, board, move_input, board_flipped)?;
 
-    if let Some((from, to)) = current_arrow {
-        draw_arrow(ctx, &mut canvas, from, to)?;
+    if let Some(game_picker) = &overlays.game_picker {
+        draw_game_picker(ctx, &mut canvas, game_picker)?;
+    }
+
+    if let Some(prompt) = &overlays.prompt {
+        draw_prompt(ctx, &mut canvas, prompt)?;
+    }
+
+    if let Some(summary) = overlays.summary {
+        draw_summary(ctx, &mut canvas, summary)?;
+    }
+
+    if let Some(shortcuts) = overlays.help {
+        draw_help(ctx, &mut canvas, shortcuts)?;
+    }
+
+    if let Some((message, kind)) = overlays.toast {
+        draw_toast(ctx, &mut canvas, message, kind)?;
     }
 
-    draw_evaluation_bar(ctx, &mut canvas, evaluation)?;
-    
     canvas.finish(ctx)?;
-    Ok(())
+    Ok(pv_rects)
 }
\ No newline at end of file
@@ -0,0 +1,336 @@
+use std::fmt;
+
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
+
+use crate::config::Config;
+
+/// Something the viewer can do from the keyboard. Each action can be rebound in the config
+/// file with `key.<name> = <binding>[, <binding>...]`, e.g. `key.next_move = Right, L`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    PreviousMove,
+    NextMove,
+    Start,
+    End,
+    PreviousVariation,
+    NextVariation,
+    ExitVariation,
+    Flip,
+    ToggleAnalysis,
+    Review,
+    Summary,
+    OpenFile,
+    Save,
+    SaveAs,
+    Paste,
+    GameList,
+    PreviousGame,
+    NextGame,
+    Help,
+    Close,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::PreviousMove,
+        Action::NextMove,
+        Action::Start,
+        Action::End,
+        Action::PreviousVariation,
+        Action::NextVariation,
+        Action::ExitVariation,
+        Action::Flip,
+        Action::ToggleAnalysis,
+        Action::Review,
+        Action::Summary,
+        Action::OpenFile,
+        Action::Save,
+        Action::SaveAs,
+        Action::Paste,
+        Action::GameList,
+        Action::PreviousGame,
+        Action::NextGame,
+        Action::Help,
+        Action::Close,
+    ];
+
+    /// The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PreviousMove => "previous_move",
+            Action::NextMove => "next_move",
+            Action::Start => "start",
+            Action::End => "end",
+            Action::PreviousVariation => "previous_variation",
+            Action::NextVariation => "next_variation",
+            Action::ExitVariation => "exit_variation",
+            Action::Flip => "flip",
+            Action::ToggleAnalysis => "toggle_analysis",
+            Action::Review => "review",
+            Action::Summary => "summary",
+            Action::OpenFile => "open_file",
+            Action::Save => "save",
+            Action::SaveAs => "save_as",
+            Action::Paste => "paste",
+            Action::GameList => "game_list",
+            Action::PreviousGame => "previous_game",
+            Action::NextGame => "next_game",
+            Action::Help => "help",
+            Action::Close => "close",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::PreviousMove => "Previous move",
+            Action::NextMove => "Next move",
+            Action::Start => "Go to the start",
+            Action::End => "Go to the end",
+            Action::PreviousVariation => "Previous variation",
+            Action::NextVariation => "Next variation",
+            Action::ExitVariation => "Leave the variation",
+            Action::Flip => "Flip the board",
+            Action::ToggleAnalysis => "Start or stop analysis",
+            Action::Review => "Review the game, or stop the review",
+            Action::Summary => "Show accuracy per player",
+            Action::OpenFile => "Open a PGN file",
+            Action::Save => "Save the game to its file",
+            Action::SaveAs => "Save the game to another file",
+            Action::Paste => "Load a PGN or FEN from the clipboard",
+            Action::GameList => "Show the game list",
+            Action::PreviousGame => "Previous game",
+            Action::NextGame => "Next game",
+            Action::Help => "Show this help",
+            Action::Close => "Close overlay, or quit",
+        }
+    }
+
+    /// Whether holding the key down should repeat the action.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::PreviousMove | Action::NextMove | Action::PreviousVariation | Action::NextVariation
+        )
+    }
+
+    fn default_bindings(self) -> &'static str {
+        match self {
+            Action::PreviousMove => "Left",
+            Action::NextMove => "Right",
+            Action::Start => "Home",
+            Action::End => "End",
+            Action::PreviousVariation => "Up",
+            Action::NextVariation => "Down",
+            Action::ExitVariation => "Backspace",
+            Action::Flip => "F",
+            Action::ToggleAnalysis => "Space",
+            Action::Review => "R",
+            Action::Summary => "S",
+            Action::OpenFile => "Ctrl+O",
+            Action::Save => "Ctrl+S",
+            Action::SaveAs => "Ctrl+Shift+S",
+            Action::Paste => "Ctrl+V",
+            Action::GameList => "G",
+            Action::PreviousGame => "PageUp",
+            Action::NextGame => "PageDown",
+            Action::Help => "H, F1",
+            Action::Close => "Escape",
+        }
+    }
+}
+
+/// Key names accepted in bindings. The first name listed for a key is used when showing it.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Left", KeyCode::Left), ("Right", KeyCode::Right), ("Up", KeyCode::Up), ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home), ("End", KeyCode::End), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown),
+    ("Space", KeyCode::Space), ("Escape", KeyCode::Escape), ("Esc", KeyCode::Escape),
+    ("Backspace", KeyCode::Back), ("Enter", KeyCode::Return), ("Return", KeyCode::Return),
+    ("Tab", KeyCode::Tab), ("Insert", KeyCode::Insert), ("Delete", KeyCode::Delete),
+    ("F1", KeyCode::F1), ("F2", KeyCode::F2), ("F3", KeyCode::F3), ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5), ("F6", KeyCode::F6), ("F7", KeyCode::F7), ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9), ("F10", KeyCode::F10), ("F11", KeyCode::F11), ("F12", KeyCode::F12),
+    ("A", KeyCode::A), ("B", KeyCode::B), ("C", KeyCode::C), ("D", KeyCode::D), ("E", KeyCode::E),
+    ("F", KeyCode::F), ("G", KeyCode::G), ("H", KeyCode::H), ("I", KeyCode::I), ("J", KeyCode::J),
+    ("K", KeyCode::K), ("L", KeyCode::L), ("M", KeyCode::M), ("N", KeyCode::N), ("O", KeyCode::O),
+    ("P", KeyCode::P), ("Q", KeyCode::Q), ("R", KeyCode::R), ("S", KeyCode::S), ("T", KeyCode::T),
+    ("U", KeyCode::U), ("V", KeyCode::V), ("W", KeyCode::W), ("X", KeyCode::X), ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("0", KeyCode::Key0), ("1", KeyCode::Key1), ("2", KeyCode::Key2), ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4), ("5", KeyCode::Key5), ("6", KeyCode::Key6), ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8), ("9", KeyCode::Key9),
+];
+
+/// A key with the modifiers that must be held, e.g. `Ctrl+O`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub mods: KeyMods,
+}
+
+impl KeyBinding {
+    pub fn parse(binding: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let mut mods = KeyMods::empty();
+
+        for modifier in parts {
+            mods |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyMods::CTRL,
+                "shift" => KeyMods::SHIFT,
+                "alt" => KeyMods::ALT,
+                "super" | "cmd" | "logo" => KeyMods::LOGO,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, binding)),
+            };
+        }
+
+        let key = KEY_NAMES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key_name))
+            .map(|(_, key)| *key)
+            .ok_or_else(|| format!("unknown key '{}' in '{}'", key_name, binding))?;
+
+        Ok(KeyBinding { key, mods })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [(KeyMods::CTRL, "Ctrl"), (KeyMods::ALT, "Alt"), (KeyMods::SHIFT, "Shift"), (KeyMods::LOGO, "Super")] {
+            if self.mods.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        let name = KEY_NAMES.iter()
+            .find(|(_, key)| *key == self.key)
+            .map_or("?", |(name, _)| *name);
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Keymap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Keymap {
+    /// The default bindings with any `key.<action>` entries from the config file replacing
+    /// them. Invalid entries are reported and the defaults kept.
+    pub fn from_config(config: &Config) -> Self {
+        let mut bindings = Vec::new();
+
+        for action in Action::ALL {
+            let configured = config.get(&format!("key.{}", action.name()))
+                .map(|value| (value, parse_bindings(value)));
+
+            let action_bindings = match configured {
+                Some((_, Ok(parsed))) => parsed,
+                Some((value, Err(err))) => {
+                    eprintln!("Ignoring key binding '{}' for {}: {}", value, action.name(), err);
+                    parse_bindings(action.default_bindings()).unwrap_or_default()
+                }
+                None => parse_bindings(action.default_bindings()).unwrap_or_default(),
+            };
+
+            bindings.extend(action_bindings.into_iter().map(|binding| (binding, action)));
+        }
+
+        Keymap { bindings }
+    }
+
+    /// The action bound to a key press. Shift is ignored unless the binding asks for it, so
+    /// Caps Lock or a held Shift doesn't stop plain letter bindings from working.
+    pub fn action_for(&self, input: &KeyInput) -> Option<Action> {
+        let key = input.keycode?;
+        let exact = input.mods & (KeyMods::CTRL | KeyMods::ALT | KeyMods::LOGO | KeyMods::SHIFT);
+        let without_shift = exact - KeyMods::SHIFT;
+
+        self.bindings.iter()
+            .find(|(binding, _)| binding.key == key && binding.mods == exact)
+            .or_else(|| self.bindings.iter().find(|(binding, _)| binding.key == key && binding.mods == without_shift))
+            .map(|(_, action)| *action)
+    }
+
+    /// Every action with its keys, for the help overlay.
+    pub fn describe(&self) -> Vec<(String, String)> {
+        Action::ALL.iter()
+            .map(|&action| {
+                let keys: Vec<String> = self.bindings.iter()
+                    .filter(|(_, bound)| *bound == action)
+                    .map(|(binding, _)| binding.to_string())
+                    .collect();
+                let keys = if keys.is_empty() { "unbound".to_string() } else { keys.join(", ") };
+                (keys, action.description().to_string())
+            })
+            .collect()
+    }
+}
+
+/// Parses a comma separated list of bindings. An empty value unbinds the action.
+fn parse_bindings(value: &str) -> Result<Vec<KeyBinding>, String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|binding| !binding.is_empty())
+        .map(KeyBinding::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: KeyCode, mods: KeyMods) -> KeyInput {
+        KeyInput { scancode: 0, keycode: Some(key), mods }
+    }
+
+    #[test]
+    fn parsing_bindings() {
+        assert_eq!(KeyBinding::parse("F"), Ok(KeyBinding { key: KeyCode::F, mods: KeyMods::empty() }));
+        assert_eq!(KeyBinding::parse("pagedown"), Ok(KeyBinding { key: KeyCode::PageDown, mods: KeyMods::empty() }));
+        assert_eq!(KeyBinding::parse("Esc"), Ok(KeyBinding { key: KeyCode::Escape, mods: KeyMods::empty() }));
+        assert_eq!(KeyBinding::parse("Ctrl+O"), Ok(KeyBinding { key: KeyCode::O, mods: KeyMods::CTRL }));
+        assert_eq!(KeyBinding::parse("control + shift + s"), Ok(KeyBinding { key: KeyCode::S, mods: KeyMods::CTRL | KeyMods::SHIFT }));
+        assert_eq!(KeyBinding::parse("Alt+F4"), Ok(KeyBinding { key: KeyCode::F4, mods: KeyMods::ALT }));
+        for logo in ["Super+1", "Cmd+1", "Logo+1"] {
+            assert_eq!(KeyBinding::parse(logo), Ok(KeyBinding { key: KeyCode::Key1, mods: KeyMods::LOGO }), "{}", logo);
+        }
+
+        assert_eq!(KeyBinding::parse("Hyper+A"), Err("unknown modifier 'Hyper' in 'Hyper+A'".to_string()));
+        assert_eq!(KeyBinding::parse("Ctrl+Banana"), Err("unknown key 'Banana' in 'Ctrl+Banana'".to_string()));
+        for malformed in ["", "Ctrl+", "+", "Ctrl O", "A+Ctrl"] {
+            assert!(KeyBinding::parse(malformed).is_err(), "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn bindings_display_with_their_modifiers() {
+        assert_eq!(KeyBinding::parse("shift+ctrl+s").unwrap().to_string(), "Ctrl+Shift+S");
+        assert_eq!(KeyBinding::parse("return").unwrap().to_string(), "Enter");
+    }
+
+    #[test]
+    fn config_replaces_the_defaults() {
+        let keymap = Keymap::from_config(&Config::parse("key.next_move = L, Ctrl+N\nkey.flip =\nkey.help = Ctrl+"));
+
+        assert_eq!(keymap.action_for(&press(KeyCode::L, KeyMods::empty())), Some(Action::NextMove));
+        assert_eq!(keymap.action_for(&press(KeyCode::N, KeyMods::CTRL)), Some(Action::NextMove));
+        assert_eq!(keymap.action_for(&press(KeyCode::Right, KeyMods::empty())), None);
+        // An empty value unbinds the action and a bad one keeps the defaults.
+        assert_eq!(keymap.action_for(&press(KeyCode::F, KeyMods::empty())), None);
+        assert_eq!(keymap.action_for(&press(KeyCode::F1, KeyMods::empty())), Some(Action::Help));
+    }
+
+    #[test]
+    fn shift_is_ignored_unless_bound() {
+        let keymap = Keymap::from_config(&Config::default());
+
+        assert_eq!(keymap.action_for(&press(KeyCode::F, KeyMods::empty())), Some(Action::Flip));
+        assert_eq!(keymap.action_for(&press(KeyCode::F, KeyMods::SHIFT)), Some(Action::Flip));
+        assert_eq!(keymap.action_for(&press(KeyCode::S, KeyMods::CTRL)), Some(Action::Save));
+        assert_eq!(keymap.action_for(&press(KeyCode::S, KeyMods::CTRL | KeyMods::SHIFT)), Some(Action::SaveAs));
+        assert_eq!(keymap.action_for(&press(KeyCode::S, KeyMods::SHIFT)), Some(Action::Summary));
+
+        // Other modifiers have to match.
+        assert_eq!(keymap.action_for(&press(KeyCode::F, KeyMods::CTRL)), None);
+        assert_eq!(keymap.action_for(&press(KeyCode::O, KeyMods::empty())), None);
+        assert_eq!(keymap.action_for(&KeyInput { scancode: 0, keycode: None, mods: KeyMods::empty() }), None);
+    }
+}
@@ -0,0 +1,31 @@
+//! Everything but the window: the board and game model, PGN reading and writing, the engine,
+//! game review, the offscreen renderer and the drawing code. The `brilliant` binary puts them
+//! together into the viewer and the headless subcommands.
+
+pub mod board;
+pub mod pgn;
+pub mod graphics;
+pub mod engine;
+pub mod fen;
+pub mod cli;
+pub mod config;
+pub mod uci;
+pub mod fake_engine;
+pub mod game_tree;
+pub mod database;
+pub mod game_picker;
+pub mod move_list;
+pub mod keymap;
+pub mod event_loop;
+pub mod clipboard;
+pub mod move_input;
+pub mod review;
+pub mod summary;
+pub mod pgn_writer;
+pub mod analyze;
+pub mod render;
+pub mod gif_export;
+pub mod animation;
+
+/// Size of a board square in the viewer, in pixels. Drawings at other sizes scale from it.
+pub const GRID_SIZE: f32 = 72.0;
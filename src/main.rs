@@ -1,22 +1,37 @@
-mod board;
-mod pgn;
-mod graphics;
-mod engine;
-mod fen;
-
-use std::str::FromStr;
-use std::sync::mpsc;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use board::ChessBoard;
-use pgn::ChessGamePlayer;
-use graphics::{Button, load_images, draw_ui};
-use ggez::{Context, GameResult, ContextBuilder, event, GameError};
+use std::time::{Duration, Instant};
+use brilliant::board::ChessBoard;
+use brilliant::pgn::{ChessGamePlayer, PgnError};
+use brilliant::graphics::{Button, EvalGraph, FrameView, MoveInfo, Overlays, PickerDisplay, PromptDisplay, PvDisplay, ToastKind, load_images, draw_ui};
+use ggez::{Context, GameResult, ContextBuilder, GameError};
 use ggez::event::{EventHandler, MouseButton};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use ggez::graphics::Rect;
 use ggez::mint::Point2;
-use shakmaty::Square;
-use crate::engine::{EngineUpdate, StockfishEngine};
-use crate::fen::pgn_to_fen_at_move;
-use crate::pgn::square_to_board_coord;
+use shakmaty::{Color, Move, Position, Square};
+use shakmaty::uci::UciMove;
+use brilliant::{analyze, clipboard, cli, engine, event_loop, gif_export, graphics, render, GRID_SIZE};
+use brilliant::animation::BoardAnimation;
+use brilliant::cli::{Command, Options, ANALYZE_USAGE, GIF_USAGE, RENDER_USAGE, USAGE};
+use brilliant::config::Config;
+use brilliant::database::PgnDatabase;
+use brilliant::event_loop::WindowEventHandler;
+use brilliant::fen::fen_to_position;
+use brilliant::engine::{EngineLine, EngineUpdate, Evaluation, StockfishEngine};
+use brilliant::game_picker::GamePicker;
+use brilliant::game_tree::{NodeId, ROOT};
+use brilliant::keymap::{Action, Keymap};
+use brilliant::move_input::{square_at, MoveInput};
+use brilliant::move_list::{MoveList, LINE_HEIGHT};
+use brilliant::review::{win_chance, Classification, GameReview, ReviewJob, ReviewSettings, ReviewUpdate};
+use brilliant::summary::GameSummary;
+use brilliant::board::square_to_board_coord;
 
 const SAMPLE_PGN: &str = r#"[Event "Live Chess"]
 [Site "Chess.com"]
@@ -45,187 +60,562 @@ Kc5 g4 54. Kd5 h4 55. gxh4 Rh8 56. a8=Q Rxa8 57. Rxa8 g3 58. Kc5 g2 59. Ra1 Ke6
 60. Rg1 Kd7 61. Rxg2 Ke6 62. h5 Kf7 63. h6 Kf6 64. h7 Ke5 65. h8=Q+ Ke4 66. Qf8
 Kd3 67. Rf2 Ke3 68. Qf3# 1-0"#;
 
+const DEFAULT_ENGINE_LINES: u32 = 3;
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// What a path prompt is asking for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    Open,
+    SaveAs,
+}
+
+impl PromptKind {
+    fn label(self) -> &'static str {
+        match self {
+            PromptKind::Open => "Open PGN file:",
+            PromptKind::SaveAs => "Save PGN as:",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            PromptKind::Open => "Enter to open, Escape to cancel",
+            PromptKind::SaveAs => "Enter to save, Escape to cancel",
+        }
+    }
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
 struct GameState {
-    engine: StockfishEngine,
+    engine: Option<StockfishEngine>,
     images: std::collections::HashMap<String, ggez::graphics::Image>,
     game_player: ChessGamePlayer,
+    database: PgnDatabase,
+    game_index: usize,
+    game_picker: GamePicker,
+    move_list: MoveList,
+    move_input: MoveInput,
+    animation: BoardAnimation,
     prev_button: Button,
     next_button: Button,
     reset_button: Button,
     end_button: Button,
     flip_button: Button,
+    variation_button: Button,
+    exit_variation_button: Button,
+    games_button: Button,
+    prev_game_button: Button,
+    next_game_button: Button,
+    review_button: Button,
     board_flipped: bool,
     game_info: String,
-    current_arrow: Option<(Point2<f32>, Point2<f32>)>,
+    best_move: Option<UciMove>,
+    engine_lines: Vec<EngineLine>,
+    pv_hovered: Option<usize>,
+    pv_rects: Vec<Rect>,
+    slider_dragging: bool,
     engine_update_receiver: Option<mpsc::Receiver<EngineUpdate>>,
+    /// Counts the searches asked for, so one whose thread gets the engine late can tell it has
+    /// been replaced.
+    analysis_requests: Arc<AtomicUsize>,
     finding_best_move: bool,
-    evaluation: f32,
+    evaluation: Option<Evaluation>,
+    show_wdl: bool,
     debug_mode: bool,
     max_depth: u8,
     current_depth: u8,
+    analysis_enabled: bool,
+    keymap: Keymap,
+    show_help: bool,
+    show_summary: bool,
+    prompt: Option<Prompt>,
+    current_file: Option<PathBuf>,
+    toast: Option<(String, ToastKind, Instant)>,
+    review: GameReview,
+    review_job: Option<ReviewJob>,
+    review_settings: ReviewSettings,
 }
 
 impl GameState {
-    fn new(ctx: &mut Context) -> GameResult<GameState> {
+    fn new(
+        ctx: &mut Context,
+        database: PgnDatabase,
+        game_player: ChessGamePlayer,
+        options: &Options,
+        config: &Config,
+    ) -> GameResult<GameState> {
         let debug_mode = false;
-        let grid_size = 72.0;
+        let engine = Self::start_engine(options, config, debug_mode);
+        let images = load_images(ctx)?;
+
+        Ok(Self::from_parts(engine, images, database, game_player, options, config, debug_mode))
+    }
+
+    /// The viewer around an engine that's already running, if any, and the piece images.
+    fn from_parts(
+        engine: Option<StockfishEngine>,
+        images: std::collections::HashMap<String, ggez::graphics::Image>,
+        database: PgnDatabase,
+        game_player: ChessGamePlayer,
+        options: &Options,
+        config: &Config,
+        debug_mode: bool,
+    ) -> GameState {
         let max_depth = 50;
-        let board = ChessBoard::new(grid_size);
-        let context = ctx;
-        let engine = StockfishEngine::new(debug_mode);
-        let images = load_images(context)?;
 
         let prev_button = Button::new(100.0, 810.0, 80.0, 40.0, "Prev");
         let next_button = Button::new(200.0, 810.0, 80.0, 40.0, "Next");
         let reset_button = Button::new(300.0, 810.0, 80.0, 40.0, "Start");
         let end_button = Button::new(400.0, 810.0, 80.0, 40.0, "End");
         let flip_button = Button::new(500.0, 810.0, 80.0, 40.0, "Flip");
-
-        let game_player = ChessGamePlayer::new(board.clone());
+        let variation_button = Button::new(600.0, 810.0, 80.0, 40.0, "Var");
+        let exit_variation_button = Button::new(700.0, 810.0, 80.0, 40.0, "Exit var");
+        let games_button = Button::new(800.0, 810.0, 80.0, 40.0, "Games");
+        let prev_game_button = Button::new(900.0, 810.0, 80.0, 40.0, "< Game");
+        let next_game_button = Button::new(1000.0, 810.0, 80.0, 40.0, "Game >");
+        let review_button = Button::new(1100.0, 810.0, 80.0, 40.0, "Review");
+        let game_picker = GamePicker::new(&database);
 
         let mut state = GameState {
             engine,
             images,
             game_player,
+            database,
+            game_index: options.game_index,
+            game_picker,
+            move_list: MoveList::new(),
+            move_input: MoveInput::new(),
+            animation: BoardAnimation::from_config(config),
             prev_button,
             next_button,
             reset_button,
             end_button,
             flip_button,
-            board_flipped: false,
+            variation_button,
+            exit_variation_button,
+            games_button,
+            prev_game_button,
+            next_game_button,
+            review_button,
+            board_flipped: options.board_flipped,
             game_info: "No game loaded".to_string(),
-            current_arrow: None,
+            best_move: None,
+            engine_lines: Vec::new(),
+            pv_hovered: None,
+            pv_rects: Vec::new(),
+            slider_dragging: false,
             engine_update_receiver: None,
+            analysis_requests: Arc::new(AtomicUsize::new(0)),
             finding_best_move: false,
-            evaluation: 0.0,
+            evaluation: None,
+            show_wdl: options.show_wdl || config.get("show_wdl") == Some("true"),
             debug_mode,
             max_depth,
             current_depth: 0,
+            analysis_enabled: true,
+            keymap: Keymap::from_config(config),
+            show_help: false,
+            show_summary: false,
+            prompt: None,
+            current_file: options.pgn_path.clone(),
+            toast: None,
+            review: GameReview::default(),
+            review_job: None,
+            review_settings: ReviewSettings::from_config(config),
         };
 
-        state.load_pgn_string(SAMPLE_PGN);
-        Ok(state)
+        state.update_game_info();
+        state.rebuild_move_list();
+
+        state.game_player.goto_ply(options.start_ply);
+        state.animation.skip(&state.game_player);
+
+        state
+    }
+
+    fn start_engine(options: &Options, config: &Config, debug_mode: bool) -> Option<StockfishEngine> {
+        let engine = Self::spawn_engine(options, config, debug_mode)?;
+
+        let lines = options.engine_lines
+            .or_else(|| config.get("multipv").and_then(|lines| lines.parse().ok()))
+            .unwrap_or(DEFAULT_ENGINE_LINES)
+            .min(graphics::MAX_ENGINE_LINES);
+        if let Err(err) = engine.lock().set_multipv(lines) {
+            eprintln!("Failed to set the number of engine lines: {}", err);
+        }
+
+        if options.show_wdl && !engine.lock().supports_wdl() {
+            eprintln!("The engine doesn't support UCI_ShowWDL, win/draw/loss won't be shown");
+        }
+
+        Some(engine)
+    }
+
+    fn spawn_engine(options: &Options, config: &Config, debug_mode: bool) -> Option<StockfishEngine> {
+        let engine = engine::spawn_engine(
+            options.engine_path.as_deref(),
+            options.fake_engine_script.as_deref(),
+            config,
+            debug_mode,
+        );
+
+        engine.map_err(|err| eprintln!("Analysis is disabled: {}", err)).ok()
     }
 
     pub fn flip_board(&mut self) {
         self.board_flipped = !self.board_flipped;
     }
 
-    pub fn load_pgn_string(&mut self, pgn_content: &str) {
-        if self.game_player.load_pgn(pgn_content) {
-            let headers = self.game_player.get_headers();
-            let mut white = "Unknown";
-            let mut black = "Unknown";
-            let mut event = "Unknown";
+    fn update_game_info(&mut self) {
+        let headers = self.game_player.get_headers();
+        let mut white = "Unknown";
+        let mut black = "Unknown";
+        let mut event = "Unknown";
 
-            for (key, value) in headers {
-                match key.as_str() {
-                    "White" => white = value,
-                    "Black" => black = value,
-                    "Event" => event = value,
-                    _ => {}
-                }
+        for (key, value) in headers {
+            match key.as_str() {
+                "White" => white = value,
+                "Black" => black = value,
+                "Event" => event = value,
+                _ => {}
             }
+        }
 
-            self.game_info = format!("{}: {} vs {}", event, white, black);
-        } else {
-            println!("Failed to load PGN");
-            self.game_info = "Failed to load game".to_string();
+        self.game_info = format!("{}: {} vs {}", event, white, black);
+
+        if self.database.len() > 1 {
+            self.game_info.push_str(&format!(" (game {}/{})", self.game_index + 1, self.database.len()));
+        }
+    }
+
+    fn rebuild_move_list(&mut self) {
+        self.move_list.rebuild(self.game_player.tree(), self.game_player.start_position(), self.review.classifications());
+    }
+
+    /// Loads game `index` of the database, keeping the current game if it can't be parsed.
+    fn open_game(&mut self, index: usize) {
+        self.cancel_analysis();
+
+        match self.database.load(index, &mut self.game_player) {
+            Ok(()) => {
+                self.game_index = index;
+                self.move_input.clear();
+                self.clear_review();
+                self.update_game_info();
+                self.rebuild_move_list();
+            }
+            Err(err) => self.show_error(format!("Failed to load game {}: {}", index + 1, err)),
+        }
+
+        self.start_analysis();
+    }
+
+    /// Opens the game before or after the current one in the picker's order.
+    fn step_game(&mut self, step: isize) {
+        if let Some(index) = self.game_picker.step(self.game_index, step) {
+            self.open_game(index);
+        }
+    }
+
+    fn toggle_game_picker(&mut self) {
+        self.game_picker.toggle(self.game_index);
+    }
+
+    /// Handles a click inside the open game picker: a column header sorts, a row opens the game.
+    fn click_game_picker(&mut self, pos: [f32; 2]) {
+        if let Some(key) = self.game_picker.header_at(pos) {
+            self.game_picker.sort_by(key, &self.database);
+        } else if let Some(index) = self.game_picker.game_at(pos) {
+            self.game_picker.toggle(index);
+            self.open_game(index);
         }
     }
 
     pub fn reset_position(&mut self) {
-        self.game_player.reset();
+        self.goto_ply(0);
     }
 
     pub fn go_to_end(&mut self) {
-        self.reset_position();
+        self.goto_ply(self.game_player.get_total_moves());
+    }
 
-        let total_moves = self.game_player.get_total_moves();
-        for _ in 0..total_moves {
-            self.game_player.next_move();
+    pub fn goto_ply(&mut self, ply: usize) {
+        if ply == self.game_player.get_current_move() {
+            return;
         }
+
+        self.cancel_analysis();
+        self.game_player.goto_ply(ply);
+        self.start_analysis();
+    }
+
+    pub fn goto_node(&mut self, node: NodeId) {
+        if node == self.game_player.current_node() {
+            return;
+        }
+
+        self.cancel_analysis();
+        self.game_player.goto_node(node);
+        self.start_analysis();
+    }
+
+    /// Plays a move made on the board. A move that isn't in the game yet is added to it, as a
+    /// new variation unless it continues the line, and analysed straight away.
+    fn play_move(&mut self, mv: &Move) {
+        self.cancel_analysis();
+
+        if self.game_player.play_move(mv) {
+            self.rebuild_move_list();
+        }
+
+        self.start_analysis();
+    }
+
+    /// The board square under `pos`.
+    fn board_square_at(&self, pos: [f32; 2]) -> Option<Square> {
+        square_at(pos, self.game_player.board.grid_size, self.board_flipped)
+    }
+
+    /// Jumps to the ply under `x` on the move slider.
+    fn drag_slider(&mut self, x: f32) {
+        let total_moves = self.game_player.get_total_moves();
+        self.goto_ply(graphics::slider_ply(x, total_moves));
     }
 
     fn trigger_find_best_move(&mut self) {
-        let current_move = self.game_player.get_current_move();
-        let engine_clone = self.engine.clone();
+        let Some(engine_clone) = self.engine.clone() else {
+            return;
+        };
 
         let (tx, rx) = mpsc::channel::<EngineUpdate>();
         self.engine_update_receiver = Some(rx);
 
-        let fen = pgn_to_fen_at_move(SAMPLE_PGN, current_move).unwrap();
-        println!("Getting best move for FEN: {}", fen);
+        let fen = self.game_player.get_current_fen();
+        let position = self.game_player.get_position().clone();
+        if self.debug_mode {
+            println!("Getting best move for FEN: {}", fen);
+        }
         let max_depth = self.max_depth;
-        
+        let request = self.analysis_requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let requests = self.analysis_requests.clone();
+
         thread::spawn(move || {
-            {
-                let engine = engine_clone.lock();
-                engine.set_position(&fen).unwrap();
-                let is_white_move = fen.split_whitespace().nth(1).unwrap_or("b") == "w";
-                println!("{}", if is_white_move { "white to move" } else { "black to move" });
-                engine.find_best_move(Some(max_depth), None, is_white_move, tx)
+            let engine = engine_clone.lock();
+            if requests.load(Ordering::Relaxed) != request {
+                return;
+            }
+            if let Err(err) = engine.find_best_move(&position, Some(max_depth), None, tx.clone()) {
+                let _ = tx.send(EngineUpdate::failed(&err));
             }
         });
     }
 
-    fn set_arrow_coords(&mut self, from_coords: Point2<usize>, to_coords: Point2<usize>) {
-        let (display_from_row, display_from_col) = if self.board_flipped {
-            (from_coords.x, 7 - from_coords.y)
+    fn square_center(&self, square: Square) -> Point2<f32> {
+        let coords = square_to_board_coord(square);
+        let (display_row, display_col) = if self.board_flipped {
+            (coords.x, 7 - coords.y)
         } else {
-            (7 - from_coords.x, from_coords.y)
+            (7 - coords.x, coords.y)
         };
 
-        let (display_to_row, display_to_col) = if self.board_flipped {
-            (to_coords.x, 7 - to_coords.y)
+        let grid_size = self.game_player.board.grid_size;
+
+        Point2 {
+            x: graphics::START_X + (display_col as f32 * grid_size) + (grid_size / 2.0),
+            y: graphics::START_Y + (display_row as f32 * grid_size) + (grid_size / 2.0)
+        }
+    }
+
+    /// One arrow per engine line, best line first. Falls back to the bare best move when the
+    /// engine hasn't reported any lines.
+    fn engine_arrows(&self) -> Vec<(Point2<f32>, Point2<f32>)> {
+        let first_moves: Vec<&UciMove> = if self.engine_lines.is_empty() {
+            self.best_move.iter().collect()
         } else {
-            (7 - to_coords.x, to_coords.y)
+            self.engine_lines.iter().filter_map(|line| line.pv.first()).collect()
         };
 
-        let grid_size = self.game_player.board.grid_size;
-        
-        let from_center = Point2 {
-            x: graphics::START_X + (display_from_col as f32 * grid_size) + (grid_size / 2.0),
-            y: graphics::START_Y + (display_from_row as f32 * grid_size) + (grid_size / 2.0)
-        };
+        first_moves.into_iter()
+            .filter_map(|mv| match mv {
+                UciMove::Normal { from, to, .. } => Some((self.square_center(*from), self.square_center(*to))),
+                _ => None,
+            })
+            .collect()
+    }
 
-        let to_center = Point2 {
-            x: graphics::START_X + (display_to_col as f32 * grid_size) + (grid_size / 2.0),
-            y: graphics::START_Y + (display_to_row as f32 * grid_size) + (grid_size / 2.0)
+    /// The review's classification of the last move with the square the move went to.
+    fn current_badge(&self) -> Option<(Square, Classification)> {
+        let node = self.game_player.current_node();
+        let classification = self.review.classification(node)?;
+        let mv = self.game_player.tree().node(node).mv.as_ref()?;
+        Some((mv.to(), classification))
+    }
+
+    /// The best line's moves in SAN, each prefixed with its move number where one is due.
+    fn pv_tokens(&self) -> Vec<String> {
+        let Some(line) = self.engine_lines.first() else {
+            return Vec::new();
         };
 
-        self.current_arrow = Some((from_center, to_center));
+        let position = self.game_player.get_position();
+        let mut move_number = position.fullmoves().get();
+        let mut white_to_move = position.turn().is_white();
+
+        line.pv_san.iter()
+            .enumerate()
+            .map(|(index, san)| {
+                let token = if white_to_move {
+                    format!("{}. {}", move_number, san)
+                } else if index == 0 {
+                    format!("{}... {}", move_number, san)
+                } else {
+                    san.clone()
+                };
+
+                if !white_to_move {
+                    move_number += 1;
+                }
+                white_to_move = !white_to_move;
+                token
+            })
+            .collect()
     }
 
-    pub fn next_move(&mut self) {
-        if self.finding_best_move {
-            self.engine.cancel_search();
-            self.finding_best_move = false;
-            self.engine_update_receiver = None;
+    /// The board after playing the best line up to and including the hovered move.
+    fn pv_preview(&self) -> Option<(shakmaty::Board, Option<(Square, Square)>)> {
+        let hovered = self.pv_hovered?;
+        let line = self.engine_lines.first()?;
+
+        let mut position = self.game_player.get_position().clone();
+        let mut highlight = None;
+
+        for uci_move in line.pv.iter().take(hovered + 1) {
+            let mv = uci_move.to_move(&position).ok()?;
+            highlight = mv.from().map(|from| (from, mv.to()));
+            position.play_unchecked(&mv);
         }
 
-        if self.game_player.next_move() {
+        Some((position.board().clone(), highlight))
+    }
+
+    fn start_analysis(&mut self) {
+        self.best_move = None;
+        self.engine_lines.clear();
+        self.pv_hovered = None;
+
+        if self.engine.is_some() && self.analysis_enabled && self.review_job.is_none() {
             self.finding_best_move = true;
             self.trigger_find_best_move();
         }
     }
 
-    pub fn prev_move(&mut self) {
+    fn cancel_analysis(&mut self) {
         if self.finding_best_move {
-            self.engine.cancel_search();
+            if let Some(engine) = &self.engine {
+                engine.cancel_search();
+            }
             self.finding_best_move = false;
             self.engine_update_receiver = None;
         }
+    }
 
-        if self.game_player.previous_move() {
-            self.finding_best_move = true;
-            self.trigger_find_best_move();
+    /// Stops analysis and clears its arrows and lines, or starts it again.
+    pub fn toggle_analysis(&mut self) {
+        self.analysis_enabled = !self.analysis_enabled;
+
+        if self.analysis_enabled {
+            self.start_analysis();
+        } else {
+            self.cancel_analysis();
+            self.best_move = None;
+            self.engine_lines.clear();
+            self.evaluation = None;
+            self.pv_hovered = None;
         }
     }
-}
 
-impl EventHandler for GameState {
-    fn update(&mut self, _: &mut Context) -> GameResult {
+    /// Starts reviewing the mainline in the background, skipping positions already analysed,
+    /// or stops the review if one is running. Live analysis waits until the review is done.
+    fn toggle_review(&mut self) {
+        if self.review_job.is_some() {
+            self.stop_review();
+            self.start_analysis();
+            return;
+        }
+
+        let Some(engine) = self.engine.clone() else {
+            self.show_error("No engine to review the game with".to_string());
+            return;
+        };
+
+        let positions: Vec<_> = self.mainline()
+            .into_iter()
+            .filter(|&node| !self.review.is_analysed(node))
+            .map(|node| (node, self.game_player.position_at(node).clone()))
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+
+        self.cancel_analysis();
+        self.review_job = Some(ReviewJob::start(engine, positions, self.review_settings));
+    }
+
+    /// The starting position followed by the mainline's moves.
+    fn mainline(&self) -> Vec<NodeId> {
+        std::iter::once(ROOT)
+            .chain(self.game_player.tree().mainline_from(ROOT))
+            .collect()
+    }
+
+    /// White's win chance along the mainline with its mistakes and blunders marked, once the
+    /// review has analysed something.
+    fn eval_graph(&self) -> Option<EvalGraph> {
+        let mainline = self.mainline();
+        if !mainline.iter().any(|&node| self.review.is_analysed(node)) {
+            return None;
+        }
+
+        let points = mainline.iter()
+            .map(|&node| self.review.analysis(node).map(|analysis| win_chance(&analysis.evaluation, Color::White)))
+            .collect();
+        let marks = mainline.iter()
+            .enumerate()
+            .filter_map(|(ply, &node)| Some((ply, self.review.classification(node)?)))
+            .filter(|(_, classification)| matches!(classification, Classification::Mistake | Classification::Blunder))
+            .collect();
+        let current = mainline.iter().position(|&node| node == self.game_player.current_node());
+
+        Some(EvalGraph { points, marks, current })
+    }
+
+    /// Jumps to the mainline ply under `x` on the evaluation graph.
+    fn click_eval_graph(&mut self, x: f32) {
+        let mainline = self.mainline();
+        let ply = graphics::graph_ply(x, mainline.len());
+        self.goto_node(mainline[ply]);
+    }
+
+    fn stop_review(&mut self) {
+        if let (Some(job), Some(engine)) = (self.review_job.take(), &self.engine) {
+            job.cancel(engine);
+        }
+    }
+
+    /// Drops the review of the game being replaced.
+    fn clear_review(&mut self) {
+        self.stop_review();
+        self.review.clear();
+    }
+
+    /// Applies what the engine has found since the last frame. A search that fails is reported
+    /// in a toast.
+    fn receive_engine_updates(&mut self) {
         let mut updates = Vec::new();
 
         if let Some(ref receiver) = self.engine_update_receiver {
@@ -235,24 +625,21 @@ impl EventHandler for GameState {
         }
 
         for engine_update in updates {
-            println!("Received engine update: move={:?}, eval={:?}, depth={:?}, final={}",
-                     engine_update.best_move, engine_update.evaluation, engine_update.depth, engine_update.is_final);
-
-            if let Some(best_move) = engine_update.best_move {
-                if best_move.len() >= 2 {
-                    match (Square::from_str(&best_move[0]), Square::from_str(&best_move[1])) {
-                        (Ok(from_square), Ok(to_square)) => {
-                            let from_coords = square_to_board_coord(from_square);
-                            let to_coords = square_to_board_coord(to_square);
-                            self.set_arrow_coords(from_coords, to_coords);
-                        }
-                        _ => println!("Failed to parse squares: {:?}", best_move),
-                    }
-                }
+            if self.debug_mode {
+                println!("Received engine update: move={:?}, eval={:?}, depth={:?}, final={}",
+                         engine_update.best_move, engine_update.evaluation, engine_update.depth, engine_update.is_final);
             }
 
-            if let Some(evaluation) = engine_update.evaluation {
-                self.evaluation = evaluation;
+            if engine_update.best_move.is_some() {
+                self.best_move = engine_update.best_move;
+            }
+
+            if !engine_update.lines.is_empty() {
+                self.engine_lines = engine_update.lines;
+            }
+
+            if engine_update.evaluation.is_some() {
+                self.evaluation = engine_update.evaluation;
             }
 
             if let Some(depth) = engine_update.depth {
@@ -262,7 +649,268 @@ impl EventHandler for GameState {
             if engine_update.is_final {
                 self.finding_best_move = false;
             }
+
+            if let Some(error) = engine_update.error {
+                self.show_error(format!("Engine analysis failed: {}", error));
+            }
+        }
+    }
+
+
+    fn receive_review_updates(&mut self) {
+        let Some(job) = &mut self.review_job else {
+            return;
+        };
+
+        let updates: Vec<ReviewUpdate> = job.receiver.try_iter().collect();
+        if updates.is_empty() {
+            return;
+        }
+
+        for update in updates {
+            match update {
+                ReviewUpdate::Analysed(node, analysis) => {
+                    self.review.insert(node, analysis, &self.game_player);
+                    if let Some(job) = &mut self.review_job {
+                        job.done += 1;
+                    }
+                }
+                ReviewUpdate::Finished => {
+                    self.review_job = None;
+                    self.start_analysis();
+                }
+            }
+        }
+
+        self.move_list.set_badges(self.game_player.tree(), self.game_player.start_position(), self.review.classifications());
+    }
+
+    /// Shows a prompt for a PGN path, starting from the directory of the open file.
+    fn prompt_open_file(&mut self) {
+        let directory = self.current_file.as_deref()
+            .and_then(Path::parent)
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .map(|directory| format!("{}/", directory.display()))
+            .unwrap_or_default();
+        self.prompt = Some(Prompt { kind: PromptKind::Open, input: directory });
+    }
+
+    /// Shows a prompt for the path to save to, starting from the open file.
+    fn prompt_save_as(&mut self) {
+        let path = self.current_file.as_deref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        self.prompt = Some(Prompt { kind: PromptKind::SaveAs, input: path });
+    }
+
+    /// Saves to the file the game came from, or asks where to if it didn't come from one.
+    fn save(&mut self) {
+        match self.current_file.clone() {
+            Some(path) => self.save_to(&path),
+            None => self.prompt_save_as(),
+        }
+    }
+
+    /// Writes the database to `path` with the current game, including any moves played and
+    /// annotations, in place of the one that was loaded. `path` becomes the open file.
+    fn save_to(&mut self, path: &Path) {
+        let pgn = self.game_player.to_pgn();
+        let saved = self.database.replace_game(self.game_index, &pgn)
+            .and_then(|()| fs::write(path, self.database.source()).map_err(PgnError::from));
+
+        match saved {
+            Ok(()) => {
+                self.current_file = Some(path.to_path_buf());
+                self.show_message(format!("Saved to {}", path.display()));
+            }
+            Err(err) => self.show_error(format!("Failed to save {}: {}", path.display(), err)),
+        }
+    }
+
+    /// Replaces the database with the games in `path` and shows its first game. The current
+    /// game stays open if the file can't be loaded.
+    fn open_file(&mut self, path: &Path) {
+        let loaded = fs::read_to_string(path)
+            .map_err(PgnError::from)
+            .and_then(|pgn| self.load_pgn_string(pgn, Some(path)));
+
+        if let Err(err) = loaded {
+            self.show_error(format!("Failed to open {}: {}", path.display(), err));
+        }
+    }
+
+    /// Replaces the database with the games in `pgn` and shows its first game. `source` is the
+    /// file the text came from, if any. Nothing changes if the text can't be loaded.
+    fn load_pgn_string(&mut self, pgn: String, source: Option<&Path>) -> Result<(), PgnError> {
+        let database = PgnDatabase::index(pgn)?;
+        let mut game_player = ChessGamePlayer::new(ChessBoard::new(GRID_SIZE));
+        database.load(0, &mut game_player)?;
+
+        self.cancel_analysis();
+        self.game_picker = GamePicker::new(&database);
+        self.database = database;
+        self.game_player = game_player;
+        self.game_index = 0;
+        self.current_file = source.map(Path::to_path_buf);
+        self.move_input.clear();
+        self.clear_review();
+        self.update_game_info();
+        self.rebuild_move_list();
+        self.start_analysis();
+        Ok(())
+    }
+
+    /// Loads the clipboard: a FEN opens as a game starting from that position, anything else
+    /// is read as PGN.
+    fn paste(&mut self) {
+        let loaded = clipboard::read_text()
+            .map_err(|err| format!("Failed to paste: {}", err))
+            .and_then(|text| {
+                let text = text.trim();
+                let pgn = if looks_like_fen(text) {
+                    fen_to_position(text).map_err(|err| format!("Failed to paste: {}", err))?;
+                    format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n*\n", text)
+                } else {
+                    text.to_string()
+                };
+
+                self.load_pgn_string(pgn, None)
+                    .map_err(|err| format!("Failed to load the pasted PGN: {}", err))
+            });
+
+        if let Err(message) = loaded {
+            self.show_error(message);
+        }
+    }
+
+    /// Reports an error on the terminal and in a toast at the bottom of the window.
+    fn show_error(&mut self, message: String) {
+        eprintln!("{}", message);
+        self.toast = Some((message, ToastKind::Error, Instant::now()));
+    }
+
+    fn show_message(&mut self, message: String) {
+        self.toast = Some((message, ToastKind::Info, Instant::now()));
+    }
+
+    /// Runs a keyboard action. Returns false if the viewer should quit.
+    fn run_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::PreviousMove => self.prev_move(),
+            Action::NextMove => self.next_move(),
+            Action::Start => self.goto_ply(0),
+            Action::End => self.goto_ply(self.game_player.get_total_moves()),
+            Action::PreviousVariation => self.previous_variation(),
+            Action::NextVariation => self.next_variation(),
+            Action::ExitVariation => self.exit_variation(),
+            Action::Flip => self.flip_board(),
+            Action::ToggleAnalysis => self.toggle_analysis(),
+            Action::Review => self.toggle_review(),
+            Action::Summary => self.show_summary = !self.show_summary,
+            Action::OpenFile => self.prompt_open_file(),
+            Action::Save => self.save(),
+            Action::SaveAs => self.prompt_save_as(),
+            Action::Paste => self.paste(),
+            Action::GameList => self.toggle_game_picker(),
+            Action::PreviousGame => self.step_game(-1),
+            Action::NextGame => self.step_game(1),
+            Action::Help => self.show_help = !self.show_help,
+            Action::Close => {
+                if self.move_input.selected.is_some() {
+                    self.move_input.clear();
+                } else if self.show_help {
+                    self.show_help = false;
+                } else if self.show_summary {
+                    self.show_summary = false;
+                } else if self.game_picker.open {
+                    self.toggle_game_picker();
+                } else {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Handles a key while a path prompt is shown; typed characters arrive through
+    /// `text_input_event`.
+    fn prompt_key(&mut self, input: &KeyInput) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+
+        match input.keycode {
+            Some(KeyCode::Back) => {
+                prompt.input.pop();
+            }
+            Some(KeyCode::Return | KeyCode::NumpadEnter) => {
+                let path = PathBuf::from(prompt.input.trim());
+                let kind = prompt.kind;
+                self.prompt = None;
+
+                match kind {
+                    PromptKind::Open => self.open_file(&path),
+                    PromptKind::SaveAs => self.save_to(&path),
+                }
+            }
+            Some(KeyCode::Escape) => self.prompt = None,
+            _ => {}
+        }
+    }
+
+    pub fn previous_variation(&mut self) {
+        self.cancel_analysis();
+
+        if self.game_player.previous_variation() {
+            self.start_analysis();
+        }
+    }
+
+    pub fn next_move(&mut self) {
+        self.cancel_analysis();
+
+        if self.game_player.next_move() {
+            self.start_analysis();
+        }
+    }
+
+    pub fn next_variation(&mut self) {
+        self.cancel_analysis();
+
+        if self.game_player.next_variation() {
+            self.start_analysis();
+        }
+    }
+
+    pub fn exit_variation(&mut self) {
+        self.cancel_analysis();
+
+        if self.game_player.exit_variation() {
+            self.start_analysis();
         }
+    }
+
+    pub fn prev_move(&mut self) {
+        self.cancel_analysis();
+
+        if self.game_player.previous_move() {
+            self.start_analysis();
+        }
+    }
+}
+
+impl EventHandler for GameState {
+    fn update(&mut self, _: &mut Context) -> GameResult {
+        self.move_input.sync(self.game_player.current_node());
+        self.animation.sync(&self.game_player);
+        self.receive_review_updates();
+
+        if self.toast.as_ref().is_some_and(|(_, _, shown)| shown.elapsed() > TOAST_DURATION) {
+            self.toast = None;
+        }
+
+        self.receive_engine_updates();
 
         Ok(())
     }
@@ -274,22 +922,152 @@ impl EventHandler for GameState {
             &self.reset_button,
             &self.end_button,
             &self.flip_button,
+            &self.variation_button,
+            &self.exit_variation_button,
+            &self.games_button,
+            &self.prev_game_button,
+            &self.next_game_button,
+            &self.review_button,
         ];
+        let arrows = self.engine_arrows();
+        let pv_tokens = self.pv_tokens();
+        let pv_preview = self.pv_preview();
+        let move_text = self.game_player.current_move_text();
+        let comments = self.game_player.current_comments();
+        if self.move_list.needs_layout() {
+            self.move_list.layout(graphics::MOVE_LIST_WIDTH, |text| graphics::move_list_text_width(ctx, text));
+        }
+        self.move_list.update_scroll(self.game_player.current_node(), graphics::MOVE_LIST_HEIGHT);
 
-        draw_ui(
-            ctx,
-            &self.game_player.board,
-            &self.images,
-            &buttons,
-            &self.game_info,
-            self.game_player.get_current_move(),
-            self.game_player.get_total_moves(),
-            self.board_flipped,
-            self.current_arrow,
-            self.debug_mode,
-            self.evaluation,
-            self.current_depth,
-        )
+        let move_info = MoveInfo {
+            current_move: self.game_player.get_current_move(),
+            total_moves: self.game_player.get_total_moves(),
+            current_turn: self.game_player.get_current_turn(),
+            total_turns: self.game_player.get_total_turns(),
+            move_text: move_text.as_deref(),
+            in_variation: self.game_player.is_in_variation(),
+            comments: &comments,
+            move_list: &self.move_list,
+            current_node: self.game_player.current_node(),
+            badge: self.current_badge(),
+            review_progress: self.review_job.as_ref().map(|job| (job.done, job.total)),
+        };
+        let principal_variation = PvDisplay {
+            tokens: &pv_tokens,
+            hovered: self.pv_hovered,
+            preview: pv_preview.as_ref().map(|(board, highlight)| (board, *highlight)),
+        };
+        let shortcuts = self.keymap.describe();
+        let eval_graph = self.eval_graph();
+        let summary = self.show_summary.then(|| GameSummary::new(&self.game_player, &self.review));
+        let overlays = Overlays {
+            game_picker: self.game_picker.open.then(|| PickerDisplay {
+                picker: &self.game_picker,
+                games: self.database.games(),
+                current_game: self.game_index,
+            }),
+            prompt: self.prompt.as_ref().map(|prompt| PromptDisplay {
+                label: prompt.kind.label(),
+                hint: prompt.kind.hint(),
+                input: &prompt.input,
+            }),
+            help: self.show_help.then_some(shortcuts.as_slice()),
+            summary: summary.as_ref(),
+            toast: self.toast.as_ref().map(|(message, kind, _)| (message.as_str(), *kind)),
+        };
+
+        let view = FrameView {
+            board: &self.game_player.board,
+            board_flipped: self.board_flipped,
+            buttons: &buttons,
+            game_info: &self.game_info,
+            move_info,
+            arrows: &arrows,
+            engine_lines: &self.engine_lines,
+            evaluation: self.evaluation.as_ref(),
+            show_wdl: self.show_wdl,
+            current_depth: (self.analysis_enabled && self.engine.is_some()).then_some(self.current_depth),
+            principal_variation,
+            eval_graph: eval_graph.as_ref(),
+            move_input: &self.move_input,
+            animation: self.animation.current(),
+            overlays,
+            debug_mode: self.debug_mode,
+        };
+
+        self.pv_rects = draw_ui(ctx, &self.images, &view)?;
+
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeated: bool) -> Result<(), GameError> {
+        if self.prompt.is_some() {
+            self.prompt_key(&input);
+            return Ok(());
+        }
+
+        self.animation.finish();
+
+        if let Some(action) = self.keymap.action_for(&input) {
+            if (!repeated || action.repeats()) && !self.run_action(action) {
+                ctx.request_quit();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> Result<(), GameError> {
+        if let Some(prompt) = &mut self.prompt {
+            if !character.is_control() {
+                prompt.input.push(character);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> Result<(), GameError> {
+        if self.move_input.drag.is_some() {
+            self.move_input.drag_to([x, y]);
+            return Ok(());
+        }
+
+        if self.slider_dragging {
+            self.drag_slider(x);
+            return Ok(());
+        }
+
+        if self.game_picker.open {
+            self.game_picker.hovered = self.game_picker.row_at([x, y]);
+            return Ok(());
+        }
+
+        self.pv_hovered = self.pv_rects.iter().position(|rect| rect.contains([x, y]));
+        Ok(())
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> Result<(), GameError> {
+        if self.game_picker.open {
+            self.game_picker.scroll_by(if y > 0.0 { -3 } else { 3 });
+            return Ok(());
+        }
+
+        let mouse = ctx.mouse.position();
+        if graphics::move_list_rect().contains(mouse) {
+            self.move_list.scroll_by(-y * LINE_HEIGHT * 3.0);
+            return Ok(());
+        }
+
+        if let Some(hovered) = self.pv_hovered {
+            let last = self.pv_rects.len().saturating_sub(1);
+            self.pv_hovered = Some(if y > 0.0 {
+                hovered.saturating_sub(1)
+            } else {
+                (hovered + 1).min(last)
+            });
+        }
+        Ok(())
     }
 
     fn mouse_button_down_event(
@@ -299,10 +1077,30 @@ impl EventHandler for GameState {
         x: f32,
         y: f32,
     ) -> Result<(), GameError> {
+        self.animation.finish();
+
         if button == MouseButton::Left {
             let pos = [x, y];
 
-            if self.prev_button.contains_point(pos) {
+            if self.move_input.promotion.is_some() {
+                let grid_size = self.game_player.board.grid_size;
+                if let Some(mv) = self.move_input.choose_promotion(pos, grid_size, self.board_flipped) {
+                    self.play_move(&mv);
+                }
+                return Ok(());
+            }
+
+            let board_square = self.board_square_at(pos)
+                .filter(|_| !self.game_picker.open && !self.show_help && !self.show_summary);
+
+            if let Some(square) = board_square {
+                if let Some(mv) = self.move_input.press(self.game_player.get_position(), square, pos) {
+                    self.play_move(&mv);
+                }
+            } else if graphics::slider_rect().contains(pos) {
+                self.slider_dragging = true;
+                self.drag_slider(x);
+            } else if self.prev_button.contains_point(pos) {
                 self.prev_button.pressed = true
             } else if self.next_button.contains_point(pos) {
                 self.next_button.pressed = true;
@@ -312,6 +1110,18 @@ impl EventHandler for GameState {
                 self.end_button.pressed = true;
             } else if self.flip_button.contains_point(pos) {
                 self.flip_button.pressed = true;
+            } else if self.variation_button.contains_point(pos) {
+                self.variation_button.pressed = true;
+            } else if self.exit_variation_button.contains_point(pos) {
+                self.exit_variation_button.pressed = true;
+            } else if self.games_button.contains_point(pos) {
+                self.games_button.pressed = true;
+            } else if self.prev_game_button.contains_point(pos) {
+                self.prev_game_button.pressed = true;
+            } else if self.next_game_button.contains_point(pos) {
+                self.next_game_button.pressed = true;
+            } else if self.review_button.contains_point(pos) {
+                self.review_button.pressed = true;
             }
         }
 
@@ -328,7 +1138,23 @@ impl EventHandler for GameState {
         if button == MouseButton::Left {
             let pos = [x, y];
 
-            if self.prev_button.contains_point(pos) && self.prev_button.pressed {
+            if self.move_input.drag.is_some() {
+                if let Some(mv) = self.move_input.release(self.board_square_at(pos)) {
+                    self.play_move(&mv);
+                    self.animation.skip(&self.game_player);
+                }
+            } else if self.slider_dragging {
+                self.slider_dragging = false;
+            } else if self.game_picker.open && GamePicker::rect().contains(pos) {
+                self.click_game_picker(pos);
+            } else if graphics::graph_rect().contains(pos) && self.eval_graph().is_some() {
+                self.click_eval_graph(x);
+            } else if graphics::move_list_rect().contains(pos) {
+                let list = graphics::move_list_rect();
+                if let Some(node) = self.move_list.node_at([x - list.x, y - list.y]) {
+                    self.goto_node(node);
+                }
+            } else if self.prev_button.contains_point(pos) && self.prev_button.pressed {
                 self.prev_move();
             } else if self.next_button.contains_point(pos) && self.next_button.pressed {
                 self.next_move();
@@ -338,6 +1164,18 @@ impl EventHandler for GameState {
                 self.go_to_end();
             } else if self.flip_button.contains_point(pos) && self.flip_button.pressed {
                 self.flip_board();
+            } else if self.variation_button.contains_point(pos) && self.variation_button.pressed {
+                self.next_variation();
+            } else if self.exit_variation_button.contains_point(pos) && self.exit_variation_button.pressed {
+                self.exit_variation();
+            } else if self.games_button.contains_point(pos) && self.games_button.pressed {
+                self.toggle_game_picker();
+            } else if self.prev_game_button.contains_point(pos) && self.prev_game_button.pressed {
+                self.step_game(-1);
+            } else if self.next_game_button.contains_point(pos) && self.next_game_button.pressed {
+                self.step_game(1);
+            } else if self.review_button.contains_point(pos) && self.review_button.pressed {
+                self.toggle_review();
             }
 
             self.prev_button.pressed = false;
@@ -345,20 +1183,235 @@ impl EventHandler for GameState {
             self.reset_button.pressed = false;
             self.end_button.pressed = false;
             self.flip_button.pressed = false;
+            self.variation_button.pressed = false;
+            self.exit_variation_button.pressed = false;
+            self.games_button.pressed = false;
+            self.prev_game_button.pressed = false;
+            self.next_game_button.pressed = false;
+            self.review_button.pressed = false;
         }
 
         Ok(())
     }
 }
 
+impl WindowEventHandler for GameState {
+    fn file_dropped_event(&mut self, _ctx: &mut Context, path: PathBuf) -> GameResult {
+        self.prompt = None;
+        self.open_file(&path);
+        Ok(())
+    }
+}
+
+/// Whether pasted text is a FEN rather than PGN: a single line whose first field has the
+/// eight ranks of a board.
+fn looks_like_fen(text: &str) -> bool {
+    !text.contains('\n')
+        && text.split_whitespace().next().is_some_and(|board| board.matches('/').count() == 7)
+}
+
+fn load_game(options: &Options) -> Result<(PgnDatabase, ChessGamePlayer), String> {
+    let pgn = match &options.pgn_path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))?,
+        None => SAMPLE_PGN.to_string(),
+    };
+
+    let describe_error = |err: PgnError| match &options.pgn_path {
+        Some(path) => format!("could not load {}: {}", path.display(), err),
+        None => err.to_string(),
+    };
+
+    let database = PgnDatabase::index(pgn).map_err(describe_error)?;
+
+    let mut game_player = ChessGamePlayer::new(ChessBoard::new(GRID_SIZE));
+    database.load(options.game_index, &mut game_player).map_err(describe_error)?;
+
+    let total_moves = game_player.get_total_moves();
+    if options.start_ply > total_moves {
+        return Err(format!("ply {} is out of range, the game has {} plies", options.start_ply, total_moves));
+    }
+
+    Ok((database, game_player))
+}
+
+/// Runs a subcommand that works without a window, then exits.
+fn run_headless(show_help: bool, usage: &str, run: impl FnOnce() -> Result<(), String>) -> GameResult {
+    if show_help {
+        println!("{}", usage);
+        return Ok(());
+    }
+
+    if let Err(err) = run() {
+        eprintln!("brilliant: {}", err);
+        process::exit(1);
+    }
+    Ok(())
+}
+
 fn main() -> GameResult {
+    let usage = match std::env::args().nth(1).as_deref() {
+        Some("analyze") => ANALYZE_USAGE,
+        Some("render") => RENDER_USAGE,
+        Some("gif") => GIF_USAGE,
+        _ => USAGE,
+    };
+    let options = match cli::parse_command(std::env::args().skip(1)) {
+        Ok(Command::View(options)) => options,
+        Ok(Command::Analyze(options)) => {
+            return run_headless(options.show_help, usage, || analyze::run(&options, &Config::load()));
+        }
+        Ok(Command::Render(options)) => {
+            return run_headless(options.show_help, usage, || render::run(&options));
+        }
+        Ok(Command::Gif(options)) => {
+            return run_headless(options.show_help, usage, || gif_export::run(&options, &Config::load()));
+        }
+        Err(err) => {
+            eprintln!("brilliant: {}\n\n{}", err, usage);
+            process::exit(2);
+        }
+    };
+
+    if options.show_help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let (database, game_player) = match load_game(&options) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("brilliant: {}", err);
+            process::exit(1);
+        }
+    };
+
     let (mut ctx, event_loop) = ContextBuilder::new("Brilliant", "BongoSLAP")
         .window_mode(
             ggez::conf::WindowMode::default()
-                .dimensions(900.0, 900.0)
+                .dimensions(1280.0, 900.0)
         )
         .build()?;
 
-    let state = GameState::new(&mut ctx)?;
-    event::run(ctx, event_loop, state);
-}
\ No newline at end of file
+    let config = Config::load();
+    let state = GameState::new(&mut ctx, database, game_player, &options, &config)?;
+    event_loop::run(ctx, event_loop, state);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use brilliant::fake_engine::FakeEngineScript;
+
+    use super::*;
+
+    const SECOND_PGN: &str = "[Event \"Second\"]\n\
+        [FEN \"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\"]\n\n\
+        3. Bb5 a6 (3... Nf6 4. O-O) 4. Ba4 Nf6 *";
+
+    /// The viewer on the sample game with a fake engine, and the engine's script to see what it
+    /// was sent.
+    fn viewer() -> (GameState, FakeEngineScript) {
+        viewer_with(FakeEngineScript::default())
+    }
+
+    fn viewer_with(script: FakeEngineScript) -> (GameState, FakeEngineScript) {
+        let options = Options::default();
+        let (database, game_player) = load_game(&options).unwrap();
+        let engine = StockfishEngine::with_fake_engine(script.clone(), false).unwrap();
+
+        let state = GameState::from_parts(
+            Some(engine), HashMap::new(), database, game_player, &options, &Config::default(), false,
+        );
+        (state, script)
+    }
+
+    /// Waits for the engine to be asked to search `fen`.
+    fn assert_analysed(script: &FakeEngineScript, fen: &str) {
+        let expected = format!("position fen {}", fen);
+        let started = Instant::now();
+
+        loop {
+            let received = script.received();
+            let last_position = received.iter().rev().find(|command| command.starts_with("position"));
+            if last_position == Some(&expected) && received.last().is_some_and(|command| command.starts_with("go")) {
+                return;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "expected '{}', the engine got {:?}", expected, received);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Applies engine updates until the search ends.
+    fn finish_analysis(state: &mut GameState) {
+        let started = Instant::now();
+        while state.finding_best_move {
+            assert!(started.elapsed() < Duration::from_secs(5), "the search didn't end");
+            thread::sleep(Duration::from_millis(10));
+            state.receive_engine_updates();
+        }
+    }
+
+    #[test]
+    fn analysis_follows_the_loaded_game() {
+        let (mut state, script) = viewer();
+        state.goto_ply(2);
+        assert_analysed(&script, "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+
+        state.load_pgn_string(SECOND_PGN.to_string(), None).unwrap();
+        assert_analysed(&script, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        state.goto_ply(2);
+        assert_analysed(&script, "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4");
+
+        let tree = state.game_player.tree();
+        let bb5 = tree.node(ROOT).children[0];
+        let nf6 = tree.node(bb5).children[1];
+        state.goto_node(nf6);
+        assert_analysed(&script, "r1bqkb1r/pppp1ppp/2n2n2/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+    }
+
+    #[test]
+    fn start_and_end_buttons_restart_the_analysis() {
+        let (mut state, script) = viewer();
+        // The search for the loaded game's start is replaced before it has begun.
+        state.load_pgn_string(SECOND_PGN.to_string(), None).unwrap();
+        state.go_to_end();
+        assert_analysed(&script, "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 2 5");
+
+        state.reset_position();
+        assert_analysed(&script, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    }
+
+    #[test]
+    fn engine_crash_is_reported() {
+        let script = FakeEngineScript::parse("on go\n crash").unwrap();
+        let (mut state, _) = viewer_with(script);
+
+        state.goto_ply(2);
+        finish_analysis(&mut state);
+        assert!(state.toast.as_ref().is_some_and(|(_, kind, _)| *kind == ToastKind::Error));
+
+        // Moving on tries the dead engine again without taking the viewer down.
+        state.toast = None;
+        state.goto_ply(3);
+        finish_analysis(&mut state);
+        let (message, kind, _) = state.toast.as_ref().unwrap();
+        assert_eq!(*kind, ToastKind::Error);
+        assert!(message.contains("not running"), "{}", message);
+    }
+
+    #[test]
+    fn pasted_fens_are_told_from_pgn() {
+        assert!(looks_like_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"));
+        assert!(looks_like_fen("8/5k2/8/8/8/8/3K4/4R3"));
+
+        assert!(!looks_like_fen("[Event \"Casual\"]\n[FEN \"8/5k2/8/8/8/8/3K4/4R3 w - - 0 1\"]\n\n1. Re7+ *"));
+        assert!(!looks_like_fen("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 *"));
+        assert!(!looks_like_fen("1. e4 1/2-1/2"));
+        assert!(!looks_like_fen("hello there"));
+        assert!(!looks_like_fen("a/b/c"));
+        assert!(!looks_like_fen(""));
+    }
+}
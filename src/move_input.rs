@@ -0,0 +1,360 @@
+use ggez::graphics::Rect;
+use shakmaty::{Chess, Color, File, Move, Position, Rank, Role, Square};
+
+use crate::game_tree::NodeId;
+use crate::graphics::{START_X, START_Y};
+
+/// Pieces offered when a pawn promotes, in the order the chooser shows them.
+pub const PROMOTION_ROLES: [Role; 4] = [Role::Queen, Role::Knight, Role::Rook, Role::Bishop];
+
+/// A pawn move waiting for the promotion piece to be picked.
+pub struct Promotion {
+    pub square: Square,
+    pub color: Color,
+    moves: Vec<Move>,
+}
+
+/// Moving pieces on the board with the mouse. A piece is picked up by pressing on it and can
+/// then be dragged to a square, or released and moved with a second click. Everything is for
+/// the position at `node`; the selection is dropped when the viewer moves elsewhere.
+pub struct MoveInput {
+    node: NodeId,
+    pub selected: Option<Square>,
+    moves: Vec<Move>,
+    pub drag: Option<[f32; 2]>,
+    deselect_on_release: bool,
+    pub promotion: Option<Promotion>,
+}
+
+impl Default for MoveInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveInput {
+    pub fn new() -> Self {
+        MoveInput {
+            node: 0,
+            selected: None,
+            moves: Vec::new(),
+            drag: None,
+            deselect_on_release: false,
+            promotion: None,
+        }
+    }
+
+    /// Forgets the selection if the position on the board is no longer the one it was made in.
+    pub fn sync(&mut self, node: NodeId) {
+        if node != self.node {
+            self.clear();
+            self.node = node;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.selected = None;
+        self.moves.clear();
+        self.drag = None;
+        self.deselect_on_release = false;
+        self.promotion = None;
+    }
+
+    /// The squares the selected piece can move to.
+    pub fn targets(&self) -> impl Iterator<Item = Square> + '_ {
+        self.moves.iter().map(Move::to)
+    }
+
+    /// The square being dragged from, if a piece is being dragged.
+    pub fn dragged(&self) -> Option<Square> {
+        self.selected.filter(|_| self.drag.is_some())
+    }
+
+    /// Handles a press on `square`. Returns the move to play when it completes one.
+    pub fn press(&mut self, position: &Chess, square: Square, point: [f32; 2]) -> Option<Move> {
+        if self.selected.is_some() && self.selected != Some(square) {
+            let mv = self.choose_target(square);
+            if mv.is_some() || self.promotion.is_some() {
+                return mv;
+            }
+        }
+
+        let own_piece = position.board().color_at(square) == Some(position.turn());
+        if !own_piece {
+            self.clear();
+            return None;
+        }
+
+        self.deselect_on_release = self.selected == Some(square);
+        self.selected = Some(square);
+        self.moves = position.legal_moves().into_iter()
+            .filter(|mv| mv.from() == Some(square))
+            .collect();
+        self.drag = Some(point);
+        None
+    }
+
+    pub fn drag_to(&mut self, point: [f32; 2]) {
+        if self.drag.is_some() {
+            self.drag = Some(point);
+        }
+    }
+
+    /// Handles the release of the mouse over `square`, dropping a dragged piece there. Returns
+    /// the move to play when it completes one.
+    pub fn release(&mut self, square: Option<Square>) -> Option<Move> {
+        self.drag.take()?;
+
+        match square {
+            Some(square) if self.selected == Some(square) => {
+                if self.deselect_on_release {
+                    self.clear();
+                }
+                None
+            }
+            Some(square) => {
+                let mv = self.choose_target(square);
+                if mv.is_none() && self.promotion.is_none() {
+                    self.clear();
+                }
+                mv
+            }
+            None => {
+                self.clear();
+                None
+            }
+        }
+    }
+
+    /// The move to `square`, or none if the selected piece can't go there. A promotion opens
+    /// the chooser instead.
+    fn choose_target(&mut self, square: Square) -> Option<Move> {
+        let candidates: Vec<Move> = self.moves.iter()
+            .filter(|mv| mv.to() == square)
+            .cloned()
+            .collect();
+
+        match candidates.as_slice() {
+            [] => None,
+            [mv] => {
+                let mv = mv.clone();
+                self.clear();
+                Some(mv)
+            }
+            _ => {
+                let color = Color::from_white(square.rank() == Rank::Eighth);
+                self.drag = None;
+                self.promotion = Some(Promotion { square, color, moves: candidates });
+                None
+            }
+        }
+    }
+
+    /// Picks the promotion piece under `point`, or cancels the promotion if there's none.
+    pub fn choose_promotion(&mut self, point: [f32; 2], grid_size: f32, flipped: bool) -> Option<Move> {
+        let promotion = self.promotion.take()?;
+
+        let mv = promotion_rects(promotion.square, grid_size, flipped)
+            .into_iter()
+            .find(|(_, rect)| rect.contains(point))
+            .and_then(|(role, _)| promotion.moves.into_iter().find(|mv| mv.promotion() == Some(role)));
+
+        self.clear();
+        mv
+    }
+}
+
+/// The square of the board under `point`.
+pub fn square_at(point: [f32; 2], grid_size: f32, flipped: bool) -> Option<Square> {
+    let col = ((point[0] - START_X) / grid_size).floor();
+    let row = ((point[1] - START_Y) / grid_size).floor();
+    if !(0.0..8.0).contains(&col) || !(0.0..8.0).contains(&row) {
+        return None;
+    }
+
+    let (file, rank) = if flipped {
+        (7 - col as u32, row as u32)
+    } else {
+        (col as u32, 7 - row as u32)
+    };
+    Some(Square::from_coords(File::new(file), Rank::new(rank)))
+}
+
+/// Where `square` is drawn on the window.
+pub fn square_rect(square: Square, grid_size: f32, flipped: bool) -> Rect {
+    let file = square.file() as usize;
+    let rank = square.rank() as usize;
+    let (display_row, display_col) = if flipped {
+        (rank, 7 - file)
+    } else {
+        (7 - rank, file)
+    };
+
+    Rect::new(
+        START_X + display_col as f32 * grid_size,
+        START_Y + display_row as f32 * grid_size,
+        grid_size,
+        grid_size,
+    )
+}
+
+/// The chooser's squares: a column starting on the promotion square and running towards the
+/// middle of the board.
+pub fn promotion_rects(square: Square, grid_size: f32, flipped: bool) -> Vec<(Role, Rect)> {
+    let first = square_rect(square, grid_size, flipped);
+    let step = if first.y < START_Y + 4.0 * grid_size { grid_size } else { -grid_size };
+
+    PROMOTION_ROLES.iter()
+        .enumerate()
+        .map(|(index, &role)| {
+            let rect = Rect::new(first.x, first.y + index as f32 * step, grid_size, grid_size);
+            (role, rect)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::CastlingMode;
+
+    use super::*;
+    use crate::fen::fen_to_position;
+
+    const GRID_SIZE: f32 = 72.0;
+    const POINT: [f32; 2] = [0.0, 0.0];
+
+    fn uci(mv: Option<Move>) -> Option<String> {
+        mv.map(|mv| mv.to_uci(CastlingMode::Standard).to_string())
+    }
+
+    fn targets(input: &MoveInput) -> Vec<Square> {
+        let mut targets: Vec<Square> = input.targets().collect();
+        targets.sort();
+        targets
+    }
+
+    #[test]
+    fn clicking_a_piece_then_a_square() {
+        let position = Chess::default();
+        let mut input = MoveInput::new();
+
+        assert!(input.press(&position, Square::E2, POINT).is_none());
+        assert_eq!(input.dragged(), Some(Square::E2));
+        assert!(input.release(Some(Square::E2)).is_none());
+        assert_eq!(input.selected, Some(Square::E2));
+        assert_eq!(input.dragged(), None);
+        assert_eq!(targets(&input), [Square::E3, Square::E4]);
+
+        assert_eq!(uci(input.press(&position, Square::E4, POINT)).as_deref(), Some("e2e4"));
+        assert_eq!(input.selected, None);
+        assert_eq!(targets(&input), []);
+    }
+
+    #[test]
+    fn dragging_a_piece() {
+        let position = Chess::default();
+        let mut input = MoveInput::new();
+
+        input.press(&position, Square::G1, POINT);
+        input.drag_to([100.0, 200.0]);
+        assert_eq!(input.drag, Some([100.0, 200.0]));
+        assert_eq!(uci(input.release(Some(Square::F3))).as_deref(), Some("g1f3"));
+        assert_eq!(input.selected, None);
+
+        // Dropped off the board.
+        input.press(&position, Square::G1, POINT);
+        assert!(input.release(None).is_none());
+        assert_eq!(input.selected, None);
+    }
+
+    #[test]
+    fn deselecting() {
+        let position = Chess::default();
+        let mut input = MoveInput::new();
+
+        input.press(&position, Square::E2, POINT);
+        input.release(Some(Square::E2));
+        input.press(&position, Square::E2, POINT);
+        assert!(input.release(Some(Square::E2)).is_none());
+        assert_eq!(input.selected, None);
+
+        // Clicking another of the mover's pieces selects it instead, and anything else clears.
+        input.press(&position, Square::E2, POINT);
+        input.release(Some(Square::E2));
+        input.press(&position, Square::B1, POINT);
+        assert_eq!(input.selected, Some(Square::B1));
+        input.release(Some(Square::B1));
+        input.press(&position, Square::E7, POINT);
+        assert_eq!(input.selected, None);
+
+        // Moving to another position forgets the selection.
+        input.press(&position, Square::E2, POINT);
+        input.sync(5);
+        assert_eq!(input.selected, None);
+        assert_eq!(input.drag, None);
+    }
+
+    #[test]
+    fn illegal_targets() {
+        let position = Chess::default();
+        let mut input = MoveInput::new();
+
+        input.press(&position, Square::E2, POINT);
+        input.release(Some(Square::E2));
+        assert!(input.press(&position, Square::E5, POINT).is_none());
+        assert_eq!(input.selected, None);
+
+        input.press(&position, Square::E2, POINT);
+        assert!(input.release(Some(Square::D3)).is_none());
+        assert_eq!(input.selected, None);
+
+        // The opponent's pieces can't be picked up.
+        assert!(input.press(&position, Square::E7, POINT).is_none());
+        assert_eq!(input.selected, None);
+    }
+
+    #[test]
+    fn promoting() {
+        let position = fen_to_position("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut input = MoveInput::new();
+        let center = |rect: Rect| [rect.x + rect.w / 2.0, rect.y + rect.h / 2.0];
+
+        input.press(&position, Square::A7, POINT);
+        assert!(input.release(Some(Square::B8)).is_none());
+        let promotion = input.promotion.as_ref().unwrap();
+        assert_eq!((promotion.square, promotion.color), (Square::B8, Color::White));
+
+        let rects = promotion_rects(Square::B8, GRID_SIZE, false);
+        assert_eq!(rects.iter().map(|&(role, _)| role).collect::<Vec<_>>(), PROMOTION_ROLES);
+        assert_eq!(rects[0].1, square_rect(Square::B8, GRID_SIZE, false));
+        assert_eq!(rects[1].1, square_rect(Square::B7, GRID_SIZE, false));
+
+        let knight = center(rects[1].1);
+        assert_eq!(uci(input.choose_promotion(knight, GRID_SIZE, false)).as_deref(), Some("a7b8n"));
+        assert!(input.promotion.is_none());
+        assert_eq!(input.selected, None);
+
+        // Clicking away from the chooser cancels the move.
+        input.press(&position, Square::A7, POINT);
+        input.release(Some(Square::A7));
+        assert!(input.press(&position, Square::A8, POINT).is_none());
+        assert!(input.promotion.is_some());
+        assert!(input.choose_promotion(center(square_rect(Square::H1, GRID_SIZE, false)), GRID_SIZE, false).is_none());
+        assert!(input.promotion.is_none());
+        assert_eq!(input.selected, None);
+    }
+
+    #[test]
+    fn squares_and_points() {
+        for flipped in [false, true] {
+            for square in Square::ALL {
+                let rect = square_rect(square, GRID_SIZE, flipped);
+                assert_eq!(square_at([rect.x + 1.0, rect.y + 1.0], GRID_SIZE, flipped), Some(square));
+            }
+        }
+        assert_eq!(square_rect(Square::A1, GRID_SIZE, false), Rect::new(START_X, START_Y + 7.0 * GRID_SIZE, GRID_SIZE, GRID_SIZE));
+        assert_eq!(square_rect(Square::A1, GRID_SIZE, true), Rect::new(START_X + 7.0 * GRID_SIZE, START_Y, GRID_SIZE, GRID_SIZE));
+        assert_eq!(square_at([START_X - 1.0, START_Y], GRID_SIZE, false), None);
+        assert_eq!(square_at([START_X, START_Y + 8.0 * GRID_SIZE], GRID_SIZE, false), None);
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use ggez::graphics::Rect;
+use shakmaty::{Chess, Position};
+
+use crate::game_tree::{nag_symbol, GameTree, NodeId, ROOT};
+use crate::review::Classification;
+
+pub const LINE_HEIGHT: f32 = 20.0;
+pub const INDENT: f32 = 18.0;
+const TOKEN_SPACING: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Move(NodeId),
+    Badge(Classification),
+    MoveNumber,
+    Comment,
+    Bracket,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub text: String,
+    pub kind: TokenKind,
+}
+
+/// A paragraph of the notation. Mainline moves come in numbered pairs, one row per move
+/// number; comments and variations get rows of their own, variations indented by depth.
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub indent: usize,
+    pub tokens: Vec<Token>,
+}
+
+/// A token with its position relative to the top left of the move list.
+#[derive(Debug, Clone)]
+pub struct PlacedToken {
+    pub rect: Rect,
+    pub text: String,
+    pub kind: TokenKind,
+}
+
+/// The notation panel: the game as rows of tokens, their layout and the scroll position.
+/// Rows are rebuilt when the game changes; laying them out needs text measurement, so that
+/// happens lazily when the panel is next drawn.
+pub struct MoveList {
+    rows: Vec<Row>,
+    placed: Vec<PlacedToken>,
+    needs_layout: bool,
+    content_height: f32,
+    pub scroll: f32,
+    follow_current: bool,
+    current: NodeId,
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            rows: Vec::new(),
+            placed: Vec::new(),
+            needs_layout: true,
+            content_height: 0.0,
+            scroll: 0.0,
+            follow_current: true,
+            current: ROOT,
+        }
+    }
+
+    pub fn rebuild(&mut self, tree: &GameTree, start: &Chess, badges: &HashMap<NodeId, Classification>) {
+        self.rows = build_rows(tree, start, badges);
+        self.needs_layout = true;
+        self.scroll = 0.0;
+        self.follow_current = true;
+    }
+
+    /// Rebuilds the rows with new review badges, keeping the scroll position.
+    pub fn set_badges(&mut self, tree: &GameTree, start: &Chess, badges: &HashMap<NodeId, Classification>) {
+        self.rows = build_rows(tree, start, badges);
+        self.needs_layout = true;
+    }
+
+    pub fn needs_layout(&self) -> bool {
+        self.needs_layout
+    }
+
+    /// Flows the rows into lines `width` wide, measuring text with `measure`.
+    pub fn layout(&mut self, width: f32, measure: impl Fn(&str) -> f32) {
+        self.placed.clear();
+        let mut y = 0.0;
+
+        for row in &self.rows {
+            let left = row.indent as f32 * INDENT;
+            let mut x = left;
+
+            for token in &row.tokens {
+                let token_width = measure(&token.text);
+                if x > left && x + token_width > width {
+                    x = left;
+                    y += LINE_HEIGHT;
+                }
+
+                self.placed.push(PlacedToken {
+                    rect: Rect::new(x, y, token_width, LINE_HEIGHT),
+                    text: token.text.clone(),
+                    kind: token.kind,
+                });
+                x += token_width + TOKEN_SPACING;
+            }
+
+            y += LINE_HEIGHT;
+        }
+
+        self.content_height = y;
+        self.needs_layout = false;
+    }
+
+    /// Adjusts the scroll position for a viewport `height` tall, bringing `current` into view
+    /// if navigation moved it or the game changed.
+    pub fn update_scroll(&mut self, current: NodeId, height: f32) {
+        if self.follow_current || current != self.current {
+            self.follow_current = false;
+            self.current = current;
+
+            let current_rect = self.placed.iter()
+                .find(|token| token.kind == TokenKind::Move(current))
+                .map(|token| token.rect);
+
+            match current_rect {
+                Some(rect) if rect.y < self.scroll => self.scroll = rect.y,
+                Some(rect) if rect.y + rect.h > self.scroll + height => {
+                    self.scroll = rect.y + rect.h - height;
+                }
+                None if current == ROOT => self.scroll = 0.0,
+                _ => {}
+            }
+        }
+
+        self.scroll = self.scroll.clamp(0.0, (self.content_height - height).max(0.0));
+    }
+
+    pub fn scroll_by(&mut self, amount: f32) {
+        self.scroll += amount;
+    }
+
+    pub fn placed(&self) -> &[PlacedToken] {
+        &self.placed
+    }
+
+    /// The move at `point`, given relative to the top left of the list before scrolling.
+    pub fn node_at(&self, point: [f32; 2]) -> Option<NodeId> {
+        let point = [point[0], point[1] + self.scroll];
+
+        self.placed.iter().find_map(|token| match token.kind {
+            TokenKind::Move(node) if token.rect.contains(point) => Some(node),
+            _ => None,
+        })
+    }
+}
+
+fn build_rows(tree: &GameTree, start: &Chess, badges: &HashMap<NodeId, Classification>) -> Vec<Row> {
+    let numbering = Numbering::new(start);
+    let mut rows = Vec::new();
+
+    let root = tree.node(ROOT);
+    push_comments(&mut rows, 0, root.starting_comments.iter().chain(root.comments.iter()));
+
+    let mut row = Row { indent: 0, tokens: Vec::new() };
+    let mut node = ROOT;
+
+    while let Some(&main) = tree.node(node).children.first() {
+        let white = numbering.is_white(tree.depth(main));
+        if white && !row.tokens.is_empty() {
+            rows.push(std::mem::replace(&mut row, Row { indent: 0, tokens: Vec::new() }));
+        }
+        if white || row.tokens.is_empty() {
+            row.tokens.push(numbering.token(tree.depth(main)));
+        }
+        push_move(&mut row.tokens, tree, badges, main);
+
+        let alternatives = &tree.node(node).children[1..];
+        let main_node = tree.node(main);
+        if !main_node.comments.is_empty() || !alternatives.is_empty() {
+            rows.push(std::mem::replace(&mut row, Row { indent: 0, tokens: Vec::new() }));
+            push_comments(&mut rows, 0, main_node.comments.iter());
+
+            for &alternative in alternatives {
+                let mut tokens = Vec::new();
+                variation_tokens(tree, &numbering, badges, alternative, &mut tokens);
+                rows.push(Row { indent: 1, tokens });
+            }
+        }
+
+        node = main;
+    }
+
+    if !row.tokens.is_empty() {
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn push_comments<'a>(rows: &mut Vec<Row>, indent: usize, comments: impl Iterator<Item = &'a String>) {
+    for comment in comments {
+        let tokens = comment.split_whitespace()
+            .map(|word| Token { text: word.to_string(), kind: TokenKind::Comment })
+            .collect();
+        rows.push(Row { indent, tokens });
+    }
+}
+
+/// A variation starting with `first`, written inline: moves, comments in braces and nested
+/// variations in parentheses.
+fn variation_tokens(
+    tree: &GameTree,
+    numbering: &Numbering,
+    badges: &HashMap<NodeId, Classification>,
+    first: NodeId,
+    tokens: &mut Vec<Token>,
+) {
+    let mut node = first;
+    let mut needs_number = true;
+
+    loop {
+        let game_node = tree.node(node);
+        let ply = tree.depth(node);
+
+        for comment in &game_node.starting_comments {
+            push_inline_comment(tokens, comment);
+            needs_number = true;
+        }
+
+        if needs_number || numbering.is_white(ply) {
+            tokens.push(numbering.token(ply));
+        }
+        push_move(tokens, tree, badges, node);
+        needs_number = false;
+
+        for comment in &game_node.comments {
+            push_inline_comment(tokens, comment);
+            needs_number = true;
+        }
+
+        let parent = game_node.parent.unwrap_or(ROOT);
+        if node != first {
+            for &alternative in &tree.node(parent).children[1..] {
+                tokens.push(Token { text: "(".to_string(), kind: TokenKind::Bracket });
+                variation_tokens(tree, numbering, badges, alternative, tokens);
+                tokens.push(Token { text: ")".to_string(), kind: TokenKind::Bracket });
+                needs_number = true;
+            }
+        }
+
+        match game_node.children.first() {
+            Some(&next) => node = next,
+            None => break,
+        }
+    }
+}
+
+/// Adds `comment` in braces, word by word so long comments wrap.
+fn push_inline_comment(tokens: &mut Vec<Token>, comment: &str) {
+    let text = format!("{{{}}}", comment);
+    tokens.extend(text.split_whitespace().map(|word| Token { text: word.to_string(), kind: TokenKind::Comment }));
+}
+
+/// Adds the move of `node`, followed by its review badge if it has one.
+fn push_move(tokens: &mut Vec<Token>, tree: &GameTree, badges: &HashMap<NodeId, Classification>, node: NodeId) {
+    let game_node = tree.node(node);
+    let nags: String = game_node.nags.iter().map(nag_symbol).collect();
+
+    tokens.push(Token {
+        text: format!("{}{}", game_node.san, nags),
+        kind: TokenKind::Move(node),
+    });
+
+    if let Some(&classification) = badges.get(&node) {
+        tokens.push(Token {
+            text: classification.symbol().to_string(),
+            kind: TokenKind::Badge(classification),
+        });
+    }
+}
+
+/// Move numbers for plies counted from the game's starting position, which may have Black to
+/// move or start later than move 1.
+struct Numbering {
+    first_move: u32,
+    black_starts: bool,
+}
+
+impl Numbering {
+    fn new(start: &Chess) -> Self {
+        Numbering {
+            first_move: start.fullmoves().get(),
+            black_starts: start.turn().is_black(),
+        }
+    }
+
+    /// Whether the move reaching `ply` is White's.
+    fn is_white(&self, ply: usize) -> bool {
+        (ply - 1 + usize::from(self.black_starts)).is_multiple_of(2)
+    }
+
+    fn token(&self, ply: usize) -> Token {
+        let half_moves = ply - 1 + usize::from(self.black_starts);
+        let number = self.first_move as usize + half_moves / 2;
+        let text = if self.is_white(ply) {
+            format!("{}.", number)
+        } else {
+            format!("{}...", number)
+        };
+
+        Token { text, kind: TokenKind::MoveNumber }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::ChessBoard;
+    use crate::pgn::ChessGamePlayer;
+
+    fn rows(pgn: &str) -> Vec<(usize, String)> {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn(pgn, 0).unwrap();
+
+        build_rows(game.tree(), game.start_position(), &HashMap::new()).into_iter()
+            .map(|row| {
+                let text: Vec<String> = row.tokens.into_iter().map(|token| token.text).collect();
+                (row.indent, text.join(" "))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numbers_from_the_initial_position() {
+        assert_eq!(rows("1. e4 e5 2. Nf3 (2. f4 exf4) Nc6 3. Bb5 *"), [
+            (0, "1. e4 e5".to_string()),
+            (0, "2. Nf3".to_string()),
+            (1, "2. f4 exf4".to_string()),
+            (0, "2... Nc6".to_string()),
+            (0, "3. Bb5".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn numbers_from_a_black_to_move_start() {
+        let pgn = concat!(
+            "[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]\n\n",
+            "1... c5 (1... e5 2. Nf3 {Open}) 2. Nf3 d6 3. d4 *",
+        );
+
+        assert_eq!(rows(pgn), [
+            (0, "1... c5".to_string()),
+            (1, "1... e5 2. Nf3 {Open}".to_string()),
+            (0, "2. Nf3 d6".to_string()),
+            (0, "3. d4".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn numbers_from_a_late_start() {
+        let pgn = "[FEN \"8/5k2/8/8/8/8/3K4/4R3 b - - 10 41\"]\n\n41... Kf6 42. Re3 (42. Kd3 Kf5) Kf5 *";
+
+        assert_eq!(rows(pgn), [
+            (0, "41... Kf6".to_string()),
+            (0, "42. Re3".to_string()),
+            (1, "42. Kd3 Kf5".to_string()),
+            (0, "42... Kf5".to_string()),
+        ]);
+    }
+}
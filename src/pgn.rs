@@ -1,15 +1,52 @@
+use std::fmt;
 use std::io::{BufReader, Cursor};
-use ggez::mint::Point2;
-use pgn_reader::{BufferedReader, Visitor, Skip, RawHeader, SanPlus};
-use shakmaty::{Chess, Position, Move, Square};
+use pgn_reader::{BufferedReader, Visitor, Skip, RawComment, RawHeader, SanPlus, Nag};
+use shakmaty::{Chess, Move, Position};
+
+use crate::board::ChessBoard;
+use crate::fen::{fen_to_position, position_to_fen};
+use crate::game_tree::{nag_symbol, GameTree, NodeId, ROOT};
+use crate::pgn_writer::write_game;
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(std::io::Error),
+    NoGame,
+    GameNotFound { index: usize, count: usize },
+    IllegalMove { ply: usize, san: String },
+    InvalidFen(String),
+}
 
-use crate::board::{ChessBoard, PieceType, Piece, Colour};
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::Io(err) => write!(f, "error reading PGN: {}", err),
+            PgnError::NoGame => write!(f, "no game found in PGN"),
+            PgnError::GameNotFound { index, count } => {
+                write!(f, "game {} not found, the PGN contains {} game(s)", index + 1, count)
+            }
+            PgnError::IllegalMove { ply, san } => {
+                write!(f, "illegal or unparseable move '{}' at ply {}", san, ply)
+            }
+            PgnError::InvalidFen(reason) => write!(f, "bad FEN header: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for PgnError {
+    fn from(err: std::io::Error) -> Self {
+        PgnError::Io(err)
+    }
+}
 
 pub struct ChessGamePlayer {
     pub board: ChessBoard,
-    moves: Vec<Move>,
+    tree: GameTree,
+    current_node: NodeId,
+    positions: Vec<Chess>,
+    line: Vec<NodeId>,
     position: Chess,
-    pub(crate) current_move: usize,
+    pub current_move: usize,
     headers: Vec<(String, String)>,
 }
 
@@ -17,60 +54,92 @@ impl ChessGamePlayer {
     pub fn new(board: ChessBoard) -> Self {
         ChessGamePlayer {
             board,
-            moves: Vec::new(),
+            tree: GameTree::new(),
+            current_node: ROOT,
+            positions: vec![Chess::default()],
+            line: vec![ROOT],
             position: Chess::default(),
             current_move: 0,
             headers: Vec::new(),
         }
     }
 
-    pub fn load_pgn(&mut self, pgn: &str) -> bool {
+    pub fn load_pgn(&mut self, pgn: &str, game_index: usize) -> Result<(), PgnError> {
         let cursor = Cursor::new(pgn);
         let reader = BufReader::new(cursor);
         let mut buffered_reader = BufferedReader::new(reader);
 
+        for skipped in 0..game_index {
+            if !buffered_reader.skip_game::<PgnVisitor>()? {
+                return Err(if skipped == 0 {
+                    PgnError::NoGame
+                } else {
+                    PgnError::GameNotFound { index: game_index, count: skipped }
+                });
+            }
+        }
+
         let mut visitor = PgnVisitor::new();
 
-        match buffered_reader.read_game(&mut visitor) {
-            Ok(Some(())) => {
-                self.moves = visitor.moves;
+        match buffered_reader.read_game(&mut visitor)? {
+            Some(()) => {
+                if let Some(reason) = visitor.invalid_fen {
+                    return Err(PgnError::InvalidFen(reason));
+                }
+
+                if let Some((ply, san)) = visitor.illegal_move {
+                    return Err(PgnError::IllegalMove { ply, san });
+                }
+
+                self.positions = visitor.positions;
+                self.tree = visitor.tree;
                 self.headers = visitor.headers;
+                self.line.clear();
 
                 self.reset();
-                true
-            },
-            Ok(None) => {
-                eprintln!("No game found in PGN");
-                false
+                Ok(())
             },
-            Err(err) => {
-                eprintln!("Error reading PGN: {}", err);
-                false
-            }
+            None if game_index == 0 => Err(PgnError::NoGame),
+            None => Err(PgnError::GameNotFound { index: game_index, count: game_index }),
         }
     }
 
     pub fn reset(&mut self) {
-        self.position = Chess::default();
-        self.current_move = 0;
-        self.board = ChessBoard::new(self.board.grid_size);
+        self.goto_node(ROOT);
     }
 
-    fn reset_internal(&mut self) {
-        self.position = Chess::default();
-        self.board = ChessBoard::new(self.board.grid_size);
+    /// Jumps to `node` using its cached position. If `node` isn't on the line being viewed,
+    /// that line becomes the moves leading to `node` followed by its main continuation.
+    pub fn goto_node(&mut self, node: NodeId) {
+        let ply = self.tree.depth(node);
+
+        if self.line.get(ply) != Some(&node) {
+            self.line = std::iter::once(ROOT)
+                .chain(self.tree.path_to(node))
+                .chain(self.tree.mainline_from(node))
+                .collect();
+        }
+
+        self.position = self.positions[node].clone();
+        self.board = ChessBoard::from_board(self.position.board(), self.board.grid_size);
+        self.current_node = node;
+        self.current_move = ply;
+    }
+
+    /// Jumps to ply `ply` of the line being viewed, or to its last move if the line is shorter.
+    pub fn goto_ply(&mut self, ply: usize) {
+        let ply = ply.min(self.line.len() - 1);
+        self.goto_node(self.line[ply]);
     }
 
     pub fn next_move(&mut self) -> bool {
-        if self.current_move >= self.moves.len() {
-            return false;
+        match self.line.get(self.current_move + 1) {
+            Some(&node) => {
+                self.goto_node(node);
+                true
+            }
+            None => false,
         }
-
-        let mv = &self.moves[self.current_move].clone();
-        self.position.play_unchecked(mv); //TODO: use play() instead of play_unchecked() and handle illegal moves in UI
-        self.apply_move_to_board(mv);
-        self.current_move += 1;
-        true
     }
 
     pub fn previous_move(&mut self) -> bool {
@@ -78,145 +147,191 @@ impl ChessGamePlayer {
             return false;
         }
 
-        self.current_move -= 1;
+        self.goto_ply(self.current_move - 1);
+        true
+    }
+
+    /// Replaces the last move with the next alternative played at the same point, wrapping
+    /// around to the first one.
+    pub fn next_variation(&mut self) -> bool {
+        self.step_variation(1)
+    }
 
-        self.reset_internal();
+    pub fn previous_variation(&mut self) -> bool {
+        self.step_variation(-1)
+    }
 
-        println!("Moving to move: {}", self.current_move);
-        for i in 0..self.current_move {
-            let mv = &self.moves[i].clone();
-            self.position.play_unchecked(mv);
-            self.apply_move_to_board(mv);
+    fn step_variation(&mut self, step: isize) -> bool {
+        let siblings = self.tree.siblings(self.current_node);
+        if siblings.len() < 2 {
+            return false;
         }
 
+        let index = siblings.iter().position(|&node| node == self.current_node).unwrap_or(0);
+        let next = (index as isize + step).rem_euclid(siblings.len() as isize) as usize;
+        let sibling = siblings[next];
+
+        self.goto_node(sibling);
         true
     }
 
-    fn apply_move_to_board(&mut self, mv: &Move) {
-        match mv {
-            Move::Normal { from, to, promotion, .. } => {
-                let from_coord = square_to_board_coord(*from);
-                let to_coord = square_to_board_coord(*to);
-
-                let piece_color = self.board.grid[7 - from_coord.x][from_coord.y].piece.colour.clone();
+    /// Leaves the innermost variation containing the current move, going to the move it was
+    /// an alternative to.
+    pub fn exit_variation(&mut self) -> bool {
+        let mut node = self.current_node;
 
-                self.move_piece(from_coord, to_coord);
+        while let Some(parent) = self.tree.node(node).parent {
+            let main_child = self.tree.node(parent).children[0];
+            if main_child != node {
+                self.goto_node(main_child);
+                return true;
+            }
+            node = parent;
+        }
 
-                if let Some(role) = promotion {
-                    let piece_type = match role {
-                        shakmaty::Role::Queen => PieceType::Queen,
-                        shakmaty::Role::Rook => PieceType::Rook,
-                        shakmaty::Role::Bishop => PieceType::Bishop,
-                        shakmaty::Role::Knight => PieceType::Knight,
-                        _ => panic!("Invalid promotion piece"),
-                    };
+        false
+    }
 
-                    self.promote_piece(to_coord, piece_type, piece_color);
-                }
-            },
-            Move::Castle { king, rook, .. } => {
-                let is_kingside = rook.file() as usize > king.file() as usize;
+    /// Plays `mv`, a legal move in the current position. If it's already in the game it's
+    /// followed; otherwise it's added, continuing the line if the current move is the last one
+    /// and as a new variation if not. Returns whether the game tree changed.
+    pub fn play_move(&mut self, mv: &Move) -> bool {
+        let existing = self.tree.node(self.current_node).children.iter()
+            .copied()
+            .find(|&child| self.tree.node(child).mv.as_ref() == Some(mv));
 
-                if is_kingside {
-                    let rank = king.rank().char() as usize - '1' as usize;
+        if let Some(child) = existing {
+            self.goto_node(child);
+            return false;
+        }
 
-                    self.move_piece(
-                        Point2 {x: rank, y: 4},
-                        Point2 {x: rank, y: 6}
-                    );
+        let mut position = self.position.clone();
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, mv);
+        let node = self.tree.add_child(self.current_node, mv.clone(), san.to_string());
+        self.positions.push(position);
 
-                    self.move_piece(
-                        Point2 {x: rank, y: 7},
-                        Point2 {x: rank, y: 5}
-                    );
-                } else {
-                    let rank = king.rank().char() as usize - '1' as usize;
+        self.goto_node(node);
+        true
+    }
 
-                    self.move_piece(
-                        Point2 {x: rank, y: 4},
-                        Point2 {x: rank, y: 2}
-                    );
+    pub fn get_position(&self) -> &Chess {
+        &self.position
+    }
 
-                    self.move_piece(
-                        Point2 {x: rank, y: 0},
-                        Point2 {x: rank, y: 3}
-                    );
-                }
-            },
-            Move::EnPassant { from, to, .. } => {
-                let from_coord = square_to_board_coord(*from);
-                let to_coord = square_to_board_coord(*to);
+    pub fn get_current_fen(&self) -> String {
+        position_to_fen(&self.position)
+    }
 
-                self.move_piece(from_coord, to_coord);
+    pub fn get_current_move(&self) -> usize {
+        self.current_move
+    }
 
-                let captured_rank = if from.rank().char() as usize - '1' as usize > 3 {
-                    to.rank().char() as usize - '1' as usize + 1
-                } else {
-                    to.rank().char() as usize - '1' as usize - 1
-                };
+    /// Length of the line being viewed, following the main continuation from the current move.
+    pub fn get_total_moves(&self) -> usize {
+        self.line.len() - 1
+    }
 
-                self.remove_piece((to.file() as usize, captured_rank));
-            },
-            _ => panic!("Unexpected move type"),
-        }
+    /// The number of the last move played, counted from the start position's move number.
+    pub fn get_current_turn(&self) -> u32 {
+        move_number(&self.position)
     }
 
-    fn move_piece(&mut self, from: Point2<usize>, to: Point2<usize>) {
-        let from_row = 7 - from.x;
-        let from_col = from.y;
+    /// The number of the last move of the line being viewed.
+    pub fn get_total_turns(&self) -> u32 {
+        let end = self.line.last().copied().unwrap_or(ROOT);
+        move_number(&self.positions[end])
+    }
 
-        let to_row = 7 - to.x;
-        let to_col = to.y;
+    pub fn is_in_variation(&self) -> bool {
+        !self.tree.is_mainline(self.current_node)
+    }
 
-        self.board.grid[to_row][to_col].piece = self.board.grid[from_row][from_col].piece.clone();
-        self.board.grid[from_row][from_col].piece = Piece::new(PieceType::None, Colour::None)
+    pub fn current_comments(&self) -> Vec<&str> {
+        let node = self.tree.node(self.current_node);
+        node.starting_comments.iter()
+            .chain(node.comments.iter())
+            .map(String::as_str)
+            .collect()
     }
 
-    fn promote_piece(&mut self, coord: Point2<usize>, piece_type: PieceType, color: Colour) {
-        let row = 7 - coord.x;
-        let col = coord.y;
+    /// The last move played in SAN with its move number and annotation symbols, e.g. "12... Nxe4?!".
+    pub fn current_move_text(&self) -> Option<String> {
+        let node = self.tree.node(self.current_node);
+        node.mv.as_ref()?;
+
+        let separator = if self.position.turn().is_black() { "." } else { "..." };
+        let nags: String = node.nags.iter().map(nag_symbol).collect();
 
-        self.board.grid[row][col].piece = Piece::new(piece_type, color)
+        Some(format!("{}{} {}{}", move_number(&self.position), separator, node.san, nags))
     }
 
-    fn remove_piece(&mut self, coord: (usize, usize)) {
-        let row = 7 - coord.1;
-        let col = coord.0;
+    pub fn tree(&self) -> &GameTree {
+        &self.tree
+    }
 
-        self.board.grid[row][col].piece = Piece::new(PieceType::None, Colour::None)
+    /// The position after `node`'s move.
+    pub fn position_at(&self, node: NodeId) -> &Chess {
+        &self.positions[node]
     }
 
-    pub fn get_current_move(&self) -> usize {
-        self.current_move
+    pub fn start_position(&self) -> &Chess {
+        &self.positions[ROOT]
     }
 
-    pub fn get_total_moves(&self) -> usize {
-        self.moves.len()
+    pub fn current_node(&self) -> NodeId {
+        self.current_node
     }
 
     pub fn get_headers(&self) -> &[(String, String)] {
         &self.headers
     }
+
+    /// The game as PGN, with every variation, comment and NAG in the tree.
+    pub fn to_pgn(&self) -> String {
+        write_game(&self.headers, &self.tree, self.start_position())
+    }
 }
 
-pub fn square_to_board_coord(square: Square) -> Point2<usize> {
-    let file = square.file() as usize;
-    let rank = square.rank().char() as usize - '1' as usize;
-    Point2 { x: rank, y: file}
+/// The number of the move that led to `position`, as the notation numbers it. Before White's
+/// first move of a game from the initial position that's 0.
+fn move_number(position: &Chess) -> u32 {
+    let fullmoves = position.fullmoves().get();
+    if position.turn().is_white() { fullmoves - 1 } else { fullmoves }
 }
 
+/// Builds the game tree. `current` is the node after the last move read; a variation is an
+/// alternative to that move, so it starts from `current`'s parent and `end_variation` returns
+/// to where the variation began.
 struct PgnVisitor {
-    position: Chess,
-    moves: Vec<Move>,
+    tree: GameTree,
+    positions: Vec<Chess>,
+    current: NodeId,
+    variation_stack: Vec<NodeId>,
+    skipped_variations: usize,
+    at_variation_start: bool,
+    pending_comments: Vec<String>,
     headers: Vec<(String, String)>,
+    fen: Option<String>,
+    set_up: Option<String>,
+    invalid_fen: Option<String>,
+    illegal_move: Option<(usize, String)>,
 }
 
 impl PgnVisitor {
     fn new() -> Self {
         PgnVisitor {
-            position: Chess::default(),
-            moves: Vec::new(),
+            tree: GameTree::new(),
+            positions: vec![Chess::default()],
+            current: ROOT,
+            variation_stack: Vec::new(),
+            skipped_variations: 0,
+            at_variation_start: false,
+            pending_comments: Vec::new(),
             headers: Vec::new(),
+            fen: None,
+            set_up: None,
+            invalid_fen: None,
+            illegal_move: None,
         }
     }
 }
@@ -225,9 +340,7 @@ impl Visitor for PgnVisitor {
     type Result = ();
 
     fn begin_game(&mut self) {
-        self.position = Chess::default();
-        self.moves.clear();
-        self.headers.clear();
+        *self = PgnVisitor::new();
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
@@ -235,24 +348,364 @@ impl Visitor for PgnVisitor {
             std::str::from_utf8(key),
             value.decode_utf8()
         ) {
+            match key_str {
+                "FEN" => self.fen = Some(value_str.to_string()),
+                "SetUp" => self.set_up = Some(value_str.to_string()),
+                _ => {}
+            }
             self.headers.push((key_str.to_string(), value_str.to_string()));
         }
     }
 
+    /// Starts from the `FEN` header unless `SetUp` explicitly says the game starts from the
+    /// initial position. Many files leave out `SetUp`, so a lone `FEN` is honoured too.
+    fn end_headers(&mut self) -> Skip {
+        if let Some(fen) = &self.fen {
+            if self.set_up.as_deref() != Some("0") {
+                match fen_to_position(fen) {
+                    Ok(position) => self.positions[ROOT] = position,
+                    Err(reason) => self.invalid_fen = Some(reason),
+                }
+            }
+        }
+
+        Skip(self.invalid_fen.is_some())
+    }
+
     fn san(&mut self, san_plus: SanPlus) {
-        let san = san_plus.san;
+        if self.illegal_move.is_some() {
+            return;
+        }
+
+        let position = &self.positions[self.current];
+
+        match san_plus.san.to_move(position) {
+            Ok(mv) => {
+                let mut next_position = position.clone();
+                next_position.play_unchecked(&mv);
 
-        if let Ok(mv) = san.to_move(&self.position) {
-            self.moves.push(mv.clone());
-            self.position.play_unchecked(&mv);
+                self.current = self.tree.add_child(self.current, mv, san_plus.to_string());
+                self.positions.push(next_position);
+                self.tree.node_mut(self.current).starting_comments = std::mem::take(&mut self.pending_comments);
+                self.at_variation_start = false;
+            }
+            Err(_) => self.illegal_move = Some((self.tree.node(self.current).ply + 1, san_plus.to_string())),
+        }
+    }
+
+    fn nag(&mut self, nag: Nag) {
+        if self.illegal_move.is_none() {
+            self.tree.node_mut(self.current).nags.push(nag);
+        }
+    }
+
+    fn comment(&mut self, comment: RawComment<'_>) {
+        if self.illegal_move.is_some() {
+            return;
+        }
+
+        let text = String::from_utf8_lossy(comment.as_bytes()).trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+
+        if self.at_variation_start {
+            self.pending_comments.push(text);
+        } else {
+            self.tree.node_mut(self.current).comments.push(text);
         }
     }
 
+    /// Only variations that are read go on the stack. pgn-reader still calls `end_variation`
+    /// for a skipped one, so those are counted to tell their ends apart.
     fn begin_variation(&mut self) -> Skip {
-        Skip(true)
+        match self.tree.node(self.current).parent {
+            Some(parent) if self.illegal_move.is_none() => {
+                self.variation_stack.push(self.current);
+                self.current = parent;
+                self.at_variation_start = true;
+                self.pending_comments.clear();
+                Skip(false)
+            }
+            _ => {
+                self.skipped_variations += 1;
+                Skip(true)
+            }
+        }
+    }
+
+    fn end_variation(&mut self) {
+        if self.skipped_variations > 0 {
+            self.skipped_variations -= 1;
+        } else if let Some(node) = self.variation_stack.pop() {
+            self.current = node;
+            self.at_variation_start = false;
+            self.pending_comments.clear();
+        }
+    }
+
+    fn end_game(&mut self) -> Self::Result {}
+}
+
+#[cfg(test)]
+mod tests {
+    use pgn_reader::BufferedReader;
+    use shakmaty::san::SanPlus;
+    use shakmaty::Square;
+
+    use super::*;
+    use crate::board::PieceType;
+
+    fn visit(pgn: &str) -> PgnVisitor {
+        let mut visitor = PgnVisitor::new();
+        BufferedReader::new_cursor(pgn.as_bytes()).read_game(&mut visitor).unwrap();
+        visitor
+    }
+
+    fn load(pgn: &str) -> Result<ChessGamePlayer, PgnError> {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn(pgn, 0).map(|()| game)
+    }
+
+    fn sans(tree: &GameTree, nodes: &[NodeId]) -> Vec<String> {
+        nodes.iter().map(|&node| tree.node(node).san.clone()).collect()
+    }
+
+    #[test]
+    fn annotated_game() {
+        let game = load(concat!(
+            "[Event \"Annotated\"]\n\n",
+            "{The Ruy Lopez} 1. e4 e5 2. Nf3 $1 {Developing} Nc6 ",
+            "(2... d6 {Philidor} 3. d4) ({Or} 2... Nf6 3. Nxe5 (3. Bc4 $5) d6) 3. Bb5 a6 *",
+        )).unwrap();
+        let tree = game.tree();
+
+        assert_eq!(tree.node(ROOT).comments, ["The Ruy Lopez"]);
+        let mainline: Vec<NodeId> = tree.mainline_from(ROOT).collect();
+        assert_eq!(sans(tree, &mainline), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+
+        let nf3 = tree.node(mainline[2]);
+        assert_eq!(nf3.nags, [Nag(1)]);
+        assert_eq!(nf3.comments, ["Developing"]);
+
+        let black_second_moves = &tree.node(mainline[2]).children;
+        assert_eq!(sans(tree, black_second_moves), ["Nc6", "d6", "Nf6"]);
+        let philidor = tree.node(black_second_moves[1]);
+        assert_eq!(philidor.comments, ["Philidor"]);
+        assert_eq!(sans(tree, &philidor.children), ["d4"]);
+
+        let petrov = tree.node(black_second_moves[2]);
+        assert_eq!(petrov.starting_comments, ["Or"]);
+        let nxe5 = petrov.children[0];
+        assert_eq!(sans(tree, &petrov.children), ["Nxe5", "Bc4"]);
+        assert_eq!(tree.node(petrov.children[1]).nags, [Nag(5)]);
+        assert_eq!(sans(tree, &tree.node(nxe5).children), ["d6"]);
+    }
+
+    #[test]
+    fn variations_leave_nothing_on_the_stack() {
+        let visitor = visit("1. e4 e5 (1... c5 2. Nf3 (2. c3) d6) (1... e6) 2. Nf3 *");
+
+        assert!(visitor.variation_stack.is_empty());
+        assert!(visitor.illegal_move.is_none());
+        assert_eq!(visitor.tree.node(visitor.current).san, "Nf3");
+    }
+
+    #[test]
+    fn skipped_variations_leave_nothing_on_the_stack() {
+        // A variation before the first move has nothing to replace, so it's skipped.
+        let visitor = visit("(1. d4 d5) 1. e4 e5 (1... c5) 2. Nf3 *");
+
+        assert!(visitor.variation_stack.is_empty());
+        assert_eq!(visitor.skipped_variations, 0);
+        let mainline: Vec<NodeId> = visitor.tree.mainline_from(ROOT).collect();
+        assert_eq!(sans(&visitor.tree, &mainline), ["e4", "e5", "Nf3"]);
+        assert_eq!(sans(&visitor.tree, &visitor.tree.node(mainline[0]).children), ["e5", "c5"]);
+    }
+
+    #[test]
+    fn variation_skipped_at_the_start_of_another() {
+        // The inner variation would replace a move before the game starts, so it's skipped and
+        // the outer one carries on from the starting position.
+        let visitor = visit("1. e4 ((1. c4) 1. d4 d5) e5 2. Nf3 *");
+
+        assert!(visitor.variation_stack.is_empty());
+        assert_eq!(visitor.skipped_variations, 0);
+        assert!(visitor.illegal_move.is_none());
+        let tree = &visitor.tree;
+        assert_eq!(sans(tree, &tree.node(ROOT).children), ["e4", "d4"]);
+        let mainline: Vec<NodeId> = tree.mainline_from(ROOT).collect();
+        assert_eq!(sans(tree, &mainline), ["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn variations_after_an_illegal_move_are_skipped() {
+        let pgn = "1. e4 e5 2. Nf3 (2. Nc3 Nf6 (2... Nc6 3. Qxf7 (3. d4) (3. Bc4 Nf6)) 3. f4) 2... Nc6 3. Bb5 *";
+
+        let visitor = visit(pgn);
+        assert!(visitor.variation_stack.is_empty());
+        assert_eq!(visitor.skipped_variations, 0);
+        assert_eq!(visitor.illegal_move, Some((5, "Qxf7".to_string())));
+
+        match load(pgn) {
+            Err(PgnError::IllegalMove { ply, san }) => assert_eq!((ply, san.as_str()), (5, "Qxf7")),
+            other => panic!("expected an illegal move, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn game_starts_from_fen_header() {
+        let game = load(concat!(
+            "[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]\n\n",
+            "1... c5 2. Nf3 *",
+        )).unwrap();
+
+        assert!(game.start_position().turn().is_black());
+        assert_eq!(game.get_total_moves(), 2);
     }
 
-    fn end_game(&mut self) -> Self::Result {
-        ()
+    #[test]
+    fn turns_follow_a_black_to_move_start() {
+        let mut game = load(concat!(
+            "[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]\n\n",
+            "1... c5 2. Nf3 d6 3. d4 *",
+        )).unwrap();
+
+        // 1. e4 was played before the game starts.
+        assert_eq!((game.get_current_turn(), game.get_total_turns()), (1, 3));
+
+        let mut turns = Vec::new();
+        while game.next_move() {
+            turns.push(game.get_current_turn());
+        }
+        assert_eq!(turns, [1, 2, 2, 3]);
+
+        game.goto_ply(2);
+        assert_eq!(game.get_current_move(), 2);
+        assert_eq!(game.get_current_turn(), 2);
+        assert_eq!(game.current_move_text().as_deref(), Some("2. Nf3"));
+        assert_eq!(game.get_current_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        game.goto_ply(99);
+        assert_eq!(game.get_current_move(), 4);
+        assert_eq!(game.current_move_text().as_deref(), Some("3. d4"));
+
+        while game.previous_move() {}
+        assert_eq!(game.get_current_turn(), 1);
+        assert_eq!(game.current_node(), ROOT);
+    }
+
+    #[test]
+    fn turns_follow_a_late_start() {
+        let mut game = load(concat!(
+            "[FEN \"8/5k2/8/8/8/8/3K4/4R3 w - - 10 41\"]\n\n",
+            "41. Re3 Kf6 42. Kd3 *",
+        )).unwrap();
+
+        assert_eq!((game.get_current_turn(), game.get_total_turns()), (40, 42));
+        game.goto_ply(2);
+        assert_eq!(game.get_current_turn(), 41);
+        assert_eq!(game.current_move_text().as_deref(), Some("41... Kf6"));
+    }
+
+    #[test]
+    fn set_up_zero_ignores_fen_header() {
+        let game = load(concat!(
+            "[SetUp \"0\"]\n[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]\n\n",
+            "1. d4 *",
+        )).unwrap();
+
+        assert_eq!(*game.start_position(), Chess::default());
+    }
+
+    #[test]
+    fn bad_fen_header_is_reported() {
+        assert!(matches!(load("[FEN \"not a position\"]\n\n1. e4 *"), Err(PgnError::InvalidFen(_))));
+    }
+
+    /// The same pseudo-random sequence as the navigation benchmark, so failures reproduce.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (self.0 >> 33) as usize % bound
+        }
+
+        fn legal_move(&mut self, position: &Chess) -> Option<Move> {
+            let legal_moves = position.legal_moves();
+            (!legal_moves.is_empty()).then(|| legal_moves[self.below(legal_moves.len())].clone())
+        }
+    }
+
+    fn random_game(rng: &mut Lcg, plies: usize) -> String {
+        let mut position = Chess::default();
+        let mut moves = Vec::new();
+
+        while let Some(mv) = rng.legal_move(&position).filter(|_| moves.len() < plies) {
+            moves.push(SanPlus::from_move_and_play_unchecked(&mut position, &mv).to_string());
+        }
+
+        format!("{} *", moves.join(" "))
+    }
+
+    /// Checks the position against the moves leading to the current node, and the board view
+    /// against the position, square by square.
+    fn assert_board_matches(game: &ChessGamePlayer, step: &str) {
+        let tree = game.tree();
+        let mut replayed = game.start_position().clone();
+        for node in tree.path_to(game.current_node()) {
+            replayed.play_unchecked(tree.node(node).mv.as_ref().unwrap());
+        }
+        assert_eq!(&replayed, game.get_position(), "position after {}", step);
+        assert_eq!(game.get_current_move(), tree.depth(game.current_node()), "ply after {}", step);
+
+        for square in Square::ALL {
+            let shown = &game.board.grid[7 - square.rank() as usize][square.file() as usize].piece;
+            match replayed.board().piece_at(square) {
+                Some(piece) => {
+                    assert_eq!(shown.piece_type, PieceType::from(piece.role), "{} after {}", square, step);
+                    let colour = piece.color.fold_wb("white-", "black-");
+                    assert!(shown.filename.starts_with(colour), "{} after {}", square, step);
+                }
+                None => assert_eq!(shown.piece_type, PieceType::None, "{} after {}", square, step),
+            }
+        }
+    }
+
+    #[test]
+    fn board_follows_random_navigation() {
+        for seed in 0..20 {
+            let mut rng = Lcg(0x2545_f491_4f6c_dd1d ^ seed);
+            let mut game = load(&random_game(&mut rng, 120)).unwrap();
+            assert_board_matches(&game, "loading");
+
+            for step in 0..400 {
+                let step = match rng.below(8) {
+                    0..=2 => {
+                        game.next_move();
+                        format!("next_move (seed {}, step {})", seed, step)
+                    }
+                    3..=4 => {
+                        game.previous_move();
+                        format!("previous_move (seed {}, step {})", seed, step)
+                    }
+                    5..=6 => {
+                        let ply = rng.below(game.get_total_moves() + 2);
+                        game.goto_ply(ply);
+                        format!("goto_ply({}) (seed {}, step {})", ply, seed, step)
+                    }
+                    _ => {
+                        // Branches off into a new variation, or follows an existing move.
+                        if let Some(mv) = rng.legal_move(game.get_position()) {
+                            game.play_move(&mv);
+                        }
+                        format!("play_move (seed {}, step {})", seed, step)
+                    }
+                };
+
+                assert_board_matches(&game, &step);
+            }
+        }
     }
 }
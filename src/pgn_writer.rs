@@ -0,0 +1,283 @@
+use shakmaty::{Chess, Position};
+
+use crate::game_tree::{GameTree, NodeId, ROOT};
+
+/// Movetext lines are wrapped before they get longer than this, as the PGN standard asks.
+const MAX_LINE_LENGTH: usize = 79;
+
+/// Writes a game as PGN: the headers in their original order, then the mainline with every
+/// variation, comment and NAG, ending with the result from the `Result` header.
+pub fn write_game(headers: &[(String, String)], tree: &GameTree, start: &Chess) -> String {
+    let mut pgn = String::new();
+
+    for (key, value) in headers {
+        pgn.push_str(&format!("[{} \"{}\"]\n", key, escape_header(value)));
+    }
+    if !headers.is_empty() {
+        pgn.push('\n');
+    }
+
+    let mut movetext = MovetextWriter::new(start);
+    for comment in &tree.node(ROOT).comments {
+        movetext.comment(comment);
+    }
+    movetext.line(tree, ROOT, true);
+
+    let result = headers.iter()
+        .find(|(key, _)| key == "Result")
+        .map_or("*", |(_, value)| value.as_str());
+    movetext.token(result);
+
+    pgn.push_str(&movetext.finish());
+    pgn
+}
+
+fn escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Lays out movetext tokens, wrapping lines and adding move numbers where a reader needs them:
+/// before White's moves, and before Black's after anything that interrupts the moves.
+struct MovetextWriter {
+    text: String,
+    line_length: usize,
+    after_open_paren: bool,
+    black_starts: bool,
+    first_move_number: u32,
+}
+
+impl MovetextWriter {
+    fn new(start: &Chess) -> Self {
+        MovetextWriter {
+            text: String::new(),
+            line_length: 0,
+            after_open_paren: false,
+            black_starts: start.turn().is_black(),
+            first_move_number: start.fullmoves().get(),
+        }
+    }
+
+    fn token(&mut self, token: &str) {
+        let space = self.line_length > 0 && !self.after_open_paren && token != ")";
+
+        if self.line_length > 0 && self.line_length + usize::from(space) + token.len() > MAX_LINE_LENGTH {
+            self.text.push('\n');
+            self.line_length = 0;
+        } else if space {
+            self.text.push(' ');
+            self.line_length += 1;
+        }
+
+        self.text.push_str(token);
+        self.line_length += token.len();
+        self.after_open_paren = token == "(";
+    }
+
+    /// Comments can't contain a closing brace, so any in the text are dropped.
+    fn comment(&mut self, comment: &str) {
+        let comment = comment.replace('}', "");
+        self.token(&format!("{{{}}}", comment.trim()));
+    }
+
+    /// The moves after `parent` along first children, each followed by the variations that
+    /// replace it. `interrupted` is whether the first move needs its number even if it's Black's.
+    fn line(&mut self, tree: &GameTree, parent: NodeId, interrupted: bool) {
+        let mut parent = parent;
+        let mut interrupted = interrupted;
+
+        while let Some((&main, alternatives)) = tree.node(parent).children.split_first() {
+            interrupted = self.write_move(tree, main, interrupted);
+
+            for &alternative in alternatives {
+                self.token("(");
+                let commented = self.write_move(tree, alternative, true);
+                self.line(tree, alternative, commented);
+                self.token(")");
+                interrupted = true;
+            }
+
+            parent = main;
+        }
+    }
+
+    /// Writes the move at `node` with its comments and NAGs. Returns whether a comment follows
+    /// it, so the next move needs its number repeated.
+    fn write_move(&mut self, tree: &GameTree, node: NodeId, interrupted: bool) -> bool {
+        let game_node = tree.node(node);
+
+        for comment in &game_node.starting_comments {
+            self.comment(comment);
+        }
+
+        // Plies since the last White move of the starting position, so even ones are White's.
+        let ply = game_node.ply - 1 + usize::from(self.black_starts);
+        let move_number = self.first_move_number + (ply / 2) as u32;
+        if ply.is_multiple_of(2) {
+            self.token(&format!("{}.", move_number));
+        } else if interrupted || !game_node.starting_comments.is_empty() {
+            self.token(&format!("{}...", move_number));
+        }
+
+        self.token(&game_node.san);
+        for nag in &game_node.nags {
+            self.token(&nag.to_string());
+        }
+        for comment in &game_node.comments {
+            self.comment(comment);
+        }
+
+        !game_node.comments.is_empty()
+    }
+
+    fn finish(mut self) -> String {
+        self.text.push('\n');
+        self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::ChessBoard;
+    use crate::pgn::ChessGamePlayer;
+
+    fn read(pgn: &str) -> ChessGamePlayer {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn(pgn, 0).unwrap();
+        game
+    }
+
+    fn assert_same_subtree(a: &GameTree, a_node: NodeId, b: &GameTree, b_node: NodeId) {
+        let (a_game_node, b_game_node) = (a.node(a_node), b.node(b_node));
+        assert_eq!(a_game_node.san, b_game_node.san);
+        assert_eq!(a_game_node.ply, b_game_node.ply);
+        assert_eq!(a_game_node.nags, b_game_node.nags, "NAGs of {}", a_game_node.san);
+        assert_eq!(a_game_node.starting_comments, b_game_node.starting_comments, "comments before {}", a_game_node.san);
+        assert_eq!(a_game_node.comments, b_game_node.comments, "comments after {}", a_game_node.san);
+        assert_eq!(a_game_node.children.len(), b_game_node.children.len(), "variations after {}", a_game_node.san);
+
+        for (&a_child, &b_child) in a_game_node.children.iter().zip(&b_game_node.children) {
+            assert_same_subtree(a, a_child, b, b_child);
+        }
+    }
+
+    /// Reads `pgn`, writes it back and reads that again, expecting the same game. Returns the
+    /// written text.
+    fn round_trip(pgn: &str) -> String {
+        let game = read(pgn);
+        let written = game.to_pgn();
+        let reread = read(&written);
+
+        assert_eq!(game.get_headers(), reread.get_headers());
+        assert_eq!(game.start_position(), reread.start_position());
+        assert_same_subtree(game.tree(), ROOT, reread.tree(), ROOT);
+
+        // Writing is stable once the text has been through the writer.
+        assert_eq!(reread.to_pgn(), written);
+        written
+    }
+
+    #[test]
+    fn nested_variations_round_trip() {
+        let written = round_trip(
+            "1. e4 e5 (1... c5 2. Nf3 (2. c3 d5 (2... Nf6)) 2... d6) (1... e6) 2. Nf3 Nc6 *",
+        );
+
+        assert_eq!(
+            written,
+            "1. e4 e5 (1... c5 2. Nf3 (2. c3 d5 (2... Nf6)) 2... d6) (1... e6) 2. Nf3 Nc6 *\n",
+        );
+    }
+
+    #[test]
+    fn comments_round_trip() {
+        let written = round_trip(
+            "{Start} 1. e4 {Solid} e5 ({Or the Sicilian} 1... c5 {sharp}) 2. Nf3 *",
+        );
+
+        assert_eq!(
+            written,
+            "{Start} 1. e4 {Solid} 1... e5 ({Or the Sicilian} 1... c5 {sharp}) 2. Nf3 *\n",
+        );
+    }
+
+    #[test]
+    fn nags_round_trip() {
+        let written = round_trip("1. e4 $1 e5 $2 $14 (1... f6 $4) 2. Qh5 $6 *");
+
+        assert!(written.starts_with("1. e4 $1 e5 $2 $14 (1... f6 $4) 2. Qh5 $6"));
+    }
+
+    #[test]
+    fn eval_and_clock_commands_round_trip() {
+        let game = read(&round_trip(
+            "1. e4 {[%eval 0.32] [%clk 0:05:00]} e5 {[%eval #-3] [%clk 0:04:58]} 2. Nf3 {[%eval -1.05]} *",
+        ));
+
+        let first = game.tree().node(game.tree().node(ROOT).children[0]);
+        assert_eq!(first.comments, ["[%eval 0.32] [%clk 0:05:00]"]);
+    }
+
+    #[test]
+    fn black_moves_are_numbered_after_interruptions() {
+        let written = round_trip("1. e4 {King's pawn} e5 2. Nf3 (2. f4 exf4) Nc6 3. Bc4 $1 Bc5 *");
+
+        assert_eq!(written, "1. e4 {King's pawn} 1... e5 2. Nf3 (2. f4 exf4) 2... Nc6 3. Bc4 $1 Bc5 *\n");
+    }
+
+    #[test]
+    fn black_to_move_start_round_trips() {
+        let written = round_trip(concat!(
+            "[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]\n",
+            "[SetUp \"1\"]\n\n",
+            "1... c5 (1... e5 2. Nf3) 2. Nf3 d6 *",
+        ));
+
+        assert!(written.ends_with("\n\n1... c5 (1... e5 2. Nf3) 2. Nf3 d6 *\n"), "{}", written);
+    }
+
+    #[test]
+    fn late_fen_start_keeps_its_move_numbers() {
+        let written = round_trip(concat!(
+            "[FEN \"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\"]\n\n",
+            "3. Bb5 a6 *",
+        ));
+
+        assert!(written.ends_with("\n\n3. Bb5 a6 *\n"), "{}", written);
+    }
+
+    #[test]
+    fn closing_braces_are_dropped_from_comments() {
+        let game = read(&read("1. e4 e5 *").to_pgn());
+        let mut tree = game.tree().clone();
+        let first = tree.node(ROOT).children[0];
+        tree.node_mut(first).comments.push("a {nested} comment".to_string());
+
+        let written = write_game(game.get_headers(), &tree, game.start_position());
+        assert_eq!(written, "1. e4 {a {nested comment} 1... e5 *\n");
+
+        let reread = read(&written);
+        let first = reread.tree().node(ROOT).children[0];
+        assert_eq!(reread.tree().node(first).comments, ["a {nested comment"]);
+        assert_eq!(reread.tree().node(first).children.len(), 1);
+    }
+
+    #[test]
+    fn long_movetext_is_wrapped() {
+        let written = round_trip(
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 *",
+        );
+
+        assert!(written.lines().count() > 1);
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+    }
+
+    #[test]
+    fn headers_are_escaped() {
+        let headers = vec![("Event".to_string(), "The \"Open\" C:\\Chess".to_string())];
+        let written = write_game(&headers, &GameTree::new(), &Chess::default());
+
+        assert_eq!(written, "[Event \"The \\\"Open\\\" C:\\\\Chess\"]\n\n*\n");
+        assert_eq!(read(&written).get_headers(), headers.as_slice());
+    }
+}
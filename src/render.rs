@@ -0,0 +1,492 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use shakmaty::uci::UciMove;
+use shakmaty::{Board, CastlingMode, Move, Position, Square};
+use tiny_skia::{FillRule, FilterQuality, Paint, PathBuilder, Pixmap, PixmapPaint, PremultipliedColorU8, Rect, Transform};
+
+use crate::board::ChessBoard;
+use crate::cli::RenderOptions;
+use crate::database::PgnDatabase;
+use crate::fen::fen_to_position;
+use crate::graphics::{arrow_outline, piece_image_name, ARROW_COLOR, DARK_SQUARE, HIGHLIGHT_SQUARE, LIGHT_SQUARE, PIECE_IMAGES};
+use crate::pgn::ChessGamePlayer;
+
+/// Cantarell, under the SIL Open Font License (see `resources/Cantarell-OFL.txt`), for
+/// coordinates and captions.
+const FONT: &[u8] = include_bytes!("../resources/Cantarell-Regular.ttf");
+
+pub const DEFAULT_SIZE: u32 = 576;
+
+/// Opacity of arrows, matching the viewer's arrow for the best engine line.
+const ARROW_ALPHA: u8 = 200;
+
+/// A position as drawn on a diagram: the pieces, which way up, the squares of the move that
+/// led to it and any arrows.
+pub struct Diagram<'a> {
+    pub board: &'a Board,
+    pub flipped: bool,
+    pub highlight: Option<(Square, Square)>,
+    pub arrows: &'a [(Square, Square)],
+    pub coordinates: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "svg" => Some(ImageFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// The piece images decoded once for drawing without a window.
+pub struct PieceImages {
+    images: HashMap<String, Pixmap>,
+}
+
+impl PieceImages {
+    pub fn load() -> Result<Self, String> {
+        let images = PIECE_IMAGES.iter()
+            .map(|(name, bytes)| {
+                Pixmap::decode_png(bytes)
+                    .map(|image| (name.to_string(), image))
+                    .map_err(|err| format!("could not decode the {} image: {}", name, err))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PieceImages { images })
+    }
+}
+
+/// Draws `diagram` onto `pixmap` as a board `size` pixels wide with its top left at `x`, `y`.
+pub fn draw_diagram(pixmap: &mut Pixmap, pieces: &PieceImages, diagram: &Diagram, x: f32, y: f32, size: f32) {
+    let grid_size = size / 8.0;
+    let origin = |square: Square| {
+        let (column, row) = display_position(square, diagram.flipped);
+        (x + column as f32 * grid_size, y + row as f32 * grid_size)
+    };
+
+    for square in Square::ALL {
+        let (square_x, square_y) = origin(square);
+        let highlighted = diagram.highlight.is_some_and(|(from, to)| square == from || square == to);
+        let color = square_color(square, highlighted);
+
+        if let Some(rect) = Rect::from_xywh(square_x, square_y, grid_size, grid_size) {
+            pixmap.fill_rect(rect, &paint(color, 255), Transform::identity(), None);
+        }
+
+        if diagram.coordinates {
+            draw_coordinates(pixmap, diagram, square, square_x, square_y, grid_size);
+        }
+
+        let image = diagram.board.piece_at(square)
+            .and_then(|piece| pieces.images.get(&piece_image_name(piece)));
+        if let Some(image) = image {
+            let scale = (grid_size / image.width() as f32).min(grid_size / image.height() as f32);
+            let image_paint = PixmapPaint { quality: FilterQuality::Bicubic, ..PixmapPaint::default() };
+            let transform = Transform::from_row(scale, 0.0, 0.0, scale, square_x, square_y);
+            pixmap.draw_pixmap(0, 0, image.as_ref(), &image_paint, transform, None);
+        }
+    }
+
+    for &(from, to) in diagram.arrows {
+        let center = |square: Square| {
+            let (square_x, square_y) = origin(square);
+            [square_x + grid_size / 2.0, square_y + grid_size / 2.0]
+        };
+
+        let mut path = PathBuilder::new();
+        for (index, [point_x, point_y]) in arrow_outline(center(from), center(to), grid_size / crate::GRID_SIZE).into_iter().enumerate() {
+            if index == 0 {
+                path.move_to(point_x, point_y);
+            } else {
+                path.line_to(point_x, point_y);
+            }
+        }
+        path.close();
+
+        if let Some(path) = path.finish() {
+            pixmap.fill_path(&path, &paint(ARROW_COLOR, ARROW_ALPHA), FillRule::Winding, Transform::identity(), None);
+        }
+    }
+}
+
+/// File letters along the bottom edge and rank numbers along the left edge, in the colour of
+/// the other kind of square so they show on both.
+fn draw_coordinates(pixmap: &mut Pixmap, diagram: &Diagram, square: Square, x: f32, y: f32, grid_size: f32) {
+    let (column, row) = display_position(square, diagram.flipped);
+    let color = square_color(square, false);
+    let label_color = if color == LIGHT_SQUARE { DARK_SQUARE } else { LIGHT_SQUARE };
+    let text_size = grid_size * 0.22;
+
+    if row == 7 {
+        let label = square.file().char().to_string();
+        let label_x = x + grid_size - text_width(&label, text_size) - grid_size * 0.05;
+        draw_text(pixmap, &label, label_x, y + grid_size - grid_size * 0.05, text_size, label_color);
+    }
+    if column == 0 {
+        let label = square.rank().char().to_string();
+        draw_text(pixmap, &label, x + grid_size * 0.05, y + grid_size * 0.05 + text_size * 0.8, text_size, label_color);
+    }
+}
+
+/// Draws the position as a PNG image `size` pixels square.
+pub fn render_png(diagram: &Diagram, pieces: &PieceImages, size: u32) -> Result<Vec<u8>, String> {
+    let mut pixmap = Pixmap::new(size, size).ok_or("the image size must be above zero")?;
+    draw_diagram(&mut pixmap, pieces, diagram, 0.0, 0.0, size as f32);
+
+    pixmap.encode_png().map_err(|err| format!("could not encode the image: {}", err))
+}
+
+/// Draws the position as an SVG image `size` pixels square. Each piece image is embedded once
+/// and placed on its squares with `<use>`.
+pub fn render_svg(diagram: &Diagram, size: u32) -> String {
+    let grid_size = size as f32 / 8.0;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         width=\"{size}\" height=\"{size}\" viewBox=\"0 0 {size} {size}\">\n"
+    );
+
+    svg.push_str("<defs>\n");
+    for (name, bytes) in PIECE_IMAGES {
+        let used = Square::ALL.iter().any(|&square| {
+            diagram.board.piece_at(square).is_some_and(|piece| piece_image_name(piece) == name)
+        });
+        if used {
+            svg.push_str(&format!(
+                "<image id=\"{}\" width=\"{}\" height=\"{}\" xlink:href=\"data:image/png;base64,{}\"/>\n",
+                name, grid_size, grid_size, base64(bytes)
+            ));
+        }
+    }
+    svg.push_str("</defs>\n");
+
+    for square in Square::ALL {
+        let (column, row) = display_position(square, diagram.flipped);
+        let (x, y) = (column as f32 * grid_size, row as f32 * grid_size);
+        let highlighted = diagram.highlight.is_some_and(|(from, to)| square == from || square == to);
+        let color = square_color(square, highlighted);
+
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            x, y, grid_size, grid_size, hex(color)
+        ));
+
+        if diagram.coordinates {
+            let label_color = hex(if square_color(square, false) == LIGHT_SQUARE { DARK_SQUARE } else { LIGHT_SQUARE });
+            let text_size = grid_size * 0.22;
+            if row == 7 {
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-family=\"Cantarell, sans-serif\" font-size=\"{}\" text-anchor=\"end\" fill=\"{}\">{}</text>\n",
+                    x + grid_size * 0.95, y + grid_size * 0.95, text_size, label_color, square.file().char()
+                ));
+            }
+            if column == 0 {
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-family=\"Cantarell, sans-serif\" font-size=\"{}\" fill=\"{}\">{}</text>\n",
+                    x + grid_size * 0.05, y + grid_size * 0.05 + text_size * 0.8, text_size, label_color, square.rank().char()
+                ));
+            }
+        }
+    }
+
+    for square in Square::ALL {
+        if let Some(piece) = diagram.board.piece_at(square) {
+            let (column, row) = display_position(square, diagram.flipped);
+            svg.push_str(&format!(
+                "<use xlink:href=\"#{}\" x=\"{}\" y=\"{}\"/>\n",
+                piece_image_name(piece), column as f32 * grid_size, row as f32 * grid_size
+            ));
+        }
+    }
+
+    let center = |square: Square| {
+        let (column, row) = display_position(square, diagram.flipped);
+        [(column as f32 + 0.5) * grid_size, (row as f32 + 0.5) * grid_size]
+    };
+    for &(from, to) in diagram.arrows {
+        let points: Vec<String> = arrow_outline(center(from), center(to), grid_size / crate::GRID_SIZE)
+            .iter()
+            .map(|[x, y]| format!("{:.1},{:.1}", x, y))
+            .collect();
+        svg.push_str(&format!(
+            "<polygon points=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\"/>\n",
+            points.join(" "), hex(ARROW_COLOR), ARROW_ALPHA as f32 / 255.0
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Where `square` is drawn, as a column and row counted from the top left.
+fn display_position(square: Square, flipped: bool) -> (usize, usize) {
+    let file = square.file() as usize;
+    let rank = square.rank() as usize;
+    if flipped { (7 - file, rank) } else { (file, 7 - rank) }
+}
+
+fn square_color(square: Square, highlighted: bool) -> (u8, u8, u8) {
+    if highlighted {
+        HIGHLIGHT_SQUARE
+    } else if square.is_light() {
+        LIGHT_SQUARE
+    } else {
+        DARK_SQUARE
+    }
+}
+
+fn paint(color: (u8, u8, u8), alpha: u8) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.0, color.1, color.2, alpha);
+    paint.anti_alias = true;
+    paint
+}
+
+fn hex(color: (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+/// How wide `text` is at `size` pixels.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    let Ok(font) = FontRef::try_from_slice(FONT) else {
+        return 0.0;
+    };
+    let font = font.as_scaled(PxScale::from(size));
+    text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum()
+}
+
+/// Draws `text` at `size` pixels with its baseline starting at `x`, `y`.
+pub fn draw_text(pixmap: &mut Pixmap, text: &str, x: f32, y: f32, size: f32, color: (u8, u8, u8)) {
+    let Ok(font) = FontRef::try_from_slice(FONT) else {
+        return;
+    };
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut caret = x;
+
+    for c in text.chars() {
+        let mut glyph = scaled.scaled_glyph(c);
+        glyph.position = point(caret, y);
+        caret += scaled.h_advance(glyph.id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|glyph_x, glyph_y, coverage| {
+            blend_pixel(pixmap, bounds.min.x as i32 + glyph_x as i32, bounds.min.y as i32 + glyph_y as i32, color, coverage);
+        });
+    }
+}
+
+/// Mixes `color` into the pixel at `x`, `y` by `coverage`, 0 to 1.
+fn blend_pixel(pixmap: &mut Pixmap, x: i32, y: i32, color: (u8, u8, u8), coverage: f32) {
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return;
+    }
+
+    let pixels = pixmap.pixels_mut();
+    let pixel = &mut pixels[(y * width + x) as usize];
+    let coverage = coverage.clamp(0.0, 1.0);
+    let mix = |source: u8, destination: u8| (source as f32 * coverage + destination as f32 * (1.0 - coverage)).round() as u8;
+
+    let alpha = mix(255, pixel.alpha());
+    let mixed = PremultipliedColorU8::from_rgba(
+        mix(color.0, pixel.red()).min(alpha),
+        mix(color.1, pixel.green()).min(alpha),
+        mix(color.2, pixel.blue()).min(alpha),
+        alpha,
+    );
+    if let Some(mixed) = mixed {
+        *pixel = mixed;
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (index, &byte)| triple | (byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// The squares a move is drawn between. Castling goes from the king to where it lands rather
+/// than to the rook.
+pub fn move_squares(mv: &Move) -> Option<(Square, Square)> {
+    match mv.to_uci(CastlingMode::Standard) {
+        UciMove::Normal { from, to, .. } => Some((from, to)),
+        _ => None,
+    }
+}
+
+/// Loads game `game_index` of a PGN file for drawing.
+pub fn load_game(pgn_path: &Path, game_index: usize) -> Result<ChessGamePlayer, String> {
+    let pgn = fs::read_to_string(pgn_path)
+        .map_err(|err| format!("could not read {}: {}", pgn_path.display(), err))?;
+    let describe_error = |err| format!("could not load {}: {}", pgn_path.display(), err);
+    let database = PgnDatabase::index(pgn).map_err(describe_error)?;
+
+    let mut game = ChessGamePlayer::new(ChessBoard::new(crate::GRID_SIZE));
+    database.load(game_index, &mut game).map_err(describe_error)?;
+    Ok(game)
+}
+
+/// Renders the position picked by `options` to the output file, as PNG or SVG by its extension.
+pub fn run(options: &RenderOptions) -> Result<(), String> {
+    let output_path = options.output_path.as_deref().ok_or("no output file given")?;
+    let format = ImageFormat::from_path(output_path)
+        .ok_or_else(|| format!("can't tell the image format of {}, use .png or .svg", output_path.display()))?;
+
+    let (board, highlight) = match (&options.fen, &options.pgn_path) {
+        (Some(fen), _) => (fen_to_position(fen)?.board().clone(), None),
+        (None, Some(pgn_path)) => {
+            let mut game = load_game(pgn_path, options.game_index)?;
+            let total_moves = game.get_total_moves();
+            if options.ply > total_moves {
+                return Err(format!("ply {} is out of range, the game has {} plies", options.ply, total_moves));
+            }
+            game.goto_ply(options.ply);
+
+            let last_move = game.tree().node(game.current_node()).mv.as_ref().and_then(move_squares);
+            (game.get_position().board().clone(), last_move)
+        }
+        (None, None) => return Err("no FEN or PGN file given".to_string()),
+    };
+
+    let diagram = Diagram {
+        board: &board,
+        flipped: options.board_flipped,
+        highlight,
+        arrows: &options.arrows,
+        coordinates: !options.hide_coordinates,
+    };
+    let size = options.size.unwrap_or(DEFAULT_SIZE);
+
+    let image = match format {
+        ImageFormat::Png => render_png(&diagram, &PieceImages::load()?, size)?,
+        ImageFormat::Svg => render_svg(&diagram, size).into_bytes(),
+    };
+
+    fs::write(output_path, image).map_err(|err| format!("could not write {}: {}", output_path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    const KINGS: &str = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+
+    fn diagram(board: &Board, flipped: bool) -> Diagram<'_> {
+        Diagram { board, flipped, highlight: None, arrows: &[], coordinates: false }
+    }
+
+    #[test]
+    fn base64_matches_the_rfc_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, expected) in vectors {
+            assert_eq!(base64(input.as_bytes()), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn blending() {
+        let rgba = |pixmap: &Pixmap, x, y| {
+            let pixel = pixmap.pixel(x, y).unwrap();
+            (pixel.red(), pixel.green(), pixel.blue(), pixel.alpha())
+        };
+
+        let mut pixmap = Pixmap::new(2, 2).unwrap();
+        blend_pixel(&mut pixmap, 0, 0, (200, 100, 50), 1.0);
+        blend_pixel(&mut pixmap, 1, 0, (200, 100, 50), 0.5);
+        blend_pixel(&mut pixmap, 0, 1, (200, 100, 50), 3.0);
+        assert_eq!(rgba(&pixmap, 0, 0), (200, 100, 50, 255));
+        assert_eq!(rgba(&pixmap, 1, 0), (100, 50, 25, 128));
+        assert_eq!(rgba(&pixmap, 0, 1), (200, 100, 50, 255));
+        assert_eq!(rgba(&pixmap, 1, 1), (0, 0, 0, 0));
+
+        pixmap.fill(Color::WHITE);
+        blend_pixel(&mut pixmap, 1, 1, (0, 0, 0), 0.5);
+        assert_eq!(rgba(&pixmap, 1, 1), (128, 128, 128, 255));
+
+        // Off the edges is ignored.
+        blend_pixel(&mut pixmap, -1, 0, (0, 0, 0), 1.0);
+        blend_pixel(&mut pixmap, 0, 2, (0, 0, 0), 1.0);
+        assert!(pixmap.pixels().iter().all(|pixel| pixel.alpha() == 255));
+    }
+
+    #[test]
+    fn svg_squares_and_pieces() {
+        let board = fen_to_position(KINGS).unwrap().board().clone();
+
+        let svg = render_svg(&diagram(&board, false), 80);
+        assert_eq!(svg.matches("<rect ").count(), 64);
+        assert!(svg.contains("<rect x=\"0\" y=\"70\" width=\"10\" height=\"10\" fill=\"#b58863\"/>"));
+        assert!(svg.contains("<rect x=\"70\" y=\"70\" width=\"10\" height=\"10\" fill=\"#f0d9b5\"/>"));
+        assert_eq!(svg.matches("<use ").count(), 2);
+        assert!(svg.contains("<use xlink:href=\"#white-king\" x=\"40\" y=\"70\"/>"));
+        assert!(svg.contains("<use xlink:href=\"#black-king\" x=\"40\" y=\"0\"/>"));
+        assert_eq!(svg.matches("<image ").count(), 2);
+        assert!(!svg.contains("id=\"white-queen\""));
+
+        let flipped = render_svg(&diagram(&board, true), 80);
+        assert!(flipped.contains("<rect x=\"70\" y=\"0\" width=\"10\" height=\"10\" fill=\"#b58863\"/>"));
+        assert!(flipped.contains("<use xlink:href=\"#white-king\" x=\"30\" y=\"0\"/>"));
+        assert!(flipped.contains("<use xlink:href=\"#black-king\" x=\"30\" y=\"70\"/>"));
+    }
+
+    #[test]
+    fn png_size_and_squares() {
+        let board = fen_to_position(KINGS).unwrap().board().clone();
+        // Only the squares are checked, so the pieces are left out.
+        let pieces = PieceImages { images: HashMap::new() };
+        let color_at = |png: &[u8], x, y| {
+            let pixel = Pixmap::decode_png(png).unwrap().pixel(x, y).unwrap();
+            (pixel.red(), pixel.green(), pixel.blue())
+        };
+
+        let png = render_png(&diagram(&board, false), &pieces, 80).unwrap();
+        let image = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (80, 80));
+        assert_eq!(color_at(&png, 5, 75), DARK_SQUARE);
+        assert_eq!(color_at(&png, 75, 75), LIGHT_SQUARE);
+
+        let flipped = render_png(&diagram(&board, true), &pieces, 80).unwrap();
+        assert_eq!(color_at(&flipped, 75, 5), DARK_SQUARE);
+
+        let highlighted = Diagram { highlight: Some((Square::E2, Square::E4)), ..diagram(&board, false) };
+        let png = render_png(&highlighted, &pieces, 80).unwrap();
+        assert_eq!(color_at(&png, 45, 45), HIGHLIGHT_SQUARE);
+
+        assert!(render_png(&diagram(&board, false), &pieces, 0).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use shakmaty::uci::UciMove;
+use shakmaty::{Chess, Color, Move, Position, Role};
+
+use crate::config::Config;
+use crate::engine::{Evaluation, StockfishEngine};
+use crate::game_tree::{GameTree, NodeId, ROOT};
+use crate::pgn::ChessGamePlayer;
+use crate::uci::{GoParams, Score, ScoreBound, ScoreValue};
+
+const DEFAULT_REVIEW_DEPTH: u8 = 16;
+
+/// Win chance lost by a move, in percentage points, below which it still counts as excellent,
+/// good, an inaccuracy and a mistake. Anything losing more is a blunder.
+pub const EXCELLENT_LOSS: f64 = 2.0;
+pub const GOOD_LOSS: f64 = 5.0;
+pub const INACCURACY_LOSS: f64 = 10.0;
+pub const MISTAKE_LOSS: f64 = 20.0;
+
+/// A brilliant move can't be played from a position the mover was already winning this
+/// comfortably, and has to leave them at least this well off.
+const BRILLIANT_MAX_WIN_CHANCE: f64 = 90.0;
+const BRILLIANT_MIN_WIN_CHANCE: f64 = 45.0;
+
+/// How a reviewed move compares to the engine's choice. Moves are judged by how much of the
+/// mover's win chance (see `win_chance`) they give away against the engine's best move:
+///
+/// * best: the engine's first choice
+/// * excellent: loses less than `EXCELLENT_LOSS` points
+/// * good: less than `GOOD_LOSS`
+/// * inaccuracy: less than `INACCURACY_LOSS`
+/// * mistake: less than `MISTAKE_LOSS`
+/// * blunder: `MISTAKE_LOSS` or more
+/// * miss: an inaccuracy or mistake replying to the opponent's mistake or blunder, letting
+///   their error go unpunished
+/// * brilliant: a best or excellent move that leaves a piece to be taken for less than it's
+///   worth, when the mover wasn't already completely winning and doesn't end up worse off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Classification {
+    Brilliant,
+    Best,
+    Excellent,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Miss,
+    Blunder,
+}
+
+impl Classification {
+    pub const ALL: [Classification; 8] = [
+        Classification::Brilliant,
+        Classification::Best,
+        Classification::Excellent,
+        Classification::Good,
+        Classification::Inaccuracy,
+        Classification::Mistake,
+        Classification::Miss,
+        Classification::Blunder,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Classification::Brilliant => "Brilliant",
+            Classification::Best => "Best",
+            Classification::Excellent => "Excellent",
+            Classification::Good => "Good",
+            Classification::Inaccuracy => "Inaccuracy",
+            Classification::Mistake => "Mistake",
+            Classification::Miss => "Miss",
+            Classification::Blunder => "Blunder",
+        }
+    }
+
+    /// The text on the classification's badge.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Classification::Brilliant => "!!",
+            Classification::Best => "*",
+            Classification::Excellent => "+",
+            Classification::Good => "=",
+            Classification::Inaccuracy => "?!",
+            Classification::Mistake => "?",
+            Classification::Miss => "x",
+            Classification::Blunder => "??",
+        }
+    }
+}
+
+/// The chance of winning, 0 to 100, for the side `color` given an evaluation. Uses the
+/// logistic curve fitted by Lichess to games between strong players, so a pawn up is about a
+/// 59% chance and mates count as certain wins.
+pub fn win_chance(evaluation: &Evaluation, color: Color) -> f64 {
+    let centipawns = color.fold_wb(1, -1) * evaluation.white_centipawns();
+    100.0 / (1.0 + (-0.00368208 * centipawns as f64).exp())
+}
+
+/// How hard the engine looks at each position of a review: to a fixed depth, number of nodes
+/// or time per position, stopping at whichever comes first. Set with `review_depth`,
+/// `review_nodes` or `review_time` (milliseconds) in the config.
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewSettings {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub time_ms: Option<u64>,
+}
+
+impl ReviewSettings {
+    pub fn from_config(config: &Config) -> Self {
+        let depth = config.get("review_depth").and_then(|depth| depth.parse().ok());
+        let nodes = config.get("review_nodes").and_then(|nodes| nodes.parse().ok());
+        let time_ms = config.get("review_time").and_then(|time| time.parse().ok());
+
+        ReviewSettings {
+            depth: depth.or(if nodes.is_some() || time_ms.is_some() { None } else { Some(DEFAULT_REVIEW_DEPTH) }),
+            nodes,
+            time_ms,
+        }
+    }
+
+    fn go_params(&self) -> GoParams {
+        GoParams {
+            depth: self.depth.map(u32::from),
+            nodes: self.nodes,
+            move_time: self.time_ms,
+            ..GoParams::default()
+        }
+    }
+}
+
+/// What the engine made of one position. `pv` is its main line, starting with `best_move`.
+#[derive(Debug, Clone)]
+pub struct PositionAnalysis {
+    pub evaluation: Evaluation,
+    pub best_move: Option<UciMove>,
+    pub pv: Vec<UciMove>,
+}
+
+/// Runs the engine on `position` until the search ends and returns its final verdict. Game
+/// over positions are scored without asking the engine. Returns `None` if the search is
+/// cancelled, the engine gives no score or it stops running before the search ends.
+pub fn analyse_position(engine: &StockfishEngine, position: &Chess, settings: &ReviewSettings) -> Option<PositionAnalysis> {
+    if position.is_game_over() {
+        let value = if position.is_checkmate() { ScoreValue::Mate(0) } else { ScoreValue::Centipawns(0) };
+        let evaluation = Evaluation {
+            score: Score { value, bound: ScoreBound::Exact },
+            wdl: None,
+            turn: position.turn(),
+        };
+        return Some(PositionAnalysis { evaluation, best_move: None, pv: Vec::new() });
+    }
+
+    let (tx, rx) = mpsc::channel();
+    engine.lock().search(position, settings.go_params(), tx).ok()?;
+
+    let mut evaluation = None;
+    let mut best_move = None;
+    let mut pv = Vec::new();
+
+    for update in rx {
+        evaluation = update.evaluation.or(evaluation);
+        best_move = update.best_move.or(best_move);
+        if let Some(line) = update.lines.into_iter().find(|line| line.multipv == 1) {
+            pv = line.pv;
+        }
+
+        if update.is_final {
+            if engine.lock().has_exited() {
+                return None;
+            }
+            return evaluation.map(|evaluation| PositionAnalysis { evaluation, best_move, pv });
+        }
+    }
+
+    None
+}
+
+pub enum ReviewUpdate {
+    Analysed(NodeId, PositionAnalysis),
+    Finished,
+}
+
+/// A review running in the background, one position at a time.
+pub struct ReviewJob {
+    pub receiver: mpsc::Receiver<ReviewUpdate>,
+    pub total: usize,
+    pub done: usize,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ReviewJob {
+    pub fn start(engine: StockfishEngine, positions: Vec<(NodeId, Chess)>, settings: ReviewSettings) -> Self {
+        let (tx, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let total = positions.len();
+
+        let thread_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            for (node, position) in positions {
+                if thread_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                if let Some(analysis) = analyse_position(&engine, &position, &settings) {
+                    if tx.send(ReviewUpdate::Analysed(node, analysis)).is_err() {
+                        return;
+                    }
+                }
+            }
+
+            let _ = tx.send(ReviewUpdate::Finished);
+        });
+
+        ReviewJob { receiver, total, done: 0, cancelled }
+    }
+
+    pub fn cancel(&self, engine: &StockfishEngine) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        engine.cancel_search();
+    }
+}
+
+/// The engine's analysis of a game's positions and the classification of each move whose
+/// position before and after have both been analysed.
+#[derive(Debug, Default)]
+pub struct GameReview {
+    analyses: HashMap<NodeId, PositionAnalysis>,
+    classifications: HashMap<NodeId, Classification>,
+}
+
+impl GameReview {
+    pub fn clear(&mut self) {
+        self.analyses.clear();
+        self.classifications.clear();
+    }
+
+    pub fn is_analysed(&self, node: NodeId) -> bool {
+        self.analyses.contains_key(&node)
+    }
+
+    pub fn analysis(&self, node: NodeId) -> Option<&PositionAnalysis> {
+        self.analyses.get(&node)
+    }
+
+    pub fn classification(&self, node: NodeId) -> Option<Classification> {
+        self.classifications.get(&node).copied()
+    }
+
+    pub fn classifications(&self) -> &HashMap<NodeId, Classification> {
+        &self.classifications
+    }
+
+    /// Records the analysis of `node` and classifies the moves leading to and from it.
+    pub fn insert(&mut self, node: NodeId, analysis: PositionAnalysis, game: &ChessGamePlayer) {
+        self.analyses.insert(node, analysis);
+
+        let tree = game.tree();
+        self.classify(tree, game, node);
+
+        for &child in &tree.node(node).children {
+            self.classify(tree, game, child);
+            // A miss depends on the move before, so the replies may change too.
+            for &reply in &tree.node(child).children {
+                self.classify(tree, game, reply);
+            }
+        }
+    }
+
+    fn classify(&mut self, tree: &GameTree, game: &ChessGamePlayer, node: NodeId) {
+        let game_node = tree.node(node);
+        let (Some(parent), Some(mv)) = (game_node.parent, game_node.mv.as_ref()) else {
+            return;
+        };
+        let (Some(before), Some(after)) = (self.analyses.get(&parent), self.analyses.get(&node)) else {
+            return;
+        };
+
+        let position = game.position_at(parent);
+        let previous = (parent != ROOT).then(|| self.classification(parent)).flatten();
+
+        self.classifications.insert(node, classify_move(position, mv, before, after, previous));
+    }
+}
+
+/// Classifies `mv`, played in `position`, from the analyses of the positions before and after
+/// it. `previous` is the classification of the opponent's move before it, if known.
+pub fn classify_move(
+    position: &Chess,
+    mv: &Move,
+    before: &PositionAnalysis,
+    after: &PositionAnalysis,
+    previous: Option<Classification>,
+) -> Classification {
+    let mover = position.turn();
+    let best_chance = win_chance(&before.evaluation, mover);
+    let chance = win_chance(&after.evaluation, mover);
+    let loss = (best_chance - chance).max(0.0);
+
+    let is_best = before.best_move.as_ref()
+        .and_then(|best| best.to_move(position).ok())
+        .is_some_and(|best| best == *mv);
+
+    let brilliant = (is_best || loss < EXCELLENT_LOSS)
+        && best_chance < BRILLIANT_MAX_WIN_CHANCE
+        && chance >= BRILLIANT_MIN_WIN_CHANCE
+        && is_sacrifice(position, mv);
+
+    let opponent_erred = matches!(previous, Some(Classification::Mistake | Classification::Blunder));
+
+    if brilliant {
+        Classification::Brilliant
+    } else if is_best {
+        Classification::Best
+    } else {
+        classify_loss(loss, opponent_erred)
+    }
+}
+
+/// Classifies a move other than the engine's choice by the win chance it gives away.
+fn classify_loss(loss: f64, opponent_erred: bool) -> Classification {
+    if loss < EXCELLENT_LOSS {
+        Classification::Excellent
+    } else if loss < GOOD_LOSS {
+        Classification::Good
+    } else if loss < MISTAKE_LOSS && opponent_erred {
+        Classification::Miss
+    } else if loss < INACCURACY_LOSS {
+        Classification::Inaccuracy
+    } else if loss < MISTAKE_LOSS {
+        Classification::Mistake
+    } else {
+        Classification::Blunder
+    }
+}
+
+fn piece_value(role: Role) -> i32 {
+    match role {
+        Role::Pawn => 1,
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::King => 0,
+    }
+}
+
+/// Whether `mv` leaves the piece it moves where the opponent can win it: taken for nothing if
+/// undefended, or by something cheaper. Pawns and kings are never sacrifices.
+fn is_sacrifice(position: &Chess, mv: &Move) -> bool {
+    let role = mv.promotion().unwrap_or(mv.role());
+    if matches!(role, Role::Pawn | Role::King) {
+        return false;
+    }
+
+    let mover = position.turn();
+    let mut after = position.clone();
+    after.play_unchecked(mv);
+
+    let to = mv.to();
+    let cheapest_taker = after.legal_moves().iter()
+        .filter(|reply| reply.to() == to && reply.is_capture())
+        .map(|reply| piece_value(reply.role()))
+        .min();
+    let Some(cheapest_taker) = cheapest_taker else {
+        return false;
+    };
+
+    let board = after.board();
+    let defended = board.attacks_to(to, mover, board.occupied()).any();
+    let at_risk = if defended { piece_value(role) - cheapest_taker } else { piece_value(role) };
+    let gained = mv.capture().map_or(0, piece_value);
+
+    at_risk - gained >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::board::ChessBoard;
+
+    fn evaluation(turn: Color, value: ScoreValue) -> Evaluation {
+        Evaluation { score: Score { value, bound: ScoreBound::Exact }, wdl: None, turn }
+    }
+
+    /// An analysis of `position` scoring it `white_cp` for White, with `best` as the engine's move.
+    fn analysis(position: &Chess, white_cp: i32, best: &str) -> PositionAnalysis {
+        let turn = position.turn();
+        PositionAnalysis {
+            evaluation: evaluation(turn, ScoreValue::Centipawns(turn.fold_wb(white_cp, -white_cp))),
+            best_move: Some(best.parse().unwrap()),
+            pv: Vec::new(),
+        }
+    }
+
+    fn game(pgn: &str) -> ChessGamePlayer {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn(pgn, 0).unwrap();
+        game
+    }
+
+    /// Classifies the last move of `pgn` with the position before it scored `before_cp` and the
+    /// one after `after_cp`, both for White.
+    fn classify_last(pgn: &str, before_cp: i32, best: &str, after_cp: i32, previous: Option<Classification>) -> Classification {
+        let game = game(pgn);
+        let tree = game.tree();
+        let node = tree.mainline_from(ROOT).last().unwrap();
+        let parent = tree.node(node).parent.unwrap();
+        let (before, after) = (game.position_at(parent), game.position_at(node));
+
+        classify_move(
+            before,
+            tree.node(node).mv.as_ref().unwrap(),
+            &analysis(before, before_cp, best),
+            &analysis(after, after_cp, "a2a3"),
+            previous,
+        )
+    }
+
+    #[test]
+    fn win_chance_of_mates() {
+        let checkmated = evaluation(Color::White, ScoreValue::Mate(0));
+        assert!(win_chance(&checkmated, Color::White) < 0.01);
+        assert!(win_chance(&checkmated, Color::Black) > 99.99);
+
+        let checkmated = evaluation(Color::Black, ScoreValue::Mate(0));
+        assert!(win_chance(&checkmated, Color::Black) < 0.01);
+        assert!(win_chance(&checkmated, Color::White) > 99.99);
+
+        let mating = evaluation(Color::Black, ScoreValue::Mate(3));
+        assert!(win_chance(&mating, Color::Black) > 99.99);
+        let mated = evaluation(Color::Black, ScoreValue::Mate(-3));
+        assert!(win_chance(&mated, Color::White) > 99.99);
+
+        let even = evaluation(Color::White, ScoreValue::Centipawns(0));
+        assert_eq!(win_chance(&even, Color::White), 50.0);
+        assert_eq!(win_chance(&even, Color::Black), 50.0);
+    }
+
+    #[test]
+    fn loss_thresholds() {
+        let cases = [
+            (0.0, Classification::Excellent),
+            (1.99, Classification::Excellent),
+            (2.0, Classification::Good),
+            (4.99, Classification::Good),
+            (5.0, Classification::Inaccuracy),
+            (9.99, Classification::Inaccuracy),
+            (10.0, Classification::Mistake),
+            (19.99, Classification::Mistake),
+            (20.0, Classification::Blunder),
+            (60.0, Classification::Blunder),
+        ];
+
+        for (loss, expected) in cases {
+            assert_eq!(classify_loss(loss, false), expected, "loss {}", loss);
+        }
+    }
+
+    #[test]
+    fn loss_thresholds_after_an_opponent_error() {
+        let cases = [
+            (1.99, Classification::Excellent),
+            (2.0, Classification::Good),
+            (4.99, Classification::Good),
+            (5.0, Classification::Miss),
+            (10.0, Classification::Miss),
+            (19.99, Classification::Miss),
+            (20.0, Classification::Blunder),
+        ];
+
+        for (loss, expected) in cases {
+            assert_eq!(classify_loss(loss, true), expected, "loss {}", loss);
+        }
+    }
+
+    #[test]
+    fn moves_are_classified_by_the_win_chance_they_lose() {
+        // From an even position each pair of scores falls just either side of a threshold.
+        let cases = [
+            (-21, Classification::Excellent),
+            (-22, Classification::Good),
+            (-54, Classification::Good),
+            (-55, Classification::Inaccuracy),
+            (-110, Classification::Inaccuracy),
+            (-111, Classification::Mistake),
+            (-230, Classification::Mistake),
+            (-231, Classification::Blunder),
+            (40, Classification::Excellent),
+        ];
+
+        for (after_cp, expected) in cases {
+            assert_eq!(classify_last("1. e4 *", 0, "d2d4", after_cp, None), expected, "{} after 1. e4", after_cp);
+        }
+
+        // The engine's move is best however the position after it is scored.
+        assert_eq!(classify_last("1. e4 *", 0, "e2e4", -231, None), Classification::Best);
+
+        // Black's losses are measured from Black's side.
+        assert_eq!(classify_last("1. e4 e5 *", 0, "c7c5", 22, None), Classification::Good);
+        assert_eq!(classify_last("1. e4 e5 *", 0, "c7c5", -300, None), Classification::Excellent);
+    }
+
+    #[test]
+    fn missing_an_opponent_error() {
+        let pgn = "1. e4 e5 2. Nf3 *";
+
+        assert_eq!(classify_last(pgn, 500, "d1h5", 300, Some(Classification::Blunder)), Classification::Miss);
+        assert_eq!(classify_last(pgn, 500, "d1h5", 300, Some(Classification::Mistake)), Classification::Miss);
+        assert_eq!(classify_last(pgn, 500, "d1h5", 300, Some(Classification::Inaccuracy)), Classification::Mistake);
+        assert_eq!(classify_last(pgn, 500, "d1h5", 300, None), Classification::Mistake);
+        assert_eq!(classify_last(pgn, 500, "d1h5", 0, Some(Classification::Blunder)), Classification::Blunder);
+    }
+
+    #[test]
+    fn review_finds_a_miss_after_a_blunder() {
+        let game = game("1. e4 e5 2. Nf3 *");
+        let tree = game.tree();
+        let nodes: Vec<NodeId> = iter::once(ROOT).chain(tree.mainline_from(ROOT)).collect();
+        let analyses = [(0, "e2e4"), (0, "c7c5"), (500, "d1h5"), (300, "b8c6")];
+        let scored = |index: usize| analysis(game.position_at(nodes[index]), analyses[index].0, analyses[index].1);
+
+        // The reply is reclassified once the blunder before it is known, whatever the order.
+        for order in [[0, 1, 2, 3], [3, 2, 1, 0], [2, 3, 0, 1]] {
+            let mut review = GameReview::default();
+            for index in order {
+                review.insert(nodes[index], scored(index), &game);
+            }
+
+            assert_eq!(review.classification(nodes[1]), Some(Classification::Best));
+            assert_eq!(review.classification(nodes[2]), Some(Classification::Blunder));
+            assert_eq!(review.classification(nodes[3]), Some(Classification::Miss));
+        }
+    }
+
+    #[test]
+    fn sacrifices() {
+        let last_move = |pgn: &str| {
+            let game = game(pgn);
+            let node = game.tree().mainline_from(ROOT).last().unwrap();
+            let position = game.position_at(game.tree().node(node).parent.unwrap()).clone();
+            (position, game.tree().node(node).mv.clone().unwrap())
+        };
+        let is_sacrifice = |pgn: &str| {
+            let (position, mv) = last_move(pgn);
+            is_sacrifice(&position, &mv)
+        };
+
+        // A bishop for a pawn, taken by the king.
+        assert!(is_sacrifice("1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Bxf7+ *"));
+        // A knight for a pawn, with nothing to take back.
+        assert!(is_sacrifice("1. e4 e5 2. Nf3 Nc6 3. Nxe5 *"));
+        // A knight offered to a pawn, even though the d-pawn takes back.
+        assert!(is_sacrifice("1. d4 d6 2. Nf3 Nf6 3. Ne5 *"));
+        // A knight offered to a knight, defended by the e-pawn: an even trade.
+        assert!(!is_sacrifice("1. e4 e5 2. Nf3 Nf6 3. Nc3 Nc6 4. Nd5 *"));
+        // A bishop taking a knight: an even trade.
+        assert!(!is_sacrifice("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6 *"));
+        // Nothing can take it.
+        assert!(!is_sacrifice("1. e4 e5 2. Nf3 *"));
+        // Pawns are never sacrificed.
+        assert!(!is_sacrifice("1. e4 d5 *"));
+    }
+
+    #[test]
+    fn brilliant_sacrifices() {
+        let sacrifice = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Bxf7+ *";
+        let trade = "1. e4 e5 2. Nf3 Nf6 3. Nc3 Nc6 4. Nd5 *";
+
+        assert_eq!(classify_last(sacrifice, 0, "c4f7", 50, None), Classification::Brilliant);
+        assert_eq!(classify_last(trade, 0, "c3d5", 50, None), Classification::Best);
+
+        // Close enough to the engine's move still counts, but not a bigger loss.
+        assert_eq!(classify_last(sacrifice, 0, "e1g1", -10, None), Classification::Brilliant);
+        assert_eq!(classify_last(sacrifice, 0, "e1g1", -30, None), Classification::Good);
+
+        // Not from a position that was already won, nor into one that's lost.
+        assert_eq!(classify_last(sacrifice, 800, "c4f7", 800, None), Classification::Best);
+        assert_eq!(classify_last(sacrifice, -300, "c4f7", -300, None), Classification::Best);
+    }
+}
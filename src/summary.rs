@@ -0,0 +1,239 @@
+use shakmaty::{Chess, Color, Position};
+
+use crate::engine::Evaluation;
+use crate::game_tree::ROOT;
+use crate::pgn::ChessGamePlayer;
+use crate::review::{win_chance, Classification, GameReview};
+
+/// Centipawn losses are counted against evaluations capped at this, so a mate or a won
+/// position thrown away costs at most ten pawns.
+const MAX_CENTIPAWNS: i32 = 1000;
+
+/// The opening lasts at most this many plies.
+const OPENING_PLIES: usize = 20;
+
+/// The endgame starts once this few queens, rooks, bishops and knights are left in total.
+const ENDGAME_PIECES: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Opening, Phase::Middlegame, Phase::Endgame];
+
+    /// The phase a move played in `position`, `ply` plies into the game, belongs to.
+    pub fn of(position: &Chess, ply: usize) -> Phase {
+        let board = position.board();
+        let pieces = (board.occupied() & !board.pawns() & !board.kings()).count() as u32;
+
+        if pieces <= ENDGAME_PIECES {
+            Phase::Endgame
+        } else if ply < OPENING_PLIES {
+            Phase::Opening
+        } else {
+            Phase::Middlegame
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::Opening => "Opening",
+            Phase::Middlegame => "Middlegame",
+            Phase::Endgame => "Endgame",
+        }
+    }
+}
+
+/// How accurate a move was, 0 to 100, from the mover's win chance before and after it. This is
+/// Lichess's curve: no loss scores 100, losing 10 points of win chance about 64 and 30 points
+/// about 25.
+pub fn move_accuracy(chance_before: f64, chance_after: f64) -> f64 {
+    let loss = (chance_before - chance_after).max(0.0);
+    (103.1668 * (-0.04354 * loss).exp() - 3.1668).clamp(0.0, 100.0)
+}
+
+/// Centipawns for `color`, capped at `MAX_CENTIPAWNS` either way.
+fn capped_centipawns(evaluation: &Evaluation, color: Color) -> i32 {
+    (color.fold_wb(1, -1) * evaluation.white_centipawns()).clamp(-MAX_CENTIPAWNS, MAX_CENTIPAWNS)
+}
+
+/// Accuracy and centipawn loss over a set of moves.
+#[derive(Debug, Clone, Default)]
+pub struct MoveStats {
+    accuracies: Vec<f64>,
+    centipawn_losses: Vec<i32>,
+}
+
+impl MoveStats {
+    fn add(&mut self, accuracy: f64, centipawn_loss: i32) {
+        self.accuracies.push(accuracy);
+        self.centipawn_losses.push(centipawn_loss);
+    }
+
+    pub fn moves(&self) -> usize {
+        self.accuracies.len()
+    }
+
+    /// The mean of the moves' accuracies.
+    pub fn accuracy(&self) -> Option<f64> {
+        (!self.accuracies.is_empty())
+            .then(|| self.accuracies.iter().sum::<f64>() / self.accuracies.len() as f64)
+    }
+
+    /// Average centipawn loss.
+    pub fn acpl(&self) -> Option<f64> {
+        (!self.centipawn_losses.is_empty())
+            .then(|| self.centipawn_losses.iter().sum::<i32>() as f64 / self.centipawn_losses.len() as f64)
+    }
+}
+
+/// One player's numbers for the reviewed moves of a game.
+#[derive(Debug, Clone, Default)]
+pub struct SideSummary {
+    pub name: String,
+    pub overall: MoveStats,
+    /// Indexed like `Phase::ALL`.
+    pub phases: [MoveStats; 3],
+    /// Indexed like `Classification::ALL`.
+    pub counts: [usize; 8],
+}
+
+impl SideSummary {
+    pub fn phase(&self, phase: Phase) -> &MoveStats {
+        &self.phases[phase as usize]
+    }
+
+    pub fn count(&self, classification: Classification) -> usize {
+        self.counts[classification as usize]
+    }
+}
+
+/// Per-player accuracy, average centipawn loss and classification counts over the mainline
+/// moves the review has covered so far.
+#[derive(Debug, Clone)]
+pub struct GameSummary {
+    pub white: SideSummary,
+    pub black: SideSummary,
+    pub reviewed_moves: usize,
+    pub total_moves: usize,
+}
+
+impl GameSummary {
+    pub fn new(game: &ChessGamePlayer, review: &GameReview) -> Self {
+        let header = |name: &str| {
+            game.get_headers().iter()
+                .find(|(key, _)| key == name)
+                .map_or_else(|| name.to_string(), |(_, value)| value.clone())
+        };
+
+        let mut white = SideSummary { name: header("White"), ..SideSummary::default() };
+        let mut black = SideSummary { name: header("Black"), ..SideSummary::default() };
+        let mut reviewed_moves = 0;
+        let mut total_moves = 0;
+
+        let tree = game.tree();
+        let mut parent = ROOT;
+
+        for node in tree.mainline_from(ROOT) {
+            total_moves += 1;
+            let position = game.position_at(parent);
+            let mover = position.turn();
+
+            let analyses = review.analysis(parent).zip(review.analysis(node));
+            let classification = review.classification(node);
+
+            if let (Some((before, after)), Some(classification)) = (analyses, classification) {
+                reviewed_moves += 1;
+
+                let accuracy = move_accuracy(win_chance(&before.evaluation, mover), win_chance(&after.evaluation, mover));
+                let centipawn_loss = (capped_centipawns(&before.evaluation, mover) - capped_centipawns(&after.evaluation, mover)).max(0);
+
+                let side = mover.fold_wb(&mut white, &mut black);
+                side.overall.add(accuracy, centipawn_loss);
+                side.phases[Phase::of(position, tree.depth(parent)) as usize].add(accuracy, centipawn_loss);
+                side.counts[classification as usize] += 1;
+            }
+
+            parent = node;
+        }
+
+        GameSummary { white, black, reviewed_moves, total_moves }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::board::ChessBoard;
+    use crate::game_tree::NodeId;
+    use crate::review::PositionAnalysis;
+    use crate::uci::{Score, ScoreBound, ScoreValue};
+
+    /// An analysis of `position` scoring it `white_cp` for White, with `best` as the engine's move.
+    fn analysis(position: &Chess, white_cp: i32, best: &str) -> PositionAnalysis {
+        let turn = position.turn();
+        let score = Score { value: ScoreValue::Centipawns(turn.fold_wb(white_cp, -white_cp)), bound: ScoreBound::Exact };
+        PositionAnalysis {
+            evaluation: Evaluation { score, wdl: None, turn },
+            best_move: Some(best.parse().unwrap()),
+            pv: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accuracy_curve() {
+        assert_eq!(move_accuracy(50.0, 50.0), 100.0);
+        assert_eq!(move_accuracy(70.0, 70.0), 100.0);
+        // Gaining win chance is no better than keeping it.
+        assert_eq!(move_accuracy(40.0, 60.0), 100.0);
+
+        assert!((move_accuracy(60.0, 50.0) - 63.6).abs() < 0.05, "{}", move_accuracy(60.0, 50.0));
+        assert!((move_accuracy(80.0, 50.0) - 24.8).abs() < 0.05, "{}", move_accuracy(80.0, 50.0));
+        assert_eq!(move_accuracy(100.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn summary_per_side() {
+        let mut game = ChessGamePlayer::new(ChessBoard::new(72.0));
+        game.load_pgn("[White \"Alice\"]\n[Black \"Bob\"]\n\n1. e4 e5 2. Nf3 Nc6 *", 0).unwrap();
+        let nodes: Vec<NodeId> = iter::once(ROOT).chain(game.tree().mainline_from(ROOT)).collect();
+
+        // 1. e4 is the engine's move, 1... e5 gives White a pawn and 2. Nf3 gives most of it
+        // back. The position after 2. Nf3 is left unanalysed, so 2... Nc6 isn't reviewed.
+        let mut review = GameReview::default();
+        for (index, (white_cp, best)) in [(0, "e2e4"), (0, "c7c5"), (100, "d2d4"), (40, "b8c6")].into_iter().enumerate() {
+            review.insert(nodes[index], analysis(game.position_at(nodes[index]), white_cp, best), &game);
+        }
+
+        let summary = GameSummary::new(&game, &review);
+        assert_eq!((summary.reviewed_moves, summary.total_moves), (3, 4));
+        assert_eq!(summary.white.name, "Alice");
+        assert_eq!(summary.black.name, "Bob");
+
+        let white_chance = |white_cp: i32| 100.0 / (1.0 + (-0.00368208 * white_cp as f64).exp());
+        let nf3_accuracy = move_accuracy(white_chance(100), white_chance(40));
+        let e5_accuracy = move_accuracy(100.0 - white_chance(0), 100.0 - white_chance(100));
+
+        let white = &summary.white;
+        assert_eq!(white.overall.moves(), 2);
+        assert!((white.overall.accuracy().unwrap() - (100.0 + nf3_accuracy) / 2.0).abs() < 1e-9);
+        assert_eq!(white.overall.acpl(), Some(30.0));
+        assert_eq!(white.phase(Phase::Opening).moves(), 2);
+        assert_eq!(white.phase(Phase::Middlegame).accuracy(), None);
+        assert_eq!(white.count(Classification::Best), 1);
+        assert_eq!(white.count(Classification::Inaccuracy), 1);
+
+        let black = &summary.black;
+        assert_eq!(black.overall.moves(), 1);
+        assert!((black.overall.accuracy().unwrap() - e5_accuracy).abs() < 1e-9);
+        assert_eq!(black.overall.acpl(), Some(100.0));
+        assert_eq!(black.count(Classification::Inaccuracy), 1);
+        assert_eq!(black.counts.iter().sum::<usize>(), 1);
+    }
+}
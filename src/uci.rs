@@ -0,0 +1,887 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::uci::UciMove;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciParseError {
+    pub line: String,
+    pub reason: String,
+}
+
+impl UciParseError {
+    fn new(line: &str, reason: impl Into<String>) -> Self {
+        UciParseError { line: line.to_string(), reason: reason.into() }
+    }
+}
+
+impl fmt::Display for UciParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in '{}'", self.reason, self.line)
+    }
+}
+
+impl std::error::Error for UciParseError {}
+
+/// Messages sent from the GUI to the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciCommand {
+    Uci,
+    Debug(bool),
+    IsReady,
+    SetOption { name: String, value: Option<String> },
+    UciNewGame,
+    Position { fen: Option<String>, moves: Vec<UciMove> },
+    Go(GoParams),
+    Stop,
+    Quit,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoParams {
+    pub search_moves: Vec<UciMove>,
+    pub ponder: bool,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub moves_to_go: Option<u32>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub move_time: Option<u64>,
+    pub infinite: bool,
+}
+
+/// Messages sent from the engine to the GUI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciMessage {
+    IdName(String),
+    IdAuthor(String),
+    UciOk,
+    ReadyOk,
+    BestMove { best_move: Option<UciMove>, ponder: Option<UciMove> },
+    CopyProtection(ProtectionState),
+    Registration(ProtectionState),
+    Option(UciOption),
+    Info(Info),
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectionState {
+    Checking,
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: String,
+    pub kind: OptionKind,
+    pub default: Option<String>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub vars: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    Check,
+    Spin,
+    Combo,
+    Button,
+    String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreValue {
+    Centipawns(i32),
+    Mate(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreBound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+/// A score as reported by the engine, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    pub value: ScoreValue,
+    pub bound: ScoreBound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wdl {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub time: Option<u64>,
+    pub nodes: Option<u64>,
+    pub pv: Vec<UciMove>,
+    pub multipv: Option<u32>,
+    pub score: Option<Score>,
+    pub wdl: Option<Wdl>,
+    pub currmove: Option<UciMove>,
+    pub currmovenumber: Option<u32>,
+    pub hashfull: Option<u32>,
+    pub nps: Option<u64>,
+    pub tbhits: Option<u64>,
+    pub sbhits: Option<u64>,
+    pub cpuload: Option<u32>,
+    pub refutation: Vec<UciMove>,
+    pub currline: Option<CurrLine>,
+    pub string: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrLine {
+    pub cpu: Option<u32>,
+    pub moves: Vec<UciMove>,
+}
+
+struct Tokens<'a> {
+    line: &'a str,
+    tokens: Vec<&'a str>,
+    index: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a str) -> Self {
+        Tokens { line, tokens: line.split_whitespace().collect(), index: 0 }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.index).copied();
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.index).copied()
+    }
+
+    fn error(&self, reason: impl Into<String>) -> UciParseError {
+        UciParseError::new(self.line, reason)
+    }
+
+    fn value<T: FromStr>(&mut self, field: &str) -> Result<T, UciParseError> {
+        let token = self.next().ok_or_else(|| self.error(format!("missing value for '{}'", field)))?;
+        token.parse().map_err(|_| self.error(format!("invalid value '{}' for '{}'", token, field)))
+    }
+
+    fn uci_move(&mut self, field: &str) -> Result<UciMove, UciParseError> {
+        let token = self.next().ok_or_else(|| self.error(format!("missing move for '{}'", field)))?;
+        token.parse().map_err(|_| self.error(format!("invalid move '{}' for '{}'", token, field)))
+    }
+
+    /// Consumes tokens for as long as they are moves.
+    fn moves(&mut self) -> Vec<UciMove> {
+        let mut moves = Vec::new();
+        while let Some(mv) = self.peek().and_then(|token| token.parse::<UciMove>().ok()) {
+            moves.push(mv);
+            self.index += 1;
+        }
+        moves
+    }
+
+    /// Joins tokens up to (not including) the first one in `stop_words`.
+    fn words_until(&mut self, stop_words: &[&str]) -> String {
+        let mut words = Vec::new();
+        while let Some(token) = self.peek() {
+            if stop_words.contains(&token) {
+                break;
+            }
+            words.push(token);
+            self.index += 1;
+        }
+        words.join(" ")
+    }
+
+    fn rest(&mut self) -> String {
+        self.words_until(&[])
+    }
+}
+
+impl UciCommand {
+    pub fn parse(line: &str) -> Result<UciCommand, UciParseError> {
+        let mut tokens = Tokens::new(line);
+
+        let command = match tokens.next() {
+            Some("uci") => UciCommand::Uci,
+            Some("debug") => match tokens.next() {
+                Some("on") => UciCommand::Debug(true),
+                Some("off") => UciCommand::Debug(false),
+                _ => return Err(tokens.error("expected 'on' or 'off'")),
+            },
+            Some("isready") => UciCommand::IsReady,
+            Some("setoption") => {
+                if tokens.next() != Some("name") {
+                    return Err(tokens.error("expected 'name'"));
+                }
+                let name = tokens.words_until(&["value"]);
+                let value = tokens.next().map(|_| tokens.rest());
+                UciCommand::SetOption { name, value }
+            }
+            Some("ucinewgame") => UciCommand::UciNewGame,
+            Some("position") => {
+                let fen = match tokens.next() {
+                    Some("startpos") => None,
+                    Some("fen") => Some(tokens.words_until(&["moves"])),
+                    _ => return Err(tokens.error("expected 'startpos' or 'fen'")),
+                };
+                let moves = match tokens.next() {
+                    Some("moves") => tokens.moves(),
+                    Some(token) => return Err(tokens.error(format!("unexpected token '{}'", token))),
+                    None => Vec::new(),
+                };
+                UciCommand::Position { fen, moves }
+            }
+            Some("go") => UciCommand::Go(Self::parse_go(&mut tokens)?),
+            Some("stop") => UciCommand::Stop,
+            Some("quit") => UciCommand::Quit,
+            Some(token) => return Err(tokens.error(format!("unknown command '{}'", token))),
+            None => return Err(tokens.error("empty command")),
+        };
+
+        Ok(command)
+    }
+
+    fn parse_go(tokens: &mut Tokens) -> Result<GoParams, UciParseError> {
+        let mut params = GoParams::default();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "searchmoves" => params.search_moves = tokens.moves(),
+                "ponder" => params.ponder = true,
+                "wtime" => params.wtime = Some(tokens.value(token)?),
+                "btime" => params.btime = Some(tokens.value(token)?),
+                "winc" => params.winc = Some(tokens.value(token)?),
+                "binc" => params.binc = Some(tokens.value(token)?),
+                "movestogo" => params.moves_to_go = Some(tokens.value(token)?),
+                "depth" => params.depth = Some(tokens.value(token)?),
+                "nodes" => params.nodes = Some(tokens.value(token)?),
+                "mate" => params.mate = Some(tokens.value(token)?),
+                "movetime" => params.move_time = Some(tokens.value(token)?),
+                "infinite" => params.infinite = true,
+                _ => return Err(tokens.error(format!("unknown go parameter '{}'", token))),
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+impl fmt::Display for UciCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciCommand::Uci => write!(f, "uci"),
+            UciCommand::Debug(on) => write!(f, "debug {}", if *on { "on" } else { "off" }),
+            UciCommand::IsReady => write!(f, "isready"),
+            UciCommand::SetOption { name, value } => {
+                write!(f, "setoption name {}", name)?;
+                if let Some(value) = value {
+                    write!(f, " value {}", value)?;
+                }
+                Ok(())
+            }
+            UciCommand::UciNewGame => write!(f, "ucinewgame"),
+            UciCommand::Position { fen, moves } => {
+                match fen {
+                    Some(fen) => write!(f, "position fen {}", fen)?,
+                    None => write!(f, "position startpos")?,
+                }
+                if !moves.is_empty() {
+                    write!(f, " moves")?;
+                    write_moves(f, moves)?;
+                }
+                Ok(())
+            }
+            UciCommand::Go(params) => {
+                write!(f, "go")?;
+                if !params.search_moves.is_empty() {
+                    write!(f, " searchmoves")?;
+                    write_moves(f, &params.search_moves)?;
+                }
+                if params.ponder {
+                    write!(f, " ponder")?;
+                }
+                write_field(f, "wtime", params.wtime)?;
+                write_field(f, "btime", params.btime)?;
+                write_field(f, "winc", params.winc)?;
+                write_field(f, "binc", params.binc)?;
+                write_field(f, "movestogo", params.moves_to_go)?;
+                write_field(f, "depth", params.depth)?;
+                write_field(f, "nodes", params.nodes)?;
+                write_field(f, "mate", params.mate)?;
+                write_field(f, "movetime", params.move_time)?;
+                if params.infinite {
+                    write!(f, " infinite")?;
+                }
+                Ok(())
+            }
+            UciCommand::Stop => write!(f, "stop"),
+            UciCommand::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl UciMessage {
+    /// Parses one line of engine output. Lines that don't start with a known keyword are
+    /// returned as `Unknown`; known messages with missing or invalid fields are an error.
+    pub fn parse(line: &str) -> Result<UciMessage, UciParseError> {
+        let mut tokens = Tokens::new(line);
+
+        let message = match tokens.next() {
+            Some("id") => match tokens.next() {
+                Some("name") => UciMessage::IdName(tokens.rest()),
+                Some("author") => UciMessage::IdAuthor(tokens.rest()),
+                _ => return Err(tokens.error("expected 'name' or 'author'")),
+            },
+            Some("uciok") => UciMessage::UciOk,
+            Some("readyok") => UciMessage::ReadyOk,
+            Some("bestmove") => {
+                let best_move = match tokens.next() {
+                    Some("(none)") | Some("0000") => None,
+                    Some(token) => Some(token.parse().map_err(|_| tokens.error(format!("invalid move '{}'", token)))?),
+                    None => return Err(tokens.error("missing best move")),
+                };
+                let ponder = match tokens.next() {
+                    Some("ponder") => Some(tokens.uci_move("ponder")?),
+                    Some(token) => return Err(tokens.error(format!("unexpected token '{}'", token))),
+                    None => None,
+                };
+                UciMessage::BestMove { best_move, ponder }
+            }
+            Some("copyprotection") => UciMessage::CopyProtection(Self::parse_protection(&mut tokens)?),
+            Some("registration") => UciMessage::Registration(Self::parse_protection(&mut tokens)?),
+            Some("option") => UciMessage::Option(Self::parse_option(&mut tokens)?),
+            Some("info") => UciMessage::Info(Self::parse_info(&mut tokens)?),
+            _ => UciMessage::Unknown(line.to_string()),
+        };
+
+        Ok(message)
+    }
+
+    fn parse_protection(tokens: &mut Tokens) -> Result<ProtectionState, UciParseError> {
+        match tokens.next() {
+            Some("checking") => Ok(ProtectionState::Checking),
+            Some("ok") => Ok(ProtectionState::Ok),
+            Some("error") => Ok(ProtectionState::Error),
+            _ => Err(tokens.error("expected 'checking', 'ok' or 'error'")),
+        }
+    }
+
+    fn parse_option(tokens: &mut Tokens) -> Result<UciOption, UciParseError> {
+        const KEYWORDS: &[&str] = &["name", "type", "default", "min", "max", "var"];
+
+        let mut name = None;
+        let mut kind = None;
+        let mut option = UciOption {
+            name: String::new(),
+            kind: OptionKind::Button,
+            default: None,
+            min: None,
+            max: None,
+            vars: Vec::new(),
+        };
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "name" => name = Some(tokens.words_until(&["type"])),
+                "type" => kind = Some(match tokens.next() {
+                    Some("check") => OptionKind::Check,
+                    Some("spin") => OptionKind::Spin,
+                    Some("combo") => OptionKind::Combo,
+                    Some("button") => OptionKind::Button,
+                    Some("string") => OptionKind::String,
+                    _ => return Err(tokens.error("invalid option type")),
+                }),
+                "default" => option.default = Some(tokens.words_until(KEYWORDS)),
+                "min" => option.min = Some(tokens.value(token)?),
+                "max" => option.max = Some(tokens.value(token)?),
+                "var" => option.vars.push(tokens.words_until(KEYWORDS)),
+                _ => return Err(tokens.error(format!("unexpected token '{}'", token))),
+            }
+        }
+
+        option.name = name.ok_or_else(|| tokens.error("option without a name"))?;
+        option.kind = kind.ok_or_else(|| tokens.error("option without a type"))?;
+        Ok(option)
+    }
+
+    fn parse_info(tokens: &mut Tokens) -> Result<Info, UciParseError> {
+        let mut info = Info::default();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => info.depth = Some(tokens.value(token)?),
+                "seldepth" => info.seldepth = Some(tokens.value(token)?),
+                "time" => info.time = Some(tokens.value(token)?),
+                "nodes" => info.nodes = Some(tokens.value(token)?),
+                "pv" => info.pv = tokens.moves(),
+                "multipv" => info.multipv = Some(tokens.value(token)?),
+                "score" => info.score = Some(Self::parse_score(tokens)?),
+                "wdl" => info.wdl = Some(Wdl {
+                    wins: tokens.value(token)?,
+                    draws: tokens.value(token)?,
+                    losses: tokens.value(token)?,
+                }),
+                "currmove" => info.currmove = Some(tokens.uci_move(token)?),
+                "currmovenumber" => info.currmovenumber = Some(tokens.value(token)?),
+                "hashfull" => info.hashfull = Some(tokens.value(token)?),
+                "nps" => info.nps = Some(tokens.value(token)?),
+                "tbhits" => info.tbhits = Some(tokens.value(token)?),
+                "sbhits" => info.sbhits = Some(tokens.value(token)?),
+                "cpuload" => info.cpuload = Some(tokens.value(token)?),
+                "refutation" => info.refutation = tokens.moves(),
+                "currline" => {
+                    let cpu = match tokens.peek().map(str::parse::<u32>) {
+                        Some(Ok(cpu)) => {
+                            tokens.next();
+                            Some(cpu)
+                        }
+                        _ => None,
+                    };
+                    info.currline = Some(CurrLine { cpu, moves: tokens.moves() });
+                }
+                "string" => info.string = Some(tokens.rest()),
+                _ => return Err(tokens.error(format!("unknown info field '{}'", token))),
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn parse_score(tokens: &mut Tokens) -> Result<Score, UciParseError> {
+        let value = match tokens.next() {
+            Some("cp") => ScoreValue::Centipawns(tokens.value("cp")?),
+            Some("mate") => ScoreValue::Mate(tokens.value("mate")?),
+            _ => return Err(tokens.error("expected 'cp' or 'mate' after 'score'")),
+        };
+
+        let bound = match tokens.peek() {
+            Some("lowerbound") => ScoreBound::Lower,
+            Some("upperbound") => ScoreBound::Upper,
+            _ => ScoreBound::Exact,
+        };
+        if bound != ScoreBound::Exact {
+            tokens.next();
+        }
+
+        Ok(Score { value, bound })
+    }
+}
+
+impl fmt::Display for UciMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciMessage::IdName(name) => write!(f, "id name {}", name),
+            UciMessage::IdAuthor(author) => write!(f, "id author {}", author),
+            UciMessage::UciOk => write!(f, "uciok"),
+            UciMessage::ReadyOk => write!(f, "readyok"),
+            UciMessage::BestMove { best_move, ponder } => {
+                match best_move {
+                    Some(mv) => write!(f, "bestmove {}", mv)?,
+                    None => write!(f, "bestmove (none)")?,
+                }
+                if let Some(ponder) = ponder {
+                    write!(f, " ponder {}", ponder)?;
+                }
+                Ok(())
+            }
+            UciMessage::CopyProtection(state) => write!(f, "copyprotection {}", state),
+            UciMessage::Registration(state) => write!(f, "registration {}", state),
+            UciMessage::Option(option) => write!(f, "{}", option),
+            UciMessage::Info(info) => write!(f, "{}", info),
+            UciMessage::Unknown(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+impl fmt::Display for ProtectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtectionState::Checking => write!(f, "checking"),
+            ProtectionState::Ok => write!(f, "ok"),
+            ProtectionState::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            OptionKind::Check => "check",
+            OptionKind::Spin => "spin",
+            OptionKind::Combo => "combo",
+            OptionKind::Button => "button",
+            OptionKind::String => "string",
+        };
+
+        write!(f, "option name {} type {}", self.name, kind)?;
+        if let Some(default) = &self.default {
+            write!(f, " default {}", default)?;
+        }
+        write_field(f, "min", self.min)?;
+        write_field(f, "max", self.max)?;
+        for var in &self.vars {
+            write!(f, " var {}", var)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            ScoreValue::Centipawns(cp) => write!(f, "cp {}", cp)?,
+            ScoreValue::Mate(moves) => write!(f, "mate {}", moves)?,
+        }
+        match self.bound {
+            ScoreBound::Exact => Ok(()),
+            ScoreBound::Lower => write!(f, " lowerbound"),
+            ScoreBound::Upper => write!(f, " upperbound"),
+        }
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "info")?;
+        write_field(f, "depth", self.depth)?;
+        write_field(f, "seldepth", self.seldepth)?;
+        write_field(f, "multipv", self.multipv)?;
+        if let Some(score) = &self.score {
+            write!(f, " score {}", score)?;
+        }
+        if let Some(wdl) = &self.wdl {
+            write!(f, " wdl {} {} {}", wdl.wins, wdl.draws, wdl.losses)?;
+        }
+        write_field(f, "nodes", self.nodes)?;
+        write_field(f, "nps", self.nps)?;
+        write_field(f, "hashfull", self.hashfull)?;
+        write_field(f, "tbhits", self.tbhits)?;
+        write_field(f, "sbhits", self.sbhits)?;
+        write_field(f, "cpuload", self.cpuload)?;
+        write_field(f, "time", self.time)?;
+        if let Some(currmove) = &self.currmove {
+            write!(f, " currmove {}", currmove)?;
+        }
+        write_field(f, "currmovenumber", self.currmovenumber)?;
+        if !self.refutation.is_empty() {
+            write!(f, " refutation")?;
+            write_moves(f, &self.refutation)?;
+        }
+        if let Some(currline) = &self.currline {
+            write!(f, " currline")?;
+            write_field(f, "", currline.cpu)?;
+            write_moves(f, &currline.moves)?;
+        }
+        if !self.pv.is_empty() {
+            write!(f, " pv")?;
+            write_moves(f, &self.pv)?;
+        }
+        if let Some(string) = &self.string {
+            write!(f, " string {}", string)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_field<T: fmt::Display>(f: &mut fmt::Formatter<'_>, name: &str, value: Option<T>) -> fmt::Result {
+    match value {
+        Some(value) if name.is_empty() => write!(f, " {}", value),
+        Some(value) => write!(f, " {} {}", name, value),
+        None => Ok(()),
+    }
+}
+
+fn write_moves(f: &mut fmt::Formatter<'_>, moves: &[UciMove]) -> fmt::Result {
+    for mv in moves {
+        write!(f, " {}", mv)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(moves: &[&str]) -> Vec<UciMove> {
+        moves.iter().map(|mv| mv.parse().unwrap()).collect()
+    }
+
+    fn info(line: &str) -> Info {
+        match UciMessage::parse(line) {
+            Ok(UciMessage::Info(info)) => info,
+            other => panic!("expected info from '{}', got {:?}", line, other),
+        }
+    }
+
+    /// Stockfish's reply to `uci`, cut down to a few options of each kind.
+    const UCI_TRANSCRIPT: &[&str] = &[
+        "Stockfish 16 by the Stockfish developers (see AUTHORS file)",
+        "id name Stockfish 16",
+        "id author the Stockfish developers (see AUTHORS file)",
+        "",
+        "option name Debug Log File type string default ",
+        "option name Threads type spin default 1 min 1 max 1024",
+        "option name Clear Hash type button",
+        "option name UCI_ShowWDL type check default false",
+        "option name Analysis Contempt type combo default Both var Off var White var Black var Both",
+        "uciok",
+    ];
+
+    #[test]
+    fn handshake() {
+        let messages: Vec<UciMessage> = UCI_TRANSCRIPT.iter().map(|line| UciMessage::parse(line).unwrap()).collect();
+
+        assert_eq!(messages[0], UciMessage::Unknown(UCI_TRANSCRIPT[0].to_string()));
+        assert_eq!(messages[1], UciMessage::IdName("Stockfish 16".to_string()));
+        assert_eq!(messages[2], UciMessage::IdAuthor("the Stockfish developers (see AUTHORS file)".to_string()));
+        assert_eq!(messages[3], UciMessage::Unknown(String::new()));
+        assert_eq!(messages[9], UciMessage::UciOk);
+
+        let options: Vec<&UciOption> = messages.iter()
+            .filter_map(|message| match message {
+                UciMessage::Option(option) => Some(option),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(options.len(), 5);
+
+        assert_eq!(options[0].name, "Debug Log File");
+        assert_eq!(options[0].kind, OptionKind::String);
+        assert_eq!(options[0].default.as_deref(), Some(""));
+
+        assert_eq!(*options[1], UciOption {
+            name: "Threads".to_string(),
+            kind: OptionKind::Spin,
+            default: Some("1".to_string()),
+            min: Some(1),
+            max: Some(1024),
+            vars: Vec::new(),
+        });
+
+        assert_eq!(options[2].name, "Clear Hash");
+        assert_eq!(options[2].kind, OptionKind::Button);
+        assert_eq!(options[2].default, None);
+
+        assert_eq!(options[3].kind, OptionKind::Check);
+        assert_eq!(options[3].default.as_deref(), Some("false"));
+
+        assert_eq!(options[4].name, "Analysis Contempt");
+        assert_eq!(options[4].kind, OptionKind::Combo);
+        assert_eq!(options[4].vars, ["Off", "White", "Black", "Both"]);
+    }
+
+    #[test]
+    fn readyok() {
+        assert_eq!(UciMessage::parse("readyok"), Ok(UciMessage::ReadyOk));
+        assert_eq!(UciMessage::parse("  readyok  "), Ok(UciMessage::ReadyOk));
+    }
+
+    #[test]
+    fn info_with_multipv_and_wdl() {
+        let info = info(
+            "info depth 22 seldepth 30 multipv 2 score cp -35 wdl 40 850 110 nodes 2275417 nps 1137708 \
+             hashfull 512 tbhits 0 time 2000 pv e7e5 g1f3 b8c6 f1b5",
+        );
+
+        assert_eq!(info, Info {
+            depth: Some(22),
+            seldepth: Some(30),
+            multipv: Some(2),
+            score: Some(Score { value: ScoreValue::Centipawns(-35), bound: ScoreBound::Exact }),
+            wdl: Some(Wdl { wins: 40, draws: 850, losses: 110 }),
+            nodes: Some(2275417),
+            nps: Some(1137708),
+            hashfull: Some(512),
+            tbhits: Some(0),
+            time: Some(2000),
+            pv: moves(&["e7e5", "g1f3", "b8c6", "f1b5"]),
+            ..Info::default()
+        });
+    }
+
+    #[test]
+    fn info_with_mate_scores() {
+        assert_eq!(
+            info("info depth 12 score mate 3 pv h5f7").score,
+            Some(Score { value: ScoreValue::Mate(3), bound: ScoreBound::Exact }),
+        );
+        assert_eq!(
+            info("info depth 12 score mate -2 pv e8d8").score,
+            Some(Score { value: ScoreValue::Mate(-2), bound: ScoreBound::Exact }),
+        );
+        assert_eq!(
+            info("info depth 0 score mate 0").score,
+            Some(Score { value: ScoreValue::Mate(0), bound: ScoreBound::Exact }),
+        );
+    }
+
+    #[test]
+    fn info_with_bounds() {
+        let lower = info("info depth 18 seldepth 24 multipv 1 score cp 48 lowerbound nodes 900000 time 700 pv d2d4");
+        assert_eq!(lower.score, Some(Score { value: ScoreValue::Centipawns(48), bound: ScoreBound::Lower }));
+        assert_eq!(lower.nodes, Some(900000));
+        assert_eq!(lower.pv, moves(&["d2d4"]));
+
+        let upper = info("info depth 18 score cp 12 upperbound pv e2e4");
+        assert_eq!(upper.score, Some(Score { value: ScoreValue::Centipawns(12), bound: ScoreBound::Upper }));
+    }
+
+    #[test]
+    fn info_with_current_move_and_string() {
+        let searching = info("info depth 20 currmove g1f3 currmovenumber 3");
+        assert_eq!(searching.currmove, Some("g1f3".parse().unwrap()));
+        assert_eq!(searching.currmovenumber, Some(3));
+
+        let string = info("info string NNUE evaluation using nn-5af11540bbfe.nnue enabled");
+        assert_eq!(string.string.as_deref(), Some("NNUE evaluation using nn-5af11540bbfe.nnue enabled"));
+
+        let line = info("info currline 2 e2e4 e7e5 refutation d1h5 g7g6");
+        assert_eq!(line.currline, Some(CurrLine { cpu: Some(2), moves: moves(&["e2e4", "e7e5"]) }));
+        assert_eq!(line.refutation, moves(&["d1h5", "g7g6"]));
+    }
+
+    #[test]
+    fn bestmove() {
+        assert_eq!(
+            UciMessage::parse("bestmove e2e4 ponder e7e5"),
+            Ok(UciMessage::BestMove { best_move: Some("e2e4".parse().unwrap()), ponder: Some("e7e5".parse().unwrap()) }),
+        );
+        assert_eq!(
+            UciMessage::parse("bestmove e7e8q"),
+            Ok(UciMessage::BestMove { best_move: Some("e7e8q".parse().unwrap()), ponder: None }),
+        );
+        assert_eq!(UciMessage::parse("bestmove (none)"), Ok(UciMessage::BestMove { best_move: None, ponder: None }));
+        assert_eq!(UciMessage::parse("bestmove 0000"), Ok(UciMessage::BestMove { best_move: None, ponder: None }));
+    }
+
+    #[test]
+    fn malformed_messages_are_errors() {
+        for line in [
+            "bestmove",
+            "bestmove e2e4 ponder",
+            "bestmove e2e4 extra",
+            "bestmove z9z9",
+            "info depth deep",
+            "info score cp",
+            "info score pawns 20",
+            "info wdl 10 20",
+            "id version 16",
+            "option name Hash",
+            "option name Hash type slider",
+        ] {
+            let err = UciMessage::parse(line).unwrap_err();
+            assert_eq!(err.line, line);
+        }
+    }
+
+    #[test]
+    fn unknown_tokens() {
+        // Unrecognised output is passed on rather than rejected, so a chatty engine still works.
+        assert_eq!(UciMessage::parse("Fairy-Stockfish 14 LB"), Ok(UciMessage::Unknown("Fairy-Stockfish 14 LB".to_string())));
+        assert_eq!(UciMessage::parse("info_extra 1"), Ok(UciMessage::Unknown("info_extra 1".to_string())));
+
+        let err = UciMessage::parse("info depth 10 ebf 1.8 pv e2e4").unwrap_err();
+        assert_eq!(err.reason, "unknown info field 'ebf'");
+
+        let err = UciCommand::parse("go fast").unwrap_err();
+        assert_eq!(err.reason, "unknown go parameter 'fast'");
+        assert_eq!(UciCommand::parse("hello").unwrap_err().reason, "unknown command 'hello'");
+    }
+
+    #[test]
+    fn go_serialization() {
+        assert_eq!(UciCommand::Go(GoParams::default()).to_string(), "go");
+        assert_eq!(UciCommand::Go(GoParams { infinite: true, ..GoParams::default() }).to_string(), "go infinite");
+
+        let params = GoParams {
+            search_moves: moves(&["e2e4", "d2d4"]),
+            ponder: true,
+            wtime: Some(300000),
+            btime: Some(295000),
+            winc: Some(2000),
+            binc: Some(2000),
+            moves_to_go: Some(40),
+            depth: Some(20),
+            nodes: Some(1000000),
+            mate: Some(5),
+            move_time: Some(1500),
+            infinite: false,
+        };
+        let line = UciCommand::Go(params.clone()).to_string();
+
+        assert_eq!(
+            line,
+            "go searchmoves e2e4 d2d4 ponder wtime 300000 btime 295000 winc 2000 binc 2000 movestogo 40 \
+             depth 20 nodes 1000000 mate 5 movetime 1500",
+        );
+        assert_eq!(UciCommand::parse(&line), Ok(UciCommand::Go(params)));
+    }
+
+    #[test]
+    fn position_serialization() {
+        let startpos = UciCommand::Position { fen: None, moves: Vec::new() };
+        assert_eq!(startpos.to_string(), "position startpos");
+
+        let with_moves = UciCommand::Position { fen: None, moves: moves(&["e2e4", "e7e5", "g1f3"]) };
+        assert_eq!(with_moves.to_string(), "position startpos moves e2e4 e7e5 g1f3");
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let from_fen = UciCommand::Position { fen: Some(fen.to_string()), moves: moves(&["f1b5"]) };
+        assert_eq!(from_fen.to_string(), format!("position fen {} moves f1b5", fen));
+
+        for command in [startpos, with_moves, from_fen] {
+            assert_eq!(UciCommand::parse(&command.to_string()), Ok(command));
+        }
+    }
+
+    #[test]
+    fn setoption_serialization() {
+        let multipv = UciCommand::SetOption { name: "MultiPV".to_string(), value: Some("3".to_string()) };
+        assert_eq!(multipv.to_string(), "setoption name MultiPV value 3");
+
+        let spaced = UciCommand::SetOption { name: "Skill Level".to_string(), value: Some("20".to_string()) };
+        assert_eq!(spaced.to_string(), "setoption name Skill Level value 20");
+
+        let button = UciCommand::SetOption { name: "Clear Hash".to_string(), value: None };
+        assert_eq!(button.to_string(), "setoption name Clear Hash");
+
+        for command in [multipv, spaced, button] {
+            assert_eq!(UciCommand::parse(&command.to_string()), Ok(command));
+        }
+    }
+
+    #[test]
+    fn messages_serialize_as_they_were_read() {
+        for line in [
+            "id name Stockfish 16",
+            "uciok",
+            "readyok",
+            "bestmove g1f3 ponder d7d5",
+            "bestmove (none)",
+            "option name Threads type spin default 1 min 1 max 1024",
+            "option name Analysis Contempt type combo default Both var Off var White var Black var Both",
+            "info depth 22 seldepth 30 multipv 2 score cp -35 wdl 40 850 110 nodes 2275417 nps 1137708 time 2000 pv e7e5 g1f3",
+            "info depth 18 score mate -4 upperbound pv e8d8",
+            "info string NNUE evaluation using nn-5af11540bbfe.nnue enabled",
+        ] {
+            assert_eq!(UciMessage::parse(line).unwrap().to_string(), line);
+        }
+    }
+}